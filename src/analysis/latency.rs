@@ -0,0 +1,360 @@
+// Round-trip latency measurement through a loopback cable (output wired back to an input).
+//
+// A maximum-length sequence (MLS) replaces the main mix while the output tap and one input
+// tap are recorded. Cross-correlating both recordings with the sequence finds where it left
+// and where it came back, and the taps' first-block times put the two recordings on one
+// clock. The signal processing is kept in plain functions so it can be checked against
+// synthetic signals.
+
+use crate::audio::controls::MixControls;
+use crate::audio::tap::BlockTap;
+use realfft::RealFftPlanner;
+use realfft::num_complex::Complex;
+use ringbuf::{HeapConsumer, HeapRb};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The test sequence is 2^order - 1 samples long (about 0.34 s at 48 kHz).
+pub const MLS_ORDER: u32 = 14;
+// Playback level of the sequence (-12 dBFS)
+const MLS_LEVEL: f32 = 0.25;
+/// Longest round trip a run can find.
+pub const MAX_ROUND_TRIP_MS: u64 = 1000;
+/// Most runs per measurement.
+pub const MAX_RUNS: usize = 20;
+/// A correlation peak must stand this far above the correlation's RMS to count as found.
+pub const MIN_DETECTION_STRENGTH: f32 = 10.0;
+// Lets both taps deliver a first block before the sequence starts; also the pause between runs
+const SETTLE: Duration = Duration::from_millis(100);
+
+// Feedback taps (1-based bit positions) of a maximal-length LFSR, by order
+const LFSR_TAPS: [(u32, &[u32]); 9] = [
+    (10, &[10, 7]),
+    (11, &[11, 9]),
+    (12, &[12, 6, 4, 1]),
+    (13, &[13, 4, 3, 1]),
+    (14, &[14, 5, 3, 1]),
+    (15, &[15, 14]),
+    (16, &[16, 15, 13, 4]),
+    (17, &[17, 14]),
+    (18, &[18, 11]),
+];
+
+/// ±1 maximum-length sequence of `2^order - 1` samples; `None` unless `order` is 10..=18.
+pub fn mls(order: u32) -> Option<Vec<f32>> {
+    let taps = LFSR_TAPS.iter().find(|(o, _)| *o == order)?.1;
+    let mask = (1u32 << order) - 1;
+    let mut state = 1u32;
+    Some((0..mask).map(|_| {
+        let bit = taps.iter().fold(0, |feedback, &k| feedback ^ ((state >> (k - 1)) & 1));
+        state = ((state << 1) | bit) & mask;
+        if bit == 1 { 1.0 } else { -1.0 }
+    }).collect())
+}
+
+/// `result[lag] = Σ reference[k] · signal[k + lag]` for every lag in `0..signal.len()`, with
+/// the signal taken as silent past its end.
+pub fn cross_correlate(reference: &[f32], signal: &[f32]) -> Vec<f32> {
+    if reference.is_empty() || signal.is_empty() {
+        return vec![0.0; signal.len()];
+    }
+    // Zero-padded to at least both lengths, so positive lags never wrap around
+    let size = (reference.len() + signal.len()).next_power_of_two();
+    let mut planner = RealFftPlanner::<f32>::new();
+    let forward = planner.plan_fft_forward(size);
+    let inverse = planner.plan_fft_inverse(size);
+    let spectrum = |data: &[f32]| {
+        let mut input = forward.make_input_vec();
+        input[..data.len()].copy_from_slice(data);
+        let mut output = forward.make_output_vec();
+        forward.process(&mut input, &mut output).map(|_| output)
+    };
+    let (r, s) = match (spectrum(reference), spectrum(signal)) {
+        (Ok(r), Ok(s)) => (r, s),
+        _ => return vec![0.0; signal.len()],
+    };
+
+    let mut product: Vec<Complex<f32>> = r.iter().zip(s.iter()).map(|(r, s)| r.conj() * s).collect();
+    // DC and Nyquist must be purely real for the inverse transform
+    let last = product.len() - 1;
+    product[0].im = 0.0;
+    product[last].im = 0.0;
+    let mut result = inverse.make_output_vec();
+    if inverse.process(&mut product, &mut result).is_err() {
+        return vec![0.0; signal.len()];
+    }
+    result.truncate(signal.len());
+    let scale = 1.0 / size as f32;
+    result.iter_mut().for_each(|x| *x *= scale);
+    result
+}
+
+/// Where a reference sequence was found in a recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    /// Start of the reference in the recording, in samples (interpolated between samples).
+    pub offset: f64,
+    /// Correlation peak over its RMS; the higher, the cleaner.
+    pub strength: f32,
+}
+
+/// Find `reference` in `signal` (either polarity); `None` when no peak reaches
+/// `MIN_DETECTION_STRENGTH`, e.g. silence or noise only.
+pub fn find_reference(reference: &[f32], signal: &[f32]) -> Option<Detection> {
+    let corr = cross_correlate(reference, signal);
+    let (peak, value) = corr.iter().enumerate().fold((0, 0.0f32), |best, (i, c)| if c.abs() > best.1 { (i, c.abs()) } else { best });
+    if value <= 0.0 {
+        return None;
+    }
+    let rms = (corr.iter().map(|c| c * c).sum::<f32>() / corr.len() as f32).sqrt();
+    let strength = value / rms;
+    if strength < MIN_DETECTION_STRENGTH {
+        return None;
+    }
+
+    // Vertex of the parabola through the peak and its neighbours
+    let mut offset = peak as f64;
+    if peak > 0 && peak + 1 < corr.len() {
+        let (a, b, c) = (corr[peak - 1].abs() as f64, value as f64, corr[peak + 1].abs() as f64);
+        let curvature = a - 2.0 * b + c;
+        if curvature < 0.0 {
+            offset += (0.5 * (a - c) / curvature).clamp(-0.5, 0.5);
+        }
+    }
+    Some(Detection { offset, strength })
+}
+
+/// Round trip in ms from where the sequence sits in the output and input recordings.
+/// `capture_start` is how many seconds after the output recording's first sample the input
+/// recording's first sample was captured (negative if before).
+pub fn round_trip_ms(output_offset: f64, input_offset: f64, sample_rate: u32, capture_start: f64) -> f64 {
+    ((input_offset - output_offset) / sample_rate as f64 + capture_start) * 1000.0
+}
+
+/// Results of a measurement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundTripStats {
+    /// Each run's round trip, in ms.
+    pub runs_ms: Vec<f64>,
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    /// Standard deviation across runs, in ms.
+    pub jitter_ms: f64,
+}
+
+/// `None` without any runs.
+pub fn summarize(runs_ms: &[f64]) -> Option<RoundTripStats> {
+    if runs_ms.is_empty() {
+        return None;
+    }
+    let n = runs_ms.len() as f64;
+    let mean_ms = runs_ms.iter().sum::<f64>() / n;
+    let variance = runs_ms.iter().map(|x| (x - mean_ms).powi(2)).sum::<f64>() / n;
+    Some(RoundTripStats {
+        runs_ms: runs_ms.to_vec(),
+        mean_ms,
+        min_ms: runs_ms.iter().copied().fold(f64::INFINITY, f64::min),
+        max_ms: runs_ms.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        jitter_ms: variance.sqrt(),
+    })
+}
+
+/// Progress of a measurement, as shown in the UI and the API.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum MeasureStatus {
+    #[default]
+    Idle,
+    Running { done: usize, runs: usize },
+    Done(RoundTripStats),
+    Failed(String),
+}
+
+/// Play the sequence `runs` times and time its way back through input `slot`. `progress` is
+/// called with the number of finished runs. Blocks for about 1.5 s per run.
+///
+/// The round trip runs from the moment the engine hands a block to the output to the moment
+/// the input captured it; callback timing jitter shows up in the jitter figure.
+pub fn measure(controls: &MixControls, slot: usize, runs: usize, progress: impl Fn(usize)) -> Result<RoundTripStats, String> {
+    let format = controls.output_tap.format().ok_or("Audio is not running")?;
+    if controls.input_taps[slot].format().is_none() {
+        return Err(format!("Input {} is not running", if slot == 0 { "A" } else { "B" }));
+    }
+    let sequence = mls(MLS_ORDER).ok_or("Unsupported MLS order")?;
+    let sequence_time = Duration::from_secs_f64(sequence.len() as f64 / format.sample_rate as f64);
+    let listen_time = sequence_time + Duration::from_millis(MAX_ROUND_TRIP_MS);
+
+    let mut results = Vec::with_capacity(runs);
+    for run in 0..runs {
+        let record_seconds = (SETTLE + listen_time).as_secs_f64() + 1.0;
+        let output = Recording::attach(controls.output_tap.clone(), record_seconds)?;
+        let input = Recording::attach(controls.input_taps[slot].clone(), record_seconds)?;
+        thread::sleep(SETTLE);
+        controls.probe.play(sequence.iter().map(|x| x * MLS_LEVEL).collect());
+        thread::sleep(listen_time);
+        controls.probe.stop();
+        let (output_start, output) = output.finish(false).ok_or("The output delivered no audio")?;
+        let (input_start, input) = input.finish(true).ok_or("The input delivered no audio")?;
+
+        let sent = find_reference(&sequence, &output.channel(0)).ok_or("The test signal did not reach the output")?;
+        // The loopback may come back on any channel: take the clearest
+        let received = (0..input.channels)
+            .filter_map(|ch| find_reference(&sequence, &input.channel(ch)))
+            .max_by(|a, b| a.strength.total_cmp(&b.strength))
+            .ok_or("The test signal did not come back; check the loopback cable and the input level")?;
+        let capture_start = if input_start >= output_start {
+            (input_start - output_start).as_secs_f64()
+        } else {
+            -(output_start - input_start).as_secs_f64()
+        };
+        results.push(round_trip_ms(sent.offset, received.offset, format.sample_rate, capture_start));
+        progress(run + 1);
+        thread::sleep(SETTLE);
+    }
+    summarize(&results).ok_or_else(|| "No runs".to_string())
+}
+
+// Interleaved samples collected from a tap during one run
+struct Samples {
+    data: Vec<f32>,
+    channels: usize,
+}
+
+impl Samples {
+    fn channel(&self, ch: usize) -> Vec<f32> {
+        self.data.chunks_exact(self.channels).map(|frame| frame[ch]).collect()
+    }
+}
+
+struct Recording {
+    tap: Arc<BlockTap>,
+    id: u64,
+    consumer: HeapConsumer<f32>,
+    channels: usize,
+    sample_rate: u32,
+}
+
+impl Recording {
+    fn attach(tap: Arc<BlockTap>, seconds: f64) -> Result<Self, String> {
+        let format = tap.format().ok_or("Audio is not running")?;
+        let channels = format.channels.max(1) as usize;
+        let (producer, consumer) = HeapRb::<f32>::new((seconds * format.sample_rate as f64) as usize * channels).split();
+        let id = tap.attach(producer);
+        Ok(Self { tap, id, consumer, channels, sample_rate: format.sample_rate })
+    }
+
+    // Detach and return the samples with the time of the first one: when it was handed on
+    // for an output tap, when it was captured for an input tap (its block arrived a block
+    // length later)
+    fn finish(mut self, captured: bool) -> Option<(Instant, Samples)> {
+        let first = self.tap.first_block(self.id);
+        self.tap.detach(self.id);
+        let (arrived, len) = first?;
+        let start = if captured {
+            arrived.checked_sub(Duration::from_secs_f64(len as f64 / self.channels as f64 / self.sample_rate as f64)).unwrap_or(arrived)
+        } else {
+            arrived
+        };
+        let mut data = vec![0.0; self.consumer.len() / self.channels * self.channels];
+        self.consumer.pop_slice(&mut data);
+        Some((start, Samples { data, channels: self.channels }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Uniform noise in -level..level (xorshift32), reproducible between runs
+    fn noise(len: usize, level: f32, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * level
+        }).collect()
+    }
+
+    // `reference` starting `delay` samples into a recording of `len` samples, with noise added;
+    // a fractional delay is interpolated linearly
+    fn recording(reference: &[f32], len: usize, delay: f64, noise_level: f32) -> Vec<f32> {
+        let mut signal = noise(len, noise_level, 0x1234_5678);
+        for (i, s) in signal.iter_mut().enumerate() {
+            let t = i as f64 - delay;
+            if t < 0.0 {
+                continue;
+            }
+            let k = t as usize;
+            let frac = (t - k as f64) as f32;
+            let a = reference.get(k).copied().unwrap_or(0.0);
+            let b = reference.get(k + 1).copied().unwrap_or(0.0);
+            *s += MLS_LEVEL * (a + (b - a) * frac);
+        }
+        signal
+    }
+
+    #[test]
+    fn mls_is_maximal_and_balanced() {
+        let seq = mls(14).unwrap();
+        assert_eq!(seq.len(), 16383);
+        assert_eq!(seq.iter().sum::<f32>(), 1.0);
+        assert!(mls(9).is_none());
+
+        // Periodic autocorrelation: N at lag 0, -1 at every other lag
+        let twice: Vec<f32> = seq.iter().chain(seq.iter()).copied().collect();
+        let corr = cross_correlate(&seq, &twice);
+        assert!((corr[0] - 16383.0).abs() < 1.0);
+        for (lag, c) in corr[1..seq.len()].iter().enumerate() {
+            assert!((c + 1.0).abs() < 0.5, "lag {}: {}", lag + 1, c);
+        }
+    }
+
+    #[test]
+    fn finds_integer_and_fractional_delays() {
+        let seq = mls(12).unwrap();
+        for delay in [0.0, 137.0, 1000.25, 2500.5, 3333.75] {
+            let signal = recording(&seq, seq.len() + 5000, delay, 0.1);
+            let found = find_reference(&seq, &signal).expect("sequence not found");
+            assert!((found.offset - delay).abs() <= 0.5, "delay {}: found {}", delay, found.offset);
+            assert!(found.strength >= MIN_DETECTION_STRENGTH);
+        }
+    }
+
+    #[test]
+    fn finds_inverted_sequence() {
+        let seq = mls(12).unwrap();
+        let signal: Vec<f32> = recording(&seq, seq.len() + 2000, 700.0, 0.05).iter().map(|s| -s).collect();
+        let found = find_reference(&seq, &signal).unwrap();
+        assert!((found.offset - 700.0).abs() <= 0.5);
+    }
+
+    #[test]
+    fn nothing_found_in_silence_or_noise() {
+        let seq = mls(12).unwrap();
+        assert!(find_reference(&seq, &vec![0.0; 10_000]).is_none());
+        assert!(find_reference(&seq, &noise(10_000, 0.5, 0x2545_f491)).is_none());
+    }
+
+    #[test]
+    fn round_trip_uses_capture_start() {
+        // Sequence 480 samples later in the input than in the output: 10 ms at 48 kHz
+        assert!((round_trip_ms(100.0, 580.0, 48_000, 0.0) - 10.0).abs() < 1e-9);
+        // The input recording started 5 ms after the output recording
+        assert!((round_trip_ms(100.0, 580.0, 48_000, 0.005) - 15.0).abs() < 1e-9);
+        // ... or 2 ms before it
+        assert!((round_trip_ms(100.0, 580.0, 48_000, -0.002) - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn summarize_runs() {
+        assert!(summarize(&[]).is_none());
+        let stats = summarize(&[10.0, 12.0, 14.0]).unwrap();
+        assert_eq!(stats.runs_ms, vec![10.0, 12.0, 14.0]);
+        assert!((stats.mean_ms - 12.0).abs() < 1e-9);
+        assert_eq!(stats.min_ms, 10.0);
+        assert_eq!(stats.max_ms, 14.0);
+        assert!((stats.jitter_ms - (8.0f64 / 3.0).sqrt()).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,425 @@
+// EBU R128 / ITU-R BS.1770-4 loudness of the output mix.
+//
+//   momentary   400 ms window, updated every 100 ms
+//   short-term  3 s window
+//   integrated  since start/reset, absolute gate -70 LUFS, relative gate -10 LU
+//   true peak   4x oversampled sample peak since start/reset, in dBTP
+
+use super::TapAnalyzer;
+use crate::audio::controls::AtomicF32;
+use crate::audio::tap::BlockTap;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+
+const SUB_BLOCK_SECONDS: f64 = 0.1;
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+// Gating-block histogram: 0.01 LU bins from the absolute gate up to +10 LUFS
+const HIST_BIN_LU: f64 = 0.01;
+const HIST_BINS: usize = 8000;
+// True-peak interpolator: 4 phases of 12 taps
+const OVERSAMPLE: usize = 4;
+const TP_TAPS_PER_PHASE: usize = 12;
+
+/// Latest readings, written by the analysis thread and read by the UI, API and headless mode.
+/// Values are `-inf` until there is enough audio for the measurement.
+pub struct LoudnessReadings {
+    momentary: AtomicF32,
+    short_term: AtomicF32,
+    integrated: AtomicF32,
+    true_peak: AtomicF32,
+    reset_requested: AtomicBool,
+}
+
+impl LoudnessReadings {
+    fn new() -> Self {
+        Self {
+            momentary: AtomicF32::new(f32::NEG_INFINITY),
+            short_term: AtomicF32::new(f32::NEG_INFINITY),
+            integrated: AtomicF32::new(f32::NEG_INFINITY),
+            true_peak: AtomicF32::new(f32::NEG_INFINITY),
+            reset_requested: AtomicBool::new(false),
+        }
+    }
+
+    /// Momentary loudness in LUFS.
+    pub fn momentary(&self) -> f32 {
+        self.momentary.load()
+    }
+
+    /// Short-term loudness in LUFS.
+    pub fn short_term(&self) -> f32 {
+        self.short_term.load()
+    }
+
+    /// Integrated loudness in LUFS.
+    pub fn integrated(&self) -> f32 {
+        self.integrated.load()
+    }
+
+    /// Maximum true peak in dBTP.
+    pub fn true_peak(&self) -> f32 {
+        self.true_peak.load()
+    }
+
+    fn publish(&self, loudness: &Loudness) {
+        self.momentary.store(loudness.momentary() as f32);
+        self.short_term.store(loudness.short_term() as f32);
+        self.integrated.store(loudness.integrated() as f32);
+        self.true_peak.store(loudness.true_peak_db() as f32);
+    }
+
+    fn clear(&self) {
+        for value in [&self.momentary, &self.short_term, &self.integrated, &self.true_peak] {
+            value.store(f32::NEG_INFINITY);
+        }
+    }
+}
+
+/// Loudness analysis of the output tap on its own thread.
+pub struct LoudnessMeter {
+    analyzer: TapAnalyzer,
+    readings: Arc<LoudnessReadings>,
+}
+
+impl LoudnessMeter {
+    pub fn start(tap: Arc<BlockTap>) -> Result<Self, String> {
+        let readings = Arc::new(LoudnessReadings::new());
+        let analyzer = {
+            let readings = readings.clone();
+            TapAnalyzer::start(tap, move |format| {
+                let mut loudness = Loudness::new(format.sample_rate, format.channels as usize);
+                move |block: &[f32]| {
+                    if readings.reset_requested.swap(false, Ordering::Relaxed) {
+                        loudness.reset();
+                    }
+                    loudness.process(block);
+                    readings.publish(&loudness);
+                }
+            })?
+        };
+        Ok(Self { analyzer, readings })
+    }
+
+    pub fn readings(&self) -> &LoudnessReadings {
+        &self.readings
+    }
+
+    /// Restart the integrated and true-peak measurements (and clear the windows).
+    pub fn reset(&self) {
+        self.readings.reset_requested.store(true, Ordering::Relaxed);
+        self.readings.clear();
+    }
+
+    /// Stop analysing (audio stopped); the last readings stay visible.
+    pub fn pause(&mut self) {
+        self.analyzer.stop();
+    }
+}
+
+// Direct form I biquad, a0 normalised to 1
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, x: [0.0; 2], y: [0.0; 2] }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1] - self.a[0] * self.y[0] - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+
+    fn reset(&mut self) {
+        self.x = [0.0; 2];
+        self.y = [0.0; 2];
+    }
+}
+
+// BS.1770 K-weighting (high shelf + high pass), re-derived for any sample rate
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new([1.0, -2.0, 1.0], [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]);
+
+    [shelf, high_pass]
+}
+
+// BS.1770 channel weights; only 5.1 layouts have surrounds (and an unweighted LFE)
+fn channel_weights(channels: usize) -> Vec<f64> {
+    if channels == 6 {
+        vec![1.0, 1.0, 1.0, 0.0, 1.41, 1.41]
+    } else {
+        vec![1.0; channels]
+    }
+}
+
+// Windowed-sinc interpolator split into polyphase branches
+fn true_peak_filter() -> Vec<[f64; TP_TAPS_PER_PHASE]> {
+    let len = OVERSAMPLE * TP_TAPS_PER_PHASE;
+    let centre = (len - 1) as f64 / 2.0;
+    let taps: Vec<f64> = (0..len).map(|n| {
+        let t = (n as f64 - centre) / OVERSAMPLE as f64;
+        let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+        let window = 0.42 - 0.5 * (2.0 * PI * n as f64 / (len - 1) as f64).cos() + 0.08 * (4.0 * PI * n as f64 / (len - 1) as f64).cos();
+        sinc * window
+    }).collect();
+    (0..OVERSAMPLE).map(|phase| {
+        let mut branch = [0.0; TP_TAPS_PER_PHASE];
+        for (k, tap) in branch.iter_mut().enumerate() {
+            *tap = taps[k * OVERSAMPLE + phase];
+        }
+        // Unity gain at DC for every branch
+        let sum: f64 = branch.iter().sum();
+        branch.iter_mut().for_each(|t| *t /= sum);
+        branch
+    }).collect()
+}
+
+/// BS.1770 loudness state for one interleaved stream. Pure DSP, no threading.
+pub struct Loudness {
+    channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+    sub_block_frames: usize,
+    // Running sums for the 100 ms sub-block being filled
+    sub_block_sum: Vec<f64>,
+    sub_block_pos: usize,
+    // Weighted mean squares of the most recent sub-blocks, newest last
+    recent: VecDeque<f64>,
+    // Gating blocks above the absolute gate: count and energy sum per 0.01 LU bin
+    hist_count: Vec<u64>,
+    hist_energy: Vec<f64>,
+    // True peak: last input samples per channel (newest first) and the running maximum
+    tp_filter: Vec<[f64; TP_TAPS_PER_PHASE]>,
+    tp_history: Vec<[f64; TP_TAPS_PER_PHASE]>,
+    tp_max: f64,
+}
+
+impl Loudness {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        Self {
+            channels,
+            weights: channel_weights(channels),
+            filters: vec![k_weighting(sample_rate); channels],
+            sub_block_frames: ((sample_rate as f64 * SUB_BLOCK_SECONDS).round() as usize).max(1),
+            sub_block_sum: vec![0.0; channels],
+            sub_block_pos: 0,
+            recent: VecDeque::with_capacity(SHORT_TERM_BLOCKS),
+            hist_count: vec![0; HIST_BINS],
+            hist_energy: vec![0.0; HIST_BINS],
+            tp_filter: true_peak_filter(),
+            tp_history: vec![[0.0; TP_TAPS_PER_PHASE]; channels],
+            tp_max: 0.0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.filters.iter_mut().flatten().for_each(Biquad::reset);
+        self.sub_block_sum.iter_mut().for_each(|s| *s = 0.0);
+        self.sub_block_pos = 0;
+        self.recent.clear();
+        self.hist_count.iter_mut().for_each(|c| *c = 0);
+        self.hist_energy.iter_mut().for_each(|e| *e = 0.0);
+        self.tp_history.iter_mut().for_each(|h| *h = [0.0; TP_TAPS_PER_PHASE]);
+        self.tp_max = 0.0;
+    }
+
+    /// Feed interleaved samples; trailing partial frames are ignored.
+    pub fn process(&mut self, block: &[f32]) {
+        for frame in block.chunks_exact(self.channels) {
+            for (ch, &s) in frame.iter().enumerate() {
+                let x = s as f64;
+                self.track_true_peak(ch, x);
+                let [shelf, high_pass] = &mut self.filters[ch];
+                let y = high_pass.process(shelf.process(x));
+                self.sub_block_sum[ch] += y * y;
+            }
+            self.sub_block_pos += 1;
+            if self.sub_block_pos == self.sub_block_frames {
+                self.finish_sub_block();
+            }
+        }
+    }
+
+    fn track_true_peak(&mut self, ch: usize, x: f64) {
+        let history = &mut self.tp_history[ch];
+        history.copy_within(0..TP_TAPS_PER_PHASE - 1, 1);
+        history[0] = x;
+        let mut peak = x.abs();
+        for branch in &self.tp_filter {
+            let y: f64 = branch.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+            peak = peak.max(y.abs());
+        }
+        self.tp_max = self.tp_max.max(peak);
+    }
+
+    fn finish_sub_block(&mut self) {
+        let frames = self.sub_block_frames as f64;
+        let energy: f64 = self.sub_block_sum.iter().zip(&self.weights).map(|(sum, w)| w * sum / frames).sum();
+        self.sub_block_sum.iter_mut().for_each(|s| *s = 0.0);
+        self.sub_block_pos = 0;
+
+        if self.recent.len() == SHORT_TERM_BLOCKS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(energy);
+
+        // Every sub-block completes a 400 ms gating block (75% overlap)
+        if let Some(block_energy) = self.window_energy(MOMENTARY_BLOCKS) {
+            let lufs = energy_to_lufs(block_energy);
+            if lufs > ABSOLUTE_GATE_LUFS {
+                let bin = (((lufs - ABSOLUTE_GATE_LUFS) / HIST_BIN_LU) as usize).min(HIST_BINS - 1);
+                self.hist_count[bin] += 1;
+                self.hist_energy[bin] += block_energy;
+            }
+        }
+    }
+
+    // Mean energy of the newest `blocks` sub-blocks, once that many exist
+    fn window_energy(&self, blocks: usize) -> Option<f64> {
+        if self.recent.len() < blocks {
+            return None;
+        }
+        Some(self.recent.iter().rev().take(blocks).sum::<f64>() / blocks as f64)
+    }
+
+    pub fn momentary(&self) -> f64 {
+        self.window_energy(MOMENTARY_BLOCKS).map_or(f64::NEG_INFINITY, energy_to_lufs)
+    }
+
+    pub fn short_term(&self) -> f64 {
+        self.window_energy(SHORT_TERM_BLOCKS).map_or(f64::NEG_INFINITY, energy_to_lufs)
+    }
+
+    pub fn integrated(&self) -> f64 {
+        let count: u64 = self.hist_count.iter().sum();
+        if count == 0 {
+            return f64::NEG_INFINITY;
+        }
+        let ungated = self.hist_energy.iter().sum::<f64>() / count as f64;
+        let threshold = energy_to_lufs(ungated) + RELATIVE_GATE_LU;
+        let first = (((threshold - ABSOLUTE_GATE_LUFS) / HIST_BIN_LU).max(0.0) as usize).min(HIST_BINS);
+        let (count, energy) = (first..HIST_BINS)
+            .fold((0u64, 0.0f64), |(c, e), bin| (c + self.hist_count[bin], e + self.hist_energy[bin]));
+        if count == 0 { f64::NEG_INFINITY } else { energy_to_lufs(energy / count as f64) }
+    }
+
+    pub fn true_peak_db(&self) -> f64 {
+        if self.tp_max > 0.0 { 20.0 * self.tp_max.log10() } else { f64::NEG_INFINITY }
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    if energy > 0.0 { -0.691 + 10.0 * energy.log10() } else { f64::NEG_INFINITY }
+}
+
+/// "-23.0" style readout, or a dash while the value is not available.
+pub fn format_lufs(value: f32) -> String {
+    if value.is_finite() { format!("{:.1}", value) } else { "—".to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    // Interleaved stereo sine with the same signal on both channels
+    fn sine(freq: f64, dbfs: f64, seconds: f64, phase: f64) -> Vec<f32> {
+        let amplitude = 10f64.powf(dbfs / 20.0);
+        let frames = (seconds * RATE as f64) as usize;
+        (0..frames).flat_map(|n| {
+            let s = (amplitude * (2.0 * PI * freq * n as f64 / RATE as f64 + phase).sin()) as f32;
+            [s, s]
+        }).collect()
+    }
+
+    fn measure(parts: &[(f64, f64)]) -> Loudness {
+        let mut loudness = Loudness::new(RATE, 2);
+        for &(dbfs, seconds) in parts {
+            // Odd block sizes, so sub-blocks end mid-block
+            for block in sine(1000.0, dbfs, seconds, 0.0).chunks(2 * 777) {
+                loudness.process(block);
+            }
+        }
+        loudness
+    }
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64, what: &str) {
+        assert!((actual - expected).abs() <= tolerance, "{}: {:.3}, expected {:.3}", what, actual, expected);
+    }
+
+    #[test]
+    fn reference_sine_reads_minus_23() {
+        // EBU Tech 3341: a stereo 1 kHz sine at -23 dBFS is -23 LUFS
+        let loudness = measure(&[(-23.0, 20.0)]);
+        assert_near(loudness.momentary(), -23.0, 0.1, "momentary");
+        assert_near(loudness.short_term(), -23.0, 0.1, "short-term");
+        assert_near(loudness.integrated(), -23.0, 0.1, "integrated");
+    }
+
+    #[test]
+    fn nothing_before_the_windows_fill() {
+        let loudness = measure(&[(-23.0, 0.35)]);
+        assert_eq!(loudness.momentary(), f64::NEG_INFINITY);
+        assert_eq!(loudness.short_term(), f64::NEG_INFINITY);
+        assert_eq!(loudness.integrated(), f64::NEG_INFINITY);
+        assert!(measure(&[]).true_peak_db().is_infinite());
+    }
+
+    #[test]
+    fn gates_drop_quiet_blocks() {
+        // EBU Tech 3341 case 4 at half length: the -72 dB parts fall under the absolute gate,
+        // the -36 dB parts under the relative one
+        let loudness = measure(&[(-72.0, 5.0), (-36.0, 5.0), (-23.0, 30.0), (-36.0, 5.0), (-72.0, 5.0)]);
+        assert_near(loudness.integrated(), -23.0, 0.1, "integrated");
+    }
+
+    #[test]
+    fn blocks_above_the_relative_gate_average_by_energy() {
+        // -29 dB is within 10 LU of the ungated mean, so both halves count
+        let loudness = measure(&[(-23.0, 20.0), (-29.0, 20.0)]);
+        let expected = -23.0 + 10.0 * ((1.0 + 10f64.powf(-0.6)) / 2.0).log10();
+        assert_near(loudness.integrated(), expected, 0.1, "integrated");
+        assert_near(loudness.momentary(), -29.0, 0.1, "momentary");
+    }
+
+    #[test]
+    fn true_peak_finds_inter_sample_peak() {
+        // A quarter-rate sine at 45 degrees only ever hits 0.707 of its peak on a sample
+        let signal = sine(RATE as f64 / 4.0, -6.0, 1.0, PI / 4.0);
+        let mut loudness = Loudness::new(RATE, 2);
+        loudness.process(&signal);
+        let sample_peak = 20.0 * signal.iter().fold(0.0f32, |m, s| m.max(s.abs())).log10() as f64;
+        assert_near(sample_peak, -9.01, 0.05, "sample peak");
+        let true_peak = loudness.true_peak_db();
+        assert!(true_peak > sample_peak + 2.5, "true peak {:.2} dBTP, sample peak {:.2} dBFS", true_peak, sample_peak);
+        assert_near(true_peak, -6.0, 0.5, "true peak");
+    }
+}
@@ -0,0 +1,80 @@
+// Non-real-time analysis of tapped audio (loudness, spectrum, stereo image).
+//
+// Each analyzer attaches its own ring to a `BlockTap` and drains it on a worker thread, so
+// the audio threads only ever pay for one `push_slice`.
+
+pub mod latency;
+pub mod loudness;
+pub mod spectrum;
+pub mod stereo;
+
+use crate::audio::tap::{BlockTap, TapFormat};
+use ringbuf::HeapRb;
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Hand-off ring between a tap and the worker, and how often an idle worker looks again
+const RING_SECONDS: usize = 1;
+const POLL: Duration = Duration::from_millis(10);
+const CHUNK_FRAMES: usize = 4096;
+
+/// Worker thread feeding whole interleaved frames from a tap to a processing closure.
+pub struct TapAnalyzer {
+    tap: Arc<BlockTap>,
+    id: Option<u64>,
+    stop_flag: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TapAnalyzer {
+    /// Attach to `tap` and run `make(format)`'s closure on every chunk that arrives.
+    pub fn start<M, F>(tap: Arc<BlockTap>, make: M) -> Result<Self, String>
+    where
+        M: FnOnce(TapFormat) -> F,
+        F: FnMut(&[f32]) + Send + 'static,
+    {
+        let format = tap.format().ok_or("Audio is not running")?;
+        let channels = format.channels.max(1) as usize;
+        let mut process = make(format);
+        let (producer, mut consumer) = HeapRb::<f32>::new(format.sample_rate as usize * channels * RING_SECONDS).split();
+        let id = tap.attach(producer);
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop_flag = stop_flag.clone();
+            thread::spawn(move || {
+                let mut chunk = vec![0.0f32; CHUNK_FRAMES * channels];
+                while !stop_flag.load(Ordering::Relaxed) {
+                    // Whole frames only, so channels never shift
+                    let n = (consumer.len() / channels * channels).min(chunk.len());
+                    if n == 0 {
+                        thread::sleep(POLL);
+                        continue;
+                    }
+                    consumer.pop_slice(&mut chunk[..n]);
+                    process(&chunk[..n]);
+                }
+            })
+        };
+
+        Ok(Self { tap, id: Some(id), stop_flag, thread: Some(thread) })
+    }
+
+    /// Detach and stop the worker; idempotent.
+    pub fn stop(&mut self) {
+        if let Some(id) = self.id.take() {
+            self.tap.detach(id);
+        }
+        self.stop_flag.store(true, Ordering::Relaxed);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+impl Drop for TapAnalyzer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,151 @@
+// FFT spectrum of one tap (input A, input B or the output mix), for the analyzer panel.
+//
+// Channels are summed to mono, windowed (Hann, 4096 points) and transformed every 1024
+// samples; the published magnitudes are in dBFS, so a full-scale sine peaks at 0 dB.
+
+use super::TapAnalyzer;
+use crate::audio::controls::AtomicF32;
+use crate::audio::tap::BlockTap;
+use realfft::RealFftPlanner;
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+
+pub const FFT_SIZE: usize = 4096;
+const HOP: usize = FFT_SIZE / 4;
+// Lowest value published, so silence is finite
+pub const FLOOR_DB: f32 = -120.0;
+pub const DEFAULT_SMOOTHING: f32 = 0.7;
+
+/// Which signal the analyzer looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpectrumSource {
+    InputA,
+    InputB,
+    #[default]
+    Output,
+}
+
+impl SpectrumSource {
+    pub const ALL: [SpectrumSource; 3] = [SpectrumSource::InputA, SpectrumSource::InputB, SpectrumSource::Output];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SpectrumSource::InputA => "Input A",
+            SpectrumSource::InputB => "Input B",
+            SpectrumSource::Output => "Output",
+        }
+    }
+}
+
+/// One published spectrum: `bins_db[k]` is the level at `k * sample_rate / FFT_SIZE` Hz.
+#[derive(Debug, Clone)]
+pub struct Spectrum {
+    pub sample_rate: u32,
+    pub bins_db: Vec<f32>,
+}
+
+impl Spectrum {
+    pub fn bin_hz(&self) -> f32 {
+        self.sample_rate as f32 / FFT_SIZE as f32
+    }
+}
+
+struct Shared {
+    spectrum: Mutex<Option<Spectrum>>,
+    // 0 = raw frames, towards 1 = slower decay
+    smoothing: AtomicF32,
+}
+
+/// Spectrum analysis of a tap on its own thread.
+pub struct SpectrumAnalyzer {
+    _analyzer: TapAnalyzer,
+    shared: Arc<Shared>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn start(tap: Arc<BlockTap>, smoothing: f32) -> Result<Self, String> {
+        let shared = Arc::new(Shared { spectrum: Mutex::new(None), smoothing: AtomicF32::new(smoothing) });
+        let analyzer = {
+            let shared = shared.clone();
+            TapAnalyzer::start(tap, move |format| {
+                let channels = format.channels.max(1) as usize;
+                let mut fft = SpectrumFft::new();
+                move |block: &[f32]| {
+                    for frame in block.chunks_exact(channels) {
+                        let mono = frame.iter().sum::<f32>() / channels as f32;
+                        if let Some(bins) = fft.push(mono, shared.smoothing.load()) {
+                            *shared.spectrum.lock().unwrap() = Some(Spectrum { sample_rate: format.sample_rate, bins_db: bins.to_vec() });
+                        }
+                    }
+                }
+            })?
+        };
+        Ok(Self { _analyzer: analyzer, shared })
+    }
+
+    /// Latest spectrum, `None` until the first transform.
+    pub fn spectrum(&self) -> Option<Spectrum> {
+        self.shared.spectrum.lock().unwrap().clone()
+    }
+
+    pub fn set_smoothing(&self, smoothing: f32) {
+        self.shared.smoothing.store(smoothing);
+    }
+}
+
+// Sliding-window FFT with per-bin smoothing (instant rise, exponential fall)
+struct SpectrumFft {
+    fft: Arc<dyn realfft::RealToComplex<f32>>,
+    window: Vec<f32>,
+    // Circular input history; `pos` is the next write index
+    history: Vec<f32>,
+    pos: usize,
+    since_last: usize,
+    input: Vec<f32>,
+    output: Vec<realfft::num_complex::Complex<f32>>,
+    scratch: Vec<realfft::num_complex::Complex<f32>>,
+    smoothed: Vec<f32>,
+}
+
+impl SpectrumFft {
+    fn new() -> Self {
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+        let window = (0..FFT_SIZE).map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / FFT_SIZE as f32).cos()).collect();
+        Self {
+            input: fft.make_input_vec(),
+            output: fft.make_output_vec(),
+            scratch: fft.make_scratch_vec(),
+            fft,
+            window,
+            history: vec![0.0; FFT_SIZE],
+            pos: 0,
+            since_last: 0,
+            smoothed: vec![FLOOR_DB; FFT_SIZE / 2 + 1],
+        }
+    }
+
+    // Add one sample; returns the smoothed bins whenever a new transform was done
+    fn push(&mut self, sample: f32, smoothing: f32) -> Option<&[f32]> {
+        self.history[self.pos] = sample;
+        self.pos = (self.pos + 1) % FFT_SIZE;
+        self.since_last += 1;
+        if self.since_last < HOP {
+            return None;
+        }
+        self.since_last = 0;
+
+        for (i, x) in self.input.iter_mut().enumerate() {
+            *x = self.history[(self.pos + i) % FFT_SIZE] * self.window[i];
+        }
+        self.fft.process_with_scratch(&mut self.input, &mut self.output, &mut self.scratch).ok()?;
+
+        // Hann coherent gain is 0.5 and a real sine splits over ±f, hence 4/N for 0 dBFS
+        let scale = 4.0 / FFT_SIZE as f32;
+        let smoothing = smoothing.clamp(0.0, 0.99);
+        for (bin, value) in self.output.iter().zip(self.smoothed.iter_mut()) {
+            let db = (20.0 * (bin.norm() * scale).max(1e-9).log10()).max(FLOOR_DB);
+            *value = if db >= *value { db } else { *value * smoothing + db * (1.0 - smoothing) };
+        }
+        Some(&self.smoothed)
+    }
+}
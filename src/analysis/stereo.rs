@@ -0,0 +1,86 @@
+// Stereo image of one tap: phase correlation and recent L/R pairs for a goniometer.
+//
+// Only the first two channels are looked at; mono taps are not analysed at all.
+
+use super::TapAnalyzer;
+use crate::audio::controls::AtomicF32;
+use crate::audio::tap::BlockTap;
+use std::sync::{Arc, Mutex};
+
+// Integration time of the correlation meter
+const CORRELATION_SECONDS: f32 = 0.3;
+// Frames kept for the goniometer trace
+pub const SCOPE_FRAMES: usize = 1024;
+// Below this (mean square) both channels count as silent and correlation reads 0
+const SILENCE: f32 = 1e-9;
+
+struct Shared {
+    correlation: AtomicF32,
+    // Circular, newest at `pos - 1`
+    scope: Mutex<(Vec<(f32, f32)>, usize)>,
+}
+
+/// What the stereo panel draws for one source.
+pub struct StereoView {
+    /// -1 (out of phase) .. 0 (uncorrelated) .. +1 (mono).
+    pub correlation: f32,
+    /// Recent (left, right) pairs, oldest first.
+    pub points: Vec<(f32, f32)>,
+}
+
+/// Correlation and goniometer data for a stereo tap, on its own thread.
+pub struct StereoAnalyzer {
+    _analyzer: TapAnalyzer,
+    shared: Arc<Shared>,
+}
+
+impl StereoAnalyzer {
+    pub fn start(tap: Arc<BlockTap>) -> Result<Self, String> {
+        match tap.format() {
+            Some(f) if f.channels >= 2 => {}
+            Some(_) => return Err("Not a stereo source".into()),
+            None => return Err("Audio is not running".into()),
+        }
+        let shared = Arc::new(Shared {
+            correlation: AtomicF32::new(0.0),
+            scope: Mutex::new((vec![(0.0, 0.0); SCOPE_FRAMES], 0)),
+        });
+        let analyzer = {
+            let shared = shared.clone();
+            TapAnalyzer::start(tap, move |format| {
+                let channels = format.channels as usize;
+                let coeff = 1.0 - (-1.0 / (CORRELATION_SECONDS * format.sample_rate as f32)).exp();
+                let (mut lr, mut ll, mut rr) = (0.0f32, 0.0f32, 0.0f32);
+                move |block: &[f32]| {
+                    let mut scope = shared.scope.lock().unwrap();
+                    let (points, pos) = &mut *scope;
+                    for frame in block.chunks_exact(channels) {
+                        let (l, r) = (frame[0], frame[1]);
+                        lr += (l * r - lr) * coeff;
+                        ll += (l * l - ll) * coeff;
+                        rr += (r * r - rr) * coeff;
+                        points[*pos] = (l, r);
+                        *pos = (*pos + 1) % SCOPE_FRAMES;
+                    }
+                    drop(scope);
+                    let correlation = if ll > SILENCE && rr > SILENCE { (lr / (ll * rr).sqrt()).clamp(-1.0, 1.0) } else { 0.0 };
+                    shared.correlation.store(correlation);
+                }
+            })?
+        };
+        Ok(Self { _analyzer: analyzer, shared })
+    }
+
+    pub fn correlation(&self) -> f32 {
+        self.shared.correlation.load()
+    }
+
+    pub fn view(&self) -> StereoView {
+        let scope = self.shared.scope.lock().unwrap();
+        let (points, pos) = &*scope;
+        let mut ordered = Vec::with_capacity(SCOPE_FRAMES);
+        ordered.extend_from_slice(&points[*pos..]);
+        ordered.extend_from_slice(&points[..*pos]);
+        StereoView { correlation: self.correlation(), points: ordered }
+    }
+}
@@ -0,0 +1,1168 @@
+// Embedded HTTP/WebSocket control API.
+//
+// Everything here reads and writes the same `AppState` the UI uses, so a toggle from a
+// Stream Deck or phone shows up in the window immediately (and vice versa).
+//
+// The server only listens on localhost unless given another bind address. It sends no CORS
+// headers and takes POSTs only with `Content-Type: application/json`, so a web page open on
+// the same machine can't drive it. With a token set, every request must carry it in the
+// `X-Api-Token` header (or a `token` query parameter, for WebSocket clients in a browser).
+//
+// Connections are accepted here rather than by tiny_http: a WebSocket keeps its plain
+// `TcpStream` so reads and writes can time out, everything else is piped to tiny_http on a
+// loopback port.
+//
+// Endpoints (all JSON):
+//   GET  /api/status                 running flag, last error, routing and listen states
+//   GET  /api/devices                device list (index is what routing uses)
+//   GET  /api/devices/details        same list with supported rates/channels/formats (probes devices)
+//   GET  /api/routing                selected input A/B and output, and their channel subsets
+//   POST /api/routing                {"input_a": 3, "input_b": 3, "output": 0, "channels_a": "1-2", "channels_b": "3-4"}
+//                                    "output_channels": "3-4" (missing keys unchanged, null channels = all)
+//                                    "source_a": "device"|"generator"|"file" (a device index also switches back to the device)
+//   GET  /api/listen                 {"a": bool, "b": bool}
+//   POST /api/listen/{a|b}           {"on": bool}
+//   POST /api/listen/{a|b}/toggle
+//   GET  /api/meters                 block peak levels (linear and dBFS), per-channel peak/RMS, clip flags, stereo correlation, talkover reduction
+//   GET  /api/latency                per input: delay (ms and samples), queue, capture/render block and estimated total (ms)
+//   GET  /api/latency/measure        round-trip measurement: {"state": "idle"|"running"|"done"|"failed", ...}
+//   POST /api/latency/measure        {"input": "a"|"b", "runs": 5} start one (needs a loopback cable from the main output)
+//   GET  /api/loudness               output momentary/short-term/integrated LUFS and true peak (null until measured)
+//   POST /api/loudness/reset         restart integrated loudness and true peak
+//   POST /api/start, /api/stop       queued for the thread that owns the backend
+//   GET  /api/record                 current output recording (path, seconds, bytes) or null
+//   POST /api/record/start           {"format": "wav"|"flac", "multitrack": bool} (default wav, mix only)
+//   POST /api/record/stop            finalize the file and return its path
+//   GET  /api/history                history buffer length and how much is filled
+//   POST /api/history/save           {"seconds": 60, "format": "wav"|"flac"} dump the last N seconds
+//   GET  /api/params                 mappable parameters (ids, normalised 0..1 values, display text)
+//   POST /api/params/<id>            {"value": 0.5} normalised value
+//   POST /api/params/<id>/toggle     flip a switch (e.g. a.eq.low.kill)
+//   GET  /api/outputs                output slots (0 = main): device, channels, volume_db, delay_ms
+//   POST /api/outputs/<slot>         {"device": 2, "channels": "1-2", "volume_db": -3, "delay_ms": 12} (missing keys unchanged)
+//   GET  /api/cue                    cue buses: output device/channels, volume/delay, per-input listen and gain
+//   POST /api/cue/<bus>              {"device": 5, "channels": "3-4", "listen_a": true, "gain_b_db": -6, "listen_pads": false, "listen_click": true, "volume_db": 0, "delay_ms": 0}
+//                                    (bus from 1, missing keys unchanged); listen toggles are also params (cue1.listen.a)
+//   GET  /api/generator              test signal generator per input: kind, frequency, level_db, sweep_start/end/seconds
+//   POST /api/generator/{a|b}        {"kind": "sine"|"white"|"pink"|"sweep"|"channel_id", "frequency": 1000, "level_db": -18}
+//                                    (missing keys unchanged; applies live, select it with "source_a" in /api/routing)
+//   GET  /api/player                 file player per input: file, playing, loop, position and duration (s), error
+//   POST /api/player/{a|b}           {"path": "C:/jingles/id.mp3", "play": true, "loop": false, "seek": 12.5} (missing keys unchanged;
+//                                    select it with "source_a": "file" in /api/routing)
+//   GET  /api/pads                   sample pads: main mix listen, per pad file, length, gain_db, choke group, polyphony, voices playing
+//   POST /api/pads                   {"listen": true} hear the pads on the main mix (cue buses use "listen_pads" in /api/cue)
+//   POST /api/pads/<n>               {"path": "C:/pads/horn.wav", "gain_db": -3, "choke": 1, "polyphony": 2} (pad from 1,
+//                                    missing keys unchanged, null path empties the pad)
+//   POST /api/pads/<n>/trigger       play pad n (also the params pad1 .. pad8)
+//   GET  /api/click                  metronome: listen (main mix), bpm, beats, accents (beats from 1), sound, level_db, beat playing
+//   POST /api/click                  {"bpm": 96, "beats": 4, "accents": [1, 3], "sound": "beep"|"wood"|"tick", "level_db": -12,
+//                                    "listen": false} (missing keys unchanged; cue buses use "listen_click" in /api/cue)
+//   GET  /api/matrix                 channel routing per input: {"a": {"custom": bool, "gains": [[in0->out0, ...], ...]}, "b": ...}
+//   POST /api/matrix/{a|b}           {"gains": [[1, 0], [0, 1]]} rows per input channel, or {"reset": true} for the default mapping
+//   GET  /api/ws                     WebSocket pushing "state" (status) and "meters" messages; the recording's
+//                                    seconds/bytes/dropped_samples come with the meters
+
+use crate::analysis::latency::MeasureStatus;
+use crate::audio::ActiveBackend;
+use crate::audio::backend::{AudioBackend, BackendError, ChannelRange, DeviceEntry, Mode, OutputSelection};
+use crate::audio::click::{ClickSound, MAX_BEATS_PER_BAR, MAX_BPM, MIN_BPM, MIN_CLICK_LEVEL_DB};
+use crate::audio::controls::{MAX_CUE_BUSES, MAX_OUTPUTS, MAX_TRIM_DB, MIN_TRIM_DB};
+use crate::audio::generator::{GeneratorKind, MAX_GENERATOR_HZ, MAX_SWEEP_SECONDS, MIN_GENERATOR_HZ, MIN_GENERATOR_LEVEL_DB};
+use crate::audio::engine::MAX_OUTPUT_DELAY_MS;
+use crate::audio::meters::{linear_to_db, LevelMeter};
+use crate::audio::pads::{CHOKE_GROUPS, MAX_PADS, MAX_POLYPHONY};
+use crate::audio::params::Param;
+use crate::record::{self, RecordFormat};
+use crate::state::{AppState, Command, InputKind, Routing};
+use serde_json::{json, Value};
+use std::io::{self, Cursor};
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use tungstenite::Message;
+
+pub const DEFAULT_PORT: u16 = 8080;
+/// Localhost only; LAN access needs an explicit bind address such as 0.0.0.0.
+pub const DEFAULT_BIND: &str = "127.0.0.1";
+/// Bind address that makes the API reachable from other devices.
+pub const LAN_BIND: &str = "0.0.0.0";
+const TOKEN_HEADER: &str = "X-Api-Token";
+
+// How often WebSocket clients receive meter updates
+const WS_METER_INTERVAL: Duration = Duration::from_millis(50);
+// WebSocket clients are pinged this often and dropped after this many unanswered pings, so a
+// phone that walks out of Wi-Fi range doesn't keep a thread forever
+const WS_PING_INTERVAL: Duration = Duration::from_secs(1);
+const WS_MISSED_PONGS: u32 = 5;
+// Longest a send may block, and how long a new connection gets to show its request
+const WS_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// Parts of "recording" that change on every tick; the WebSocket sends them with the meters
+const RECORD_PROGRESS_KEYS: [&str; 3] = ["seconds", "bytes", "dropped_samples"];
+
+/// Where the API listens and what it asks of clients.
+#[derive(Debug, Clone)]
+pub struct ApiOptions {
+    pub bind: String,
+    pub port: u16,
+    /// Shared secret every request must present.
+    pub token: Option<String>,
+}
+
+impl ApiOptions {
+    pub fn new(port: u16) -> Self {
+        Self { bind: DEFAULT_BIND.to_string(), port, token: None }
+    }
+}
+
+pub struct ApiServer {
+    server: Arc<Server>,
+    stop_flag: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    accept_thread: Option<JoinHandle<()>>,
+    // Where to connect to wake the accept thread when stopping
+    wake_addr: SocketAddr,
+    port: u16,
+}
+
+impl ApiServer {
+    pub fn start(options: ApiOptions, state: Arc<AppState>) -> Result<Self, String> {
+        let ApiOptions { bind, port, token } = options;
+        let listener = TcpListener::bind((bind.as_str(), port)).map_err(|e| format!("Failed to bind API to {}:{}: {}", bind, port, e))?;
+        let local = listener.local_addr().map_err(|e| format!("Failed to bind API to {}:{}: {}", bind, port, e))?;
+        let server = Server::http((Ipv4Addr::LOCALHOST, 0)).map_err(|e| format!("Failed to start API server: {}", e))?;
+        let inner = server.server_addr().to_ip().ok_or("Failed to start API server: no local address")?;
+        let server = Arc::new(server);
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let token: Option<Arc<str>> = token.map(Into::into);
+
+        let thread = {
+            let server = server.clone();
+            let stop_flag = stop_flag.clone();
+            let state = state.clone();
+            let token = token.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    if stop_flag.load(Ordering::Relaxed) { break; }
+                    handle_request(request, &state, token.as_deref());
+                }
+            })
+        };
+        let accept_thread = {
+            let stop_flag = stop_flag.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop_flag.load(Ordering::Relaxed) { break; }
+                    let stream = match stream {
+                        Ok(s) => s,
+                        Err(_) => continue,
+                    };
+                    let (state, stop_flag, token) = (state.clone(), stop_flag.clone(), token.clone());
+                    thread::spawn(move || {
+                        if is_websocket_request(&stream) {
+                            serve_websocket(stream, state, stop_flag, token.as_deref());
+                        } else {
+                            pipe(stream, inner);
+                        }
+                    });
+                }
+            })
+        };
+
+        let wake_addr = match local.ip() {
+            ip if !ip.is_unspecified() => local,
+            ip if ip.is_ipv6() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), local.port()),
+            _ => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), local.port()),
+        };
+        println!("Remote API listening on {}:{}", bind, port);
+        Ok(Self { server, stop_flag, thread: Some(thread), accept_thread: Some(accept_thread), wake_addr, port })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn stop(&mut self) {
+        self.stop_flag.store(true, Ordering::Relaxed);
+        self.server.unblock();
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+        if let Some(t) = self.accept_thread.take() {
+            // The accept loop only looks at the flag when a connection comes in
+            let _ = TcpStream::connect_timeout(&self.wake_addr, Duration::from_secs(1));
+            let _ = t.join();
+        }
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn handle_request(mut request: Request, state: &Arc<AppState>, token: Option<&str>) {
+    let path = request.url().split('?').next().unwrap_or("").trim_end_matches('/').to_string();
+    let method = request.method().clone();
+
+    if let Some(token) = token {
+        if !has_token(&request, token) {
+            let _ = request.respond(json_response(401, &json!({ "error": format!("missing or wrong {} header", TOKEN_HEADER) })));
+            return;
+        }
+    }
+    // Browsers send cross-origin form and text POSTs without asking first; JSON they don't
+    if method == Method::Post && !is_json(&request) {
+        let _ = request.respond(json_response(415, &json!({ "error": "POST requests must have Content-Type: application/json" })));
+        return;
+    }
+
+    // WebSocket upgrades are taken over before they get here
+    if path == "/api/ws" {
+        let _ = request.respond(json_response(400, &json!({ "error": "expected a WebSocket upgrade" })));
+        return;
+    }
+
+    let (status, body) = match (&method, path.as_str()) {
+        (Method::Get, "/api/status") => (200, status_json(state)),
+        (Method::Get, "/api/devices") => (200, devices_json(&state.devices.lock().unwrap())),
+        (Method::Get, "/api/devices/details") => match describe_devices() {
+            Ok(v) => (200, v),
+            Err(msg) => (500, json!({ "error": msg })),
+        },
+        (Method::Get, "/api/routing") => (200, routing_json(state)),
+        (Method::Post, "/api/routing") | (Method::Put, "/api/routing") => {
+            match read_json(&mut request).and_then(|body| apply_routing(state, &body)) {
+                Ok(()) => (200, routing_json(state)),
+                Err(msg) => (400, json!({ "error": msg })),
+            }
+        }
+        (Method::Get, "/api/listen") => (200, listen_json(state)),
+        (Method::Post, "/api/listen/a/toggle") => { state.controls.toggle_a(); (200, listen_json(state)) }
+        (Method::Post, "/api/listen/b/toggle") => { state.controls.toggle_b(); (200, listen_json(state)) }
+        (Method::Post, "/api/listen/a") | (Method::Post, "/api/listen/b") => {
+            let flag = if path.ends_with('a') { &state.controls.listen_a } else { &state.controls.listen_b };
+            match read_json(&mut request) {
+                Ok(body) => match body.get("on").and_then(Value::as_bool) {
+                    Some(on) => { flag.store(on, Ordering::Relaxed); (200, listen_json(state)) }
+                    None => (400, json!({ "error": "expected {\"on\": true|false}" })),
+                },
+                Err(msg) => (400, json!({ "error": msg })),
+            }
+        }
+        (Method::Get, "/api/meters") => (200, meters_json(state)),
+        (Method::Get, "/api/params") => (200, params_json(state)),
+        (Method::Post, p) if p.starts_with("/api/params/") => {
+            let rest = &p["/api/params/".len()..];
+            let (id, toggle) = match rest.strip_suffix("/toggle") {
+                Some(id) => (id, true),
+                None => (rest, false),
+            };
+            match Param::parse(id) {
+                None => (404, json!({ "error": format!("unknown parameter {}", id) })),
+                Some(param) if toggle => { param.toggle(&state.controls); (200, param_json(state, param)) }
+                Some(param) => match read_json(&mut request) {
+                    Ok(body) => match body.get("value").and_then(Value::as_f64) {
+                        Some(v) => { param.set(&state.controls, v as f32); (200, param_json(state, param)) }
+                        None => (400, json!({ "error": "expected {\"value\": 0..1}" })),
+                    },
+                    Err(msg) => (400, json!({ "error": msg })),
+                },
+            }
+        }
+        (Method::Get, "/api/outputs") => (200, outputs_json(state)),
+        (Method::Post, p) if p.starts_with("/api/outputs/") => {
+            match p["/api/outputs/".len()..].parse::<usize>() {
+                Ok(slot) if slot < MAX_OUTPUTS => match read_json(&mut request).and_then(|body| apply_output(state, slot, &body)) {
+                    Ok(()) => (200, outputs_json(state)),
+                    Err(msg) => (400, json!({ "error": msg })),
+                },
+                _ => (404, json!({ "error": format!("no output slot {}", &p["/api/outputs/".len()..]) })),
+            }
+        }
+        (Method::Get, "/api/cue") => (200, cue_json(state)),
+        (Method::Post, p) if p.starts_with("/api/cue/") => {
+            match p["/api/cue/".len()..].parse::<usize>() {
+                Ok(bus) if (1..=MAX_CUE_BUSES).contains(&bus) => match read_json(&mut request).and_then(|body| apply_cue(state, bus - 1, &body)) {
+                    Ok(()) => (200, cue_json(state)),
+                    Err(msg) => (400, json!({ "error": msg })),
+                },
+                _ => (404, json!({ "error": format!("no cue bus {}", &p["/api/cue/".len()..]) })),
+            }
+        }
+        (Method::Get, "/api/generator") => (200, generator_json(state)),
+        (Method::Post, "/api/generator/a") | (Method::Post, "/api/generator/b") => {
+            let slot = if path.ends_with('a') { 0 } else { 1 };
+            match read_json(&mut request).and_then(|body| apply_generator(state, slot, &body)) {
+                Ok(()) => (200, generator_json(state)),
+                Err(msg) => (400, json!({ "error": msg })),
+            }
+        }
+        (Method::Get, "/api/player") => (200, player_json(state)),
+        (Method::Post, "/api/player/a") | (Method::Post, "/api/player/b") => {
+            let slot = if path.ends_with('a') { 0 } else { 1 };
+            match read_json(&mut request).and_then(|body| apply_player(state, slot, &body)) {
+                Ok(()) => (200, player_json(state)),
+                Err(msg) => (400, json!({ "error": msg })),
+            }
+        }
+        (Method::Get, "/api/pads") => (200, pads_json(state)),
+        (Method::Post, "/api/pads") => {
+            let listen = read_json(&mut request).and_then(|body| match body.get("listen") {
+                None => Ok(None),
+                Some(v) => v.as_bool().map(Some).ok_or_else(|| "listen must be true or false".to_string()),
+            });
+            match listen {
+                Ok(listen) => {
+                    if let Some(on) = listen {
+                        state.controls.pads.listen.store(on, Ordering::Relaxed);
+                    }
+                    (200, pads_json(state))
+                }
+                Err(msg) => (400, json!({ "error": msg })),
+            }
+        }
+        (Method::Post, p) if p.starts_with("/api/pads/") => {
+            let rest = &p["/api/pads/".len()..];
+            let (number, trigger) = match rest.strip_suffix("/trigger") {
+                Some(number) => (number, true),
+                None => (rest, false),
+            };
+            match number.parse::<usize>() {
+                Ok(n) if (1..=MAX_PADS).contains(&n) && trigger => {
+                    state.controls.pads.pads[n - 1].trigger();
+                    (200, pads_json(state))
+                }
+                Ok(n) if (1..=MAX_PADS).contains(&n) => match read_json(&mut request).and_then(|body| apply_pad(state, n - 1, &body)) {
+                    Ok(()) => (200, pads_json(state)),
+                    Err(msg) => (400, json!({ "error": msg })),
+                },
+                _ => (404, json!({ "error": format!("no pad {}", number) })),
+            }
+        }
+        (Method::Get, "/api/click") => (200, click_json(state)),
+        (Method::Post, "/api/click") => match read_json(&mut request).and_then(|body| apply_click(state, &body)) {
+            Ok(()) => (200, click_json(state)),
+            Err(msg) => (400, json!({ "error": msg })),
+        },
+        (Method::Get, "/api/matrix") => (200, matrix_json(state)),
+        (Method::Post, "/api/matrix/a") | (Method::Post, "/api/matrix/b") => {
+            let slot = if path.ends_with('a') { 0 } else { 1 };
+            match read_json(&mut request).and_then(|body| apply_matrix(state, slot, &body)) {
+                Ok(()) => (200, matrix_json(state)),
+                Err(msg) => (400, json!({ "error": msg })),
+            }
+        }
+        (Method::Get, "/api/latency") => (200, latency_json(state)),
+        (Method::Get, "/api/latency/measure") => (200, measure_json(state)),
+        (Method::Post, "/api/latency/measure") => {
+            let request = read_json(&mut request).and_then(|body| {
+                let slot = match body.get("input").and_then(Value::as_str).unwrap_or("a") {
+                    "a" => 0,
+                    "b" => 1,
+                    other => return Err(format!("unknown input '{}'", other)),
+                };
+                Ok((slot, body.get("runs").and_then(Value::as_u64).unwrap_or(5) as usize))
+            });
+            match request.map(|(slot, runs)| state.start_latency_test(slot, runs)) {
+                Ok(Ok(())) => (202, measure_json(state)),
+                Ok(Err(msg)) => (409, json!({ "error": msg })),
+                Err(msg) => (400, json!({ "error": msg })),
+            }
+        }
+        (Method::Get, "/api/loudness") => (200, loudness_json(state)),
+        (Method::Post, "/api/loudness/reset") => { state.reset_loudness(); (200, loudness_json(state)) }
+        (Method::Post, "/api/start") => { state.push_command(Command::Start); (202, json!({ "queued": "start" })) }
+        (Method::Post, "/api/stop") => { state.push_command(Command::Stop); (202, json!({ "queued": "stop" })) }
+        (Method::Get, "/api/record") => (200, record_json(state)),
+        (Method::Post, "/api/record/start") => {
+            let request = read_json(&mut request).and_then(|body| {
+                let format = match body.get("format").and_then(Value::as_str) {
+                    None => RecordFormat::default(),
+                    Some(f) => RecordFormat::parse(f).ok_or_else(|| format!("unknown format '{}' (wav or flac)", f))?,
+                };
+                Ok((format, body.get("multitrack").and_then(Value::as_bool).unwrap_or(false)))
+            });
+            match request.map(|(format, multitrack)| state.start_recording(Path::new(record::DEFAULT_DIR), format, multitrack)) {
+                Ok(Ok(_)) => (200, record_json(state)),
+                Ok(Err(msg)) => (409, json!({ "error": msg })),
+                Err(msg) => (400, json!({ "error": msg })),
+            }
+        }
+        (Method::Get, "/api/history") => (200, history_json(state)),
+        (Method::Post, "/api/history/save") => {
+            let request = read_json(&mut request).and_then(|body| {
+                let format = match body.get("format").and_then(Value::as_str) {
+                    None => RecordFormat::default(),
+                    Some(f) => RecordFormat::parse(f).ok_or_else(|| format!("unknown format '{}' (wav or flac)", f))?,
+                };
+                // Same range as the GUI's "save last" field
+                let seconds = match body.get("seconds") {
+                    None => state.history_seconds.load(Ordering::Relaxed),
+                    Some(s) => s.as_u64().ok_or("seconds must be a whole number")?.clamp(1, record::history::MAX_SECONDS as u64) as u32,
+                };
+                Ok((format, seconds))
+            });
+            match request.map(|(format, seconds)| state.save_history(Path::new(record::DEFAULT_DIR), seconds, format)) {
+                // Files are written in the background
+                Ok(Ok(path)) => (202, json!({ "saving": path.display().to_string() })),
+                Ok(Err(msg)) => (409, json!({ "error": msg })),
+                Err(msg) => (400, json!({ "error": msg })),
+            }
+        }
+        (Method::Post, "/api/record/stop") => match state.stop_recording() {
+            Some(Ok(path)) => (200, json!({ "saved": path.display().to_string() })),
+            Some(Err(msg)) => (500, json!({ "error": msg })),
+            None => (409, json!({ "error": "not recording" })),
+        },
+        _ => (404, json!({ "error": format!("no route for {:?} {}", method, path) })),
+    };
+
+    let _ = request.respond(json_response(status, &body));
+}
+
+fn json_response(status: u16, body: &Value) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(body.to_string())
+        .with_status_code(StatusCode(status))
+        .with_header(Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap())
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str())
+}
+
+fn is_json(request: &Request) -> bool {
+    header(request, "Content-Type")
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+fn has_token(request: &Request, token: &str) -> bool {
+    let query = request.url().split_once('?').map(|(_, q)| q);
+    token_matches(header(request, TOKEN_HEADER), query, token)
+}
+
+// The token from the header, or else from the `token` query parameter
+fn token_matches(header: Option<&str>, query: Option<&str>, token: &str) -> bool {
+    let from_query = query.unwrap_or("").split('&').find_map(|pair| pair.strip_prefix("token="));
+    header.or(from_query) == Some(token)
+}
+
+fn read_json(request: &mut Request) -> Result<Value, String> {
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body).map_err(|e| format!("Failed to read body: {}", e))?;
+    if body.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    serde_json::from_str(&body).map_err(|e| format!("Invalid JSON: {}", e))
+}
+
+fn apply_routing(state: &AppState, body: &Value) -> Result<(), String> {
+    let devices = state.devices.lock().unwrap();
+    let mut routing = state.routing.lock().unwrap();
+    let mut updated = *routing;
+
+    type Field<'a> = (&'a str, &'a mut Option<usize>, fn(&DeviceEntry) -> bool);
+    let fields: [Field; 3] = [
+        ("input_a", &mut updated.input_a, |d| d.is_input),
+        ("input_b", &mut updated.input_b, |d| d.is_input),
+        ("output", &mut updated.output, |d| d.is_output),
+    ];
+    for (key, slot, accepts) in fields {
+        match body.get(key) {
+            None => {}
+            Some(Value::Null) => *slot = None,
+            Some(v) => {
+                let idx = v.as_u64().ok_or_else(|| format!("{} must be a device index or null", key))? as usize;
+                match devices.get(idx) {
+                    Some(d) if accepts(d) => *slot = Some(idx),
+                    Some(d) => return Err(format!("{} cannot use device '{}'", key, d.name)),
+                    None => return Err(format!("{}: device index {} out of range", key, idx)),
+                }
+            }
+        }
+    }
+
+    // Picking a device switches the slot back from a software source
+    for (slot, (device_key, key)) in [("input_a", "source_a"), ("input_b", "source_b")].into_iter().enumerate() {
+        if body.get(device_key).is_some_and(|v| !v.is_null()) {
+            updated.sources[slot] = InputKind::Device;
+        }
+        if let Some(v) = body.get(key) {
+            let name = v.as_str().ok_or_else(|| {
+                let keys: Vec<String> = InputKind::ALL.iter().map(|k| format!("\"{}\"", k.key())).collect();
+                format!("{} must be one of {}", key, keys.join(", "))
+            })?;
+            updated.sources[slot] = InputKind::from_key(name).ok_or_else(|| format!("{}: unknown source '{}'", key, name))?;
+        }
+    }
+
+    let channel_fields = [
+        ("channels_a", &mut updated.channels_a),
+        ("channels_b", &mut updated.channels_b),
+        ("output_channels", &mut updated.output_channels),
+    ];
+    for (key, slot) in channel_fields {
+        match body.get(key) {
+            None => {}
+            Some(Value::Null) => *slot = None,
+            Some(v) => {
+                let spec = v.as_str().ok_or_else(|| format!("{} must be a channel range like \"3-4\" or null", key))?;
+                *slot = Some(ChannelRange::parse(spec)?);
+            }
+        }
+    }
+
+    *routing = updated;
+    Ok(())
+}
+
+// Slot 0 is the main output (same as "output"/"output_channels" in /api/routing)
+fn apply_output(state: &AppState, slot: usize, body: &Value) -> Result<(), String> {
+    let devices = state.devices.lock().unwrap();
+    let mut routing = state.routing.lock().unwrap();
+    let (mut device, mut channels) = match slot {
+        0 => (routing.output, routing.output_channels),
+        _ => routing.extra_outputs[slot - 1].map_or((None, None), |o| (Some(o.device), o.channels)),
+    };
+    match body.get("device") {
+        None => {}
+        Some(Value::Null) => device = None,
+        Some(v) => {
+            let idx = v.as_u64().ok_or("device must be a device index or null")? as usize;
+            match devices.get(idx) {
+                Some(d) if d.is_output => device = Some(idx),
+                Some(d) => return Err(format!("device '{}' is not an output", d.name)),
+                None => return Err(format!("device index {} out of range", idx)),
+            }
+        }
+    }
+    match body.get("channels") {
+        None => {}
+        Some(Value::Null) => channels = None,
+        Some(v) => {
+            let spec = v.as_str().ok_or("channels must be a channel range like \"3-4\" or null")?;
+            channels = Some(ChannelRange::parse(spec)?);
+        }
+    }
+    let number = |key: &str| body.get(key).map(|v| v.as_f64().map(|x| x as f32).ok_or(format!("{} must be a number", key))).transpose();
+    let volume_db = number("volume_db")?;
+    let delay_ms = number("delay_ms")?;
+
+    match slot {
+        0 => {
+            routing.output = device;
+            routing.output_channels = channels;
+        }
+        _ => routing.extra_outputs[slot - 1] = device.map(|device| OutputSelection { device, channels }),
+    }
+    let params = &state.controls.outputs[slot];
+    if let Some(db) = volume_db {
+        params.volume_db.store(db.clamp(MIN_TRIM_DB, MAX_TRIM_DB));
+    }
+    if let Some(ms) = delay_ms {
+        params.delay_ms.store(ms.clamp(0.0, MAX_OUTPUT_DELAY_MS));
+    }
+    Ok(())
+}
+
+fn apply_cue(state: &AppState, bus: usize, body: &Value) -> Result<(), String> {
+    let devices = state.devices.lock().unwrap();
+    let mut routing = state.routing.lock().unwrap();
+    let (mut device, mut channels) = routing.cue_outputs[bus].map_or((None, None), |o| (Some(o.device), o.channels));
+    match body.get("device") {
+        None => {}
+        Some(Value::Null) => device = None,
+        Some(v) => {
+            let idx = v.as_u64().ok_or("device must be a device index or null")? as usize;
+            match devices.get(idx) {
+                Some(d) if d.is_output => device = Some(idx),
+                Some(d) => return Err(format!("device '{}' is not an output", d.name)),
+                None => return Err(format!("device index {} out of range", idx)),
+            }
+        }
+    }
+    match body.get("channels") {
+        None => {}
+        Some(Value::Null) => channels = None,
+        Some(v) => {
+            let spec = v.as_str().ok_or("channels must be a channel range like \"3-4\" or null")?;
+            channels = Some(ChannelRange::parse(spec)?);
+        }
+    }
+    let number = |key: &str| body.get(key).map(|v| v.as_f64().map(|x| x as f32).ok_or(format!("{} must be a number", key))).transpose();
+    let flag = |key: &str| body.get(key).map(|v| v.as_bool().ok_or(format!("{} must be true or false", key))).transpose();
+    let listen = [flag("listen_a")?, flag("listen_b")?];
+    let gain_db = [number("gain_a_db")?, number("gain_b_db")?];
+    let listen_pads = flag("listen_pads")?;
+    let listen_click = flag("listen_click")?;
+    let volume_db = number("volume_db")?;
+    let delay_ms = number("delay_ms")?;
+
+    routing.cue_outputs[bus] = device.map(|device| OutputSelection { device, channels });
+    let params = &state.controls.cue_buses[bus];
+    for (slot, on) in listen.into_iter().enumerate() {
+        if let Some(on) = on {
+            params.listen[slot].store(on, Ordering::Relaxed);
+        }
+    }
+    for (slot, db) in gain_db.into_iter().enumerate() {
+        if let Some(db) = db {
+            params.gain_db[slot].store(db.clamp(MIN_TRIM_DB, MAX_TRIM_DB));
+        }
+    }
+    if let Some(on) = listen_pads {
+        params.listen_pads.store(on, Ordering::Relaxed);
+    }
+    if let Some(on) = listen_click {
+        params.listen_click.store(on, Ordering::Relaxed);
+    }
+    if let Some(db) = volume_db {
+        params.output.volume_db.store(db.clamp(MIN_TRIM_DB, MAX_TRIM_DB));
+    }
+    if let Some(ms) = delay_ms {
+        params.output.delay_ms.store(ms.clamp(0.0, MAX_OUTPUT_DELAY_MS));
+    }
+    Ok(())
+}
+
+fn apply_player(state: &AppState, slot: usize, body: &Value) -> Result<(), String> {
+    let flag = |key: &str| body.get(key).map(|v| v.as_bool().ok_or(format!("{} must be true or false", key))).transpose();
+    let path = body.get("path").map(|v| v.as_str().ok_or("path must be a string")).transpose()?;
+    let play = flag("play")?;
+    let looping = flag("loop")?;
+    let seek = body.get("seek").map(|v| v.as_f64().ok_or("seek must be a number of seconds")).transpose()?;
+
+    let player = &state.controls.players[slot];
+    if let Some(path) = path {
+        player.load(Path::new(path))?;
+    }
+    if let Some(looping) = looping {
+        player.looping.store(looping, Ordering::Relaxed);
+    }
+    if let Some(seconds) = seek {
+        player.seek(seconds);
+    }
+    match play {
+        Some(true) => player.play(),
+        Some(false) => player.pause(),
+        None => {}
+    }
+    Ok(())
+}
+
+fn apply_pad(state: &AppState, idx: usize, body: &Value) -> Result<(), String> {
+    let path = match body.get("path") {
+        None => None,
+        Some(Value::Null) => Some(None),
+        Some(v) => Some(Some(v.as_str().ok_or("path must be a string or null")?)),
+    };
+    let gain_db = body.get("gain_db").map(|v| v.as_f64().ok_or("gain_db must be a number")).transpose()?;
+    let count = |key: &str| body.get(key).map(|v| v.as_u64().map(|n| n as usize).ok_or(format!("{} must be a whole number", key))).transpose();
+    let choke = count("choke")?;
+    if choke.is_some_and(|group| group > CHOKE_GROUPS) {
+        return Err(format!("choke must be 0-{}", CHOKE_GROUPS));
+    }
+    let polyphony = count("polyphony")?;
+
+    let bank = &state.controls.pads;
+    if let Some(path) = path {
+        bank.load(idx, path.map(Path::new))?;
+    }
+    let pad = &bank.pads[idx];
+    if let Some(db) = gain_db {
+        pad.gain_db.store((db as f32).clamp(MIN_TRIM_DB, MAX_TRIM_DB));
+    }
+    if let Some(group) = choke {
+        pad.choke.store(group, Ordering::Relaxed);
+    }
+    if let Some(voices) = polyphony {
+        pad.polyphony.store(voices.clamp(1, MAX_POLYPHONY), Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+fn apply_click(state: &AppState, body: &Value) -> Result<(), String> {
+    let number = |key: &str| body.get(key).map(|v| v.as_f64().map(|x| x as f32).ok_or(format!("{} must be a number", key))).transpose();
+    let sound = match body.get("sound") {
+        None => None,
+        Some(v) => {
+            let key = v.as_str().ok_or("sound must be a string")?;
+            Some(ClickSound::from_key(key).ok_or_else(|| format!("unknown click sound '{}'", key))?)
+        }
+    };
+    let beats = body.get("beats").map(|v| v.as_u64().map(|n| n as usize).ok_or("beats must be a whole number")).transpose()?;
+    let accents = match body.get("accents") {
+        None => None,
+        Some(v) => {
+            let beats = v.as_array().ok_or("accents must be a list of beats")?;
+            let mut mask = 0;
+            for beat in beats {
+                match beat.as_u64().map(|n| n as usize) {
+                    Some(beat) if (1..=MAX_BEATS_PER_BAR).contains(&beat) => mask |= 1 << (beat - 1),
+                    _ => return Err(format!("accented beats must be 1-{}", MAX_BEATS_PER_BAR)),
+                }
+            }
+            Some(mask)
+        }
+    };
+    let bpm = number("bpm")?;
+    let level_db = number("level_db")?;
+    let listen = body.get("listen").map(|v| v.as_bool().ok_or("listen must be true or false")).transpose()?;
+
+    let click = &state.controls.click;
+    if let Some(sound) = sound {
+        click.sound.store(sound.index(), Ordering::Relaxed);
+    }
+    if let Some(beats) = beats {
+        click.beats.store(beats.clamp(1, MAX_BEATS_PER_BAR), Ordering::Relaxed);
+    }
+    if let Some(mask) = accents {
+        click.accents.store(mask, Ordering::Relaxed);
+    }
+    if let Some(bpm) = bpm {
+        click.bpm.store(bpm.clamp(MIN_BPM, MAX_BPM));
+    }
+    if let Some(db) = level_db {
+        click.level_db.store(db.clamp(MIN_CLICK_LEVEL_DB, 0.0));
+    }
+    if let Some(on) = listen {
+        click.listen.store(on, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+fn apply_generator(state: &AppState, slot: usize, body: &Value) -> Result<(), String> {
+    let number = |key: &str| body.get(key).map(|v| v.as_f64().map(|x| x as f32).ok_or(format!("{} must be a number", key))).transpose();
+    let kind = match body.get("kind") {
+        None => None,
+        Some(v) => {
+            let key = v.as_str().ok_or("kind must be a string")?;
+            Some(GeneratorKind::from_key(key).ok_or_else(|| format!("unknown generator kind '{}'", key))?)
+        }
+    };
+    let hz = [number("frequency")?, number("sweep_start")?, number("sweep_end")?];
+    let level_db = number("level_db")?;
+    let sweep_seconds = number("sweep_seconds")?;
+
+    let params = &state.controls.generators[slot];
+    if let Some(kind) = kind {
+        params.kind.store(kind.index(), Ordering::Relaxed);
+    }
+    for (value, param) in hz.into_iter().zip([&params.frequency, &params.sweep_start, &params.sweep_end]) {
+        if let Some(v) = value {
+            param.store(v.clamp(MIN_GENERATOR_HZ, MAX_GENERATOR_HZ));
+        }
+    }
+    if let Some(db) = level_db {
+        params.level_db.store(db.clamp(MIN_GENERATOR_LEVEL_DB, 0.0));
+    }
+    if let Some(seconds) = sweep_seconds {
+        params.sweep_seconds.store(seconds.clamp(0.1, MAX_SWEEP_SECONDS));
+    }
+    Ok(())
+}
+
+fn apply_matrix(state: &AppState, slot: usize, body: &Value) -> Result<(), String> {
+    let route = &state.controls.inputs[slot].route;
+    if body.get("reset").and_then(Value::as_bool) == Some(true) {
+        route.reset();
+        return Ok(());
+    }
+    let rows: Vec<Vec<f32>> = body.get("gains").cloned().and_then(|v| serde_json::from_value(v).ok())
+        .ok_or("expected {\"gains\": [[...], ...]} or {\"reset\": true}")?;
+    route.load_rows(&rows)
+}
+
+fn mode_str(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Shared => "shared",
+        Mode::Exclusive => "exclusive",
+    }
+}
+
+fn devices_json(devices: &[DeviceEntry]) -> Value {
+    Value::Array(devices.iter().enumerate().map(|(i, d)| json!({
+        "index": i,
+        "name": d.name,
+        "device_id": d.device_id,
+        "mode": mode_str(d.mode),
+        "is_input": d.is_input,
+        "is_output": d.is_output,
+    })).collect())
+}
+
+// Probes on the API thread with its own backend instance; the running one is left alone
+fn describe_devices() -> Result<Value, String> {
+    let described = ActiveBackend::new().and_then(|b| b.describe_devices()).map_err(|e| match e {
+        BackendError::InitError(msg) | BackendError::StartError(msg) => msg,
+    })?;
+    serde_json::to_value(described).map_err(|e| e.to_string())
+}
+
+fn routing_json(state: &AppState) -> Value {
+    let Routing { input_a, input_b, sources, output, channels_a, channels_b, output_channels, .. } = state.routing();
+    let devices = state.devices.lock().unwrap();
+    let slot = |idx: Option<usize>| match idx {
+        Some(i) => json!({ "index": i, "name": devices.get(i).map(|d| d.name.clone()) }),
+        None => Value::Null,
+    };
+    let channels = |range: Option<ChannelRange>| range.map(|r| r.label());
+    json!({
+        "input_a": slot(input_a),
+        "input_b": slot(input_b),
+        "source_a": sources[0].key(),
+        "source_b": sources[1].key(),
+        "output": slot(output),
+        "channels_a": channels(channels_a),
+        "channels_b": channels(channels_b),
+        "output_channels": channels(output_channels),
+    })
+}
+
+fn outputs_json(state: &AppState) -> Value {
+    let routing = state.routing();
+    let devices = state.devices.lock().unwrap();
+    Value::Array(routing.outputs().iter().enumerate().map(|(slot, selection)| {
+        let params = &state.controls.outputs[slot];
+        json!({
+            "slot": slot,
+            "device": selection.map(|o| json!({ "index": o.device, "name": devices.get(o.device).map(|d| d.name.clone()) })),
+            "channels": selection.and_then(|o| o.channels).map(|r| r.label()),
+            "volume_db": params.volume_db.load(),
+            "delay_ms": params.delay_ms.load(),
+        })
+    }).collect())
+}
+
+fn cue_json(state: &AppState) -> Value {
+    let routing = state.routing();
+    let devices = state.devices.lock().unwrap();
+    Value::Array(routing.cue_outputs.iter().zip(&state.controls.cue_buses).enumerate().map(|(bus, (selection, params))| json!({
+        "bus": bus + 1,
+        "device": selection.map(|o| json!({ "index": o.device, "name": devices.get(o.device).map(|d| d.name.clone()) })),
+        "channels": selection.and_then(|o| o.channels).map(|r| r.label()),
+        "listen_a": params.listen[0].load(Ordering::Relaxed),
+        "listen_b": params.listen[1].load(Ordering::Relaxed),
+        "gain_a_db": params.gain_db[0].load(),
+        "gain_b_db": params.gain_db[1].load(),
+        "listen_pads": params.listen_pads.load(Ordering::Relaxed),
+        "listen_click": params.listen_click.load(Ordering::Relaxed),
+        "volume_db": params.output.volume_db.load(),
+        "delay_ms": params.output.delay_ms.load(),
+    })).collect())
+}
+
+fn latency_json(state: &AppState) -> Value {
+    let controls = &state.controls;
+    let inputs: Vec<Value> = ["a", "b"].iter().enumerate().map(|(slot, name)| {
+        let input = &controls.inputs[slot];
+        let tap = &controls.input_taps[slot];
+        let delay_ms = input.delay_ms.load();
+        json!({
+            "input": name,
+            "delay_ms": delay_ms,
+            "delay_samples": tap.format().map(|f| (delay_ms / 1000.0 * f.sample_rate as f32) as u32),
+            "queue_ms": tap.format().map(|_| input.queue_ms.load()),
+            "capture_block_ms": tap.block_ms(),
+            "total_ms": controls.input_latency_ms(slot),
+        })
+    }).collect();
+    json!({
+        "render_block_ms": controls.output_tap.block_ms(),
+        "output_delay_ms": controls.outputs[0].delay_ms.load(),
+        "inputs": inputs,
+    })
+}
+
+fn measure_json(state: &AppState) -> Value {
+    match &*state.latency_test.lock().unwrap() {
+        MeasureStatus::Idle => json!({ "state": "idle" }),
+        MeasureStatus::Running { done, runs } => json!({ "state": "running", "done": done, "runs": runs }),
+        MeasureStatus::Done(stats) => json!({
+            "state": "done",
+            "runs_ms": stats.runs_ms,
+            "mean_ms": stats.mean_ms,
+            "min_ms": stats.min_ms,
+            "max_ms": stats.max_ms,
+            "jitter_ms": stats.jitter_ms,
+        }),
+        MeasureStatus::Failed(msg) => json!({ "state": "failed", "error": msg }),
+    }
+}
+
+fn listen_json(state: &AppState) -> Value {
+    json!({
+        "a": state.controls.listen_a.load(Ordering::Relaxed),
+        "b": state.controls.listen_b.load(Ordering::Relaxed),
+    })
+}
+
+fn meters_json(state: &AppState) -> Value {
+    let level = |m: &LevelMeter| json!({
+        "peak": m.peak(),
+        "peak_db": m.peak_db(),
+        "clipped": m.clipped(),
+        "channels": (0..m.channels()).map(|ch| json!({
+            "peak_db": linear_to_db(m.channel_peak(ch)),
+            "rms_db": linear_to_db(m.channel_rms(ch)),
+        })).collect::<Vec<_>>(),
+    });
+    let meters = &state.controls.meters;
+    let mut json = json!({ "input_a": level(&meters.input_a), "input_b": level(&meters.input_b), "output": level(&meters.output) });
+    // Phase correlation of stereo sources (null for mono or stopped ones)
+    let stereo = state.stereo.lock().unwrap();
+    for (key, analyzer) in ["input_a", "input_b", "output"].iter().zip(stereo.iter()) {
+        json[key]["correlation"] = analyzer.as_ref().map(|a| a.correlation()).into();
+    }
+    json["talkover_reduction_db"] = state.controls.talkover.reduction_db.load().into();
+    json
+}
+
+fn param_json(state: &AppState, param: Param) -> Value {
+    json!({
+        "id": param.id(),
+        "value": param.get(&state.controls),
+        "display": param.display(&state.controls),
+        "switch": param.is_switch(),
+    })
+}
+
+fn generator_json(state: &AppState) -> Value {
+    let slot = |idx: usize| {
+        let params = &state.controls.generators[idx];
+        json!({
+            "kind": GeneratorKind::from_index(params.kind.load(Ordering::Relaxed)).key(),
+            "frequency": params.frequency.load(),
+            "level_db": params.level_db.load(),
+            "sweep_start": params.sweep_start.load(),
+            "sweep_end": params.sweep_end.load(),
+            "sweep_seconds": params.sweep_seconds.load(),
+        })
+    };
+    json!({ "a": slot(0), "b": slot(1) })
+}
+
+fn player_json(state: &AppState) -> Value {
+    let slot = |idx: usize| {
+        let player = &state.controls.players[idx];
+        json!({
+            "file": player.file().map(|p| p.display().to_string()),
+            "playing": player.playing.load(Ordering::Relaxed),
+            "loop": player.looping.load(Ordering::Relaxed),
+            "position": player.position.load(),
+            "duration": player.duration.load(),
+            "error": player.error(),
+        })
+    };
+    json!({ "a": slot(0), "b": slot(1) })
+}
+
+fn pads_json(state: &AppState) -> Value {
+    let bank = &state.controls.pads;
+    json!({
+        "listen": bank.listen.load(Ordering::Relaxed),
+        "pads": bank.pads.iter().enumerate().map(|(idx, pad)| json!({
+            "pad": idx + 1,
+            "file": pad.file().map(|p| p.display().to_string()),
+            "seconds": pad.seconds(),
+            "gain_db": pad.gain_db.load(),
+            "choke": pad.choke.load(Ordering::Relaxed),
+            "polyphony": pad.polyphony.load(Ordering::Relaxed),
+            "active": pad.active.load(Ordering::Relaxed),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn click_json(state: &AppState) -> Value {
+    let click = &state.controls.click;
+    let accents = click.accents.load(Ordering::Relaxed);
+    json!({
+        "listen": click.listen.load(Ordering::Relaxed),
+        "bpm": click.bpm.load(),
+        "beats": click.beats.load(Ordering::Relaxed),
+        "accents": (0..MAX_BEATS_PER_BAR).filter(|b| accents & (1 << b) != 0).map(|b| b + 1).collect::<Vec<_>>(),
+        "sound": ClickSound::from_index(click.sound.load(Ordering::Relaxed)).key(),
+        "level_db": click.level_db.load(),
+        "beat": click.beat.load(Ordering::Relaxed) + 1,
+    })
+}
+
+fn matrix_json(state: &AppState) -> Value {
+    let slot = |idx: usize| {
+        let route = &state.controls.inputs[idx].route;
+        let (in_channels, out_channels) = state.controls.route_channels(idx);
+        json!({ "custom": route.is_custom(), "gains": route.to_rows(in_channels, out_channels) })
+    };
+    json!({ "a": slot(0), "b": slot(1) })
+}
+
+fn params_json(state: &AppState) -> Value {
+    Value::Array(Param::all().into_iter().map(|p| param_json(state, p)).collect())
+}
+
+fn loudness_json(state: &AppState) -> Value {
+    // Values not measured yet are -inf and serialize as null
+    match state.loudness.lock().unwrap().as_ref() {
+        Some(m) => {
+            let r = m.readings();
+            json!({
+                "momentary_lufs": r.momentary(),
+                "short_term_lufs": r.short_term(),
+                "integrated_lufs": r.integrated(),
+                "true_peak_dbtp": r.true_peak(),
+            })
+        }
+        None => Value::Null,
+    }
+}
+
+fn record_json(state: &AppState) -> Value {
+    match state.recorder.lock().unwrap().as_ref() {
+        Some(rec) => json!({
+            "path": rec.path().display().to_string(),
+            "seconds": rec.elapsed().as_secs_f64(),
+            "bytes": rec.file_size(),
+            // Samples the output tap could not hand over in time (0 unless the disk stalls)
+            "dropped_samples": state.controls.output_tap.dropped(),
+        }),
+        None => Value::Null,
+    }
+}
+
+fn history_json(state: &AppState) -> Value {
+    match state.history.lock().unwrap().as_ref() {
+        Some(h) => json!({
+            "seconds": h.seconds(),
+            "buffered_seconds": h.buffered_seconds(),
+            "saving": h.is_saving(),
+            "last_saved": h.last_save().and_then(Result::ok).map(|p| p.display().to_string()),
+        }),
+        None => Value::Null,
+    }
+}
+
+fn status_json(state: &AppState) -> Value {
+    json!({
+        "running": state.is_running(),
+        "last_error": state.last_error(),
+        "routing": routing_json(state),
+        "listen": listen_json(state),
+        "cue_buses": cue_json(state),
+        "recording": record_json(state),
+    })
+}
+
+// Whether a new connection asks for /api/ws, judged from its request line without consuming it
+fn is_websocket_request(stream: &TcpStream) -> bool {
+    let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let mut head = [0u8; 64];
+    loop {
+        let n = match stream.peek(&mut head) {
+            Ok(0) | Err(_) => return false,
+            Ok(n) => n,
+        };
+        // Enough to see the path: the whole line, or more than "GET /api/ws?" can take
+        if n == head.len() || head[..n].contains(&b'\n') || Instant::now() >= deadline {
+            let line = String::from_utf8_lossy(&head[..n]);
+            let path = line.strip_prefix("GET ").and_then(|rest| rest.split([' ', '?']).next());
+            return path.map(|p| p.trim_end_matches('/')) == Some("/api/ws");
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+// Hand a plain HTTP connection to tiny_http and copy both ways until either side closes
+fn pipe(client: TcpStream, inner: SocketAddr) {
+    let _ = client.set_read_timeout(None);
+    let upstream = match TcpStream::connect(inner) {
+        Ok(s) => s,
+        Err(_) => return,
+    };
+    let (mut client_read, mut upstream_write) = match (client.try_clone(), upstream.try_clone()) {
+        (Ok(c), Ok(u)) => (c, u),
+        _ => return,
+    };
+    let requests = thread::spawn(move || {
+        let _ = io::copy(&mut client_read, &mut upstream_write);
+        let _ = upstream_write.shutdown(Shutdown::Write);
+    });
+    let (mut upstream_read, mut client_write) = (upstream, client);
+    let _ = io::copy(&mut upstream_read, &mut client_write);
+    let _ = client_write.shutdown(Shutdown::Both);
+    let _ = requests.join();
+}
+
+fn serve_websocket(stream: TcpStream, state: Arc<AppState>, stop_flag: Arc<AtomicBool>, token: Option<&str>) {
+    // The error type is fixed by tungstenite's callback signature
+    #[allow(clippy::result_large_err)]
+    let check = |request: &tungstenite::handshake::server::Request, response| {
+        let header = request.headers().get(TOKEN_HEADER).and_then(|v| v.to_str().ok());
+        match token {
+            Some(token) if !token_matches(header, request.uri().query(), token) => {
+                let body = json!({ "error": format!("missing or wrong {} header", TOKEN_HEADER) }).to_string();
+                Err(tungstenite::http::Response::builder().status(401).body(Some(body)).unwrap())
+            }
+            _ => Ok(response),
+        }
+    };
+    let mut socket = match tungstenite::accept_hdr(stream, check) {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    if socket.get_mut().set_write_timeout(Some(WS_WRITE_TIMEOUT)).is_err() {
+        return;
+    }
+
+    let mut last_state = String::new();
+    let mut next_ping = Instant::now();
+    let mut last_pong = Instant::now();
+    // State when it changes, meters on every tick; in between, whatever the client sends is
+    // read until the tick is over (tungstenite answers pings and acknowledges a close itself)
+    'ticks: while !stop_flag.load(Ordering::Relaxed) {
+        let tick_end = Instant::now() + WS_METER_INTERVAL;
+        let mut status = status_json(&state);
+        let mut meters = meters_json(&state);
+        // Recording progress changes on every tick, so it goes out with the meters
+        meters["recording"] = Value::Null;
+        if let Some(recording) = status["recording"].as_object_mut() {
+            let progress: serde_json::Map<String, Value> = RECORD_PROGRESS_KEYS.iter()
+                .filter_map(|&key| Some((key.to_string(), recording.remove(key)?)))
+                .collect();
+            meters["recording"] = Value::Object(progress);
+        }
+
+        status["type"] = json!("state");
+        let status = status.to_string();
+        if status != last_state {
+            if socket.send(Message::Text(status.clone())).is_err() { break; }
+            last_state = status;
+        }
+        meters["type"] = json!("meters");
+        if socket.send(Message::Text(meters.to_string())).is_err() { break; }
+
+        if Instant::now() >= next_ping {
+            if last_pong.elapsed() > WS_PING_INTERVAL * WS_MISSED_PONGS { break; }
+            if socket.send(Message::Ping(Vec::new())).is_err() { break; }
+            next_ping += WS_PING_INTERVAL;
+        }
+
+        loop {
+            let left = tick_end.saturating_duration_since(Instant::now());
+            if left.is_zero() || socket.get_mut().set_read_timeout(Some(left)).is_err() { break; }
+            match socket.read() {
+                Ok(Message::Pong(_)) => last_pong = Instant::now(),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e)) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+                Err(_) => break 'ticks,
+            }
+        }
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
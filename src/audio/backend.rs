@@ -1,6 +1,9 @@
-use std::sync::{Arc, atomic::AtomicBool};
+use crate::audio::controls::MixControls;
+use serde::Serialize;
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Mode {
     Shared,
     Exclusive,
@@ -18,6 +21,124 @@ pub struct DeviceEntry {
     pub is_output: bool,
 }
 
+/// Contiguous block of a device's channels; `first` is 0-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelRange {
+    pub first: u16,
+    pub count: u16,
+}
+
+impl ChannelRange {
+    /// Parse a 1-based channel or range as shown in the UI: "3" or "3-4".
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid channel range '{}' (expected e.g. 3 or 3-4)", s);
+        let (first, last) = match s.trim().split_once('-') {
+            Some((a, b)) => (a.trim().parse::<u16>().map_err(|_| invalid())?, b.trim().parse::<u16>().map_err(|_| invalid())?),
+            None => { let c = s.trim().parse::<u16>().map_err(|_| invalid())?; (c, c) }
+        };
+        if first == 0 || last < first {
+            return Err(invalid());
+        }
+        Ok(Self { first: first - 1, count: last - first + 1 })
+    }
+
+    /// 1-based, the form `parse` accepts.
+    pub fn label(&self) -> String {
+        if self.count == 1 { format!("{}", self.first + 1) } else { format!("{}-{}", self.first + 1, self.first + self.count) }
+    }
+
+    /// One past the last channel.
+    pub fn end(&self) -> usize {
+        self.first as usize + self.count as usize
+    }
+
+    /// Check the range against a device with `device_channels` channels.
+    pub fn validate(&self, device_channels: usize) -> Result<(), BackendError> {
+        if self.count == 0 || self.end() > device_channels {
+            return Err(BackendError::StartError(format!("Channels {} not available on a {}-channel device", self.label(), device_channels)));
+        }
+        Ok(())
+    }
+}
+
+/// What feeds an input slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputSource {
+    /// An entry from `enumerate_devices`.
+    Device(usize),
+    /// The slot's test signal generator (`MixControls::generators`), stereo at the output's
+    /// sample rate.
+    Generator,
+    /// The slot's file player (`MixControls::players`), stereo at the output's sample rate.
+    File,
+}
+
+/// What an input slot captures: a device, optionally only some of its channels, or a
+/// software source. When both slots select the same device ("virtual inputs", e.g. decks
+/// on channels 1-2 and 3-4 of one USB mixer) the backend opens it once and splits its
+/// frames between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputSelection {
+    pub source: InputSource,
+    /// `None` captures every channel; ignored for software sources.
+    pub channels: Option<ChannelRange>,
+}
+
+impl InputSelection {
+    /// The device entry, unless a software source feeds the slot.
+    pub fn device(&self) -> Option<usize> {
+        match self.source {
+            InputSource::Device(idx) => Some(idx),
+            InputSource::Generator | InputSource::File => None,
+        }
+    }
+}
+
+/// Where the mix goes: an entry from `enumerate_devices`, optionally only some of its
+/// channels (e.g. the headphone pair 3-4 of an 8-out interface); the other channels are
+/// written as silence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputSelection {
+    pub device: usize,
+    /// `None` fills every channel.
+    pub channels: Option<ChannelRange>,
+}
+
+/// Sample rates probed when a backend can only answer "is this format supported?".
+pub const COMMON_SAMPLE_RATES: [u32; 6] = [44100, 48000, 88200, 96000, 176400, 192000];
+
+/// Serializable description of a `DeviceEntry` plus the formats it accepts in its mode.
+/// `index` is the position in `enumerate_devices`, i.e. what `start` expects.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceDescription {
+    pub index: usize,
+    pub name: String,
+    pub device_id: Option<String>,
+    pub mode: Mode,
+    pub is_input: bool,
+    pub is_output: bool,
+    pub sample_rates: Vec<u32>,
+    pub channel_counts: Vec<u16>,
+    /// Sample formats such as "f32", "i16", "i24", "i32".
+    pub formats: Vec<String>,
+}
+
+impl DeviceDescription {
+    pub fn from_entry(index: usize, entry: &DeviceEntry) -> Self {
+        Self {
+            index,
+            name: entry.name.clone(),
+            device_id: entry.device_id.clone(),
+            mode: entry.mode,
+            is_input: entry.is_input,
+            is_output: entry.is_output,
+            sample_rates: Vec::new(),
+            channel_counts: Vec::new(),
+            formats: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum BackendError {
     InitError(String),
@@ -28,9 +149,22 @@ pub trait AudioBackend {
     /// Enumerate available devices as `DeviceEntry` (name + mode).
     fn enumerate_devices(&self) -> Result<Vec<DeviceEntry>, BackendError>;
 
+    /// Same list as `enumerate_devices`, with the sample rates, channel counts and formats
+    /// each entry supports. Slower: backends may have to probe every device.
+    fn describe_devices(&self) -> Result<Vec<DeviceDescription>, BackendError>;
+
     /// Start audio processing using selected device indices (from enumerate_devices list).
     /// This is a non-blocking call; actual audio runs on backend-managed threads/callbacks.
-    fn start(&mut self, input_a: Option<usize>, input_b: Option<usize>, output: Option<usize>, listen_a: Arc<AtomicBool>, listen_b: Arc<AtomicBool>) -> Result<(), BackendError>;
+    /// `controls` carries the listen toggles read by the render path and the meters it publishes.
+    ///
+    /// `outputs` holds one entry per output slot (`MixControls::outputs`, at most
+    /// `MAX_OUTPUTS`); unused slots are `None`. The first is the main output and must be
+    /// set: its clock drives the mix, and the others each get their own stream playing a
+    /// copy of it at the same sample rate.
+    ///
+    /// `cue_buses` holds the output of each cue bus (`MixControls::cue_buses`); a bus with
+    /// an output gets its own mix of the same captured inputs, rendered like an extra output.
+    fn start(&mut self, input_a: Option<InputSelection>, input_b: Option<InputSelection>, outputs: &[Option<OutputSelection>], cue_buses: &[Option<OutputSelection>], controls: Arc<MixControls>) -> Result<(), BackendError>;
 
     /// Stop audio processing and release resources.
     fn stop(&mut self) -> Result<(), BackendError>;
@@ -0,0 +1,157 @@
+// Metronome click for live sets with a drummer, mixed into the cue buses (or the main mix)
+// that listen to it. Beats are counted in frames of the output stream, so the click is
+// locked to the output clock; a beat starts on the first frame at or past its time.
+
+use crate::audio::controls::ClickControls;
+use crate::audio::source::SOURCE_CHANNELS;
+use std::f32::consts::TAU;
+use std::sync::atomic::Ordering;
+
+pub const MIN_BPM: f32 = 30.0;
+pub const MAX_BPM: f32 = 300.0;
+/// Beats per bar; the accent pattern has one bit per beat.
+pub const MAX_BEATS_PER_BAR: usize = 16;
+pub const MIN_CLICK_LEVEL_DB: f32 = -60.0;
+
+// Unaccented beats play this much quieter than accented ones
+const UNACCENTED_GAIN: f32 = 0.5;
+// Nothing is left of any sound after this
+const CLICK_SECONDS: f32 = 0.08;
+// Onset ramp, so the tones don't click on top of the click
+const ATTACK_SECONDS: f32 = 0.001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClickSound {
+    /// Sine beep, higher on accented beats.
+    Beep,
+    Woodblock,
+    /// Short noise burst, like a closed hi-hat.
+    Tick,
+}
+
+impl ClickSound {
+    pub const ALL: [ClickSound; 3] = [Self::Beep, Self::Woodblock, Self::Tick];
+
+    pub fn from_index(index: usize) -> Self {
+        Self::ALL.get(index).copied().unwrap_or(Self::Beep)
+    }
+
+    pub fn index(self) -> usize {
+        Self::ALL.iter().position(|&s| s == self).unwrap_or(0)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Beep => "Beep",
+            Self::Woodblock => "Woodblock",
+            Self::Tick => "Tick",
+        }
+    }
+
+    /// Name used by the config file and the API.
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Beep => "beep",
+            Self::Woodblock => "wood",
+            Self::Tick => "tick",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|s| s.key() == key)
+    }
+}
+
+/// Render side of the click: a stereo block with the same signal on both sides.
+pub struct Click {
+    sample_rate: f32,
+    // Frames since the current beat started, fractional because a beat rarely lasts a
+    // whole number of frames
+    pos: f64,
+    beat: usize,
+    // Somebody listened during the last block
+    heard: bool,
+    rng: u32,
+    block: Vec<f32>,
+}
+
+impl Click {
+    /// `max_frames` is the largest block `render` will be asked for.
+    pub fn new(sample_rate: u32, max_frames: usize) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1) as f32,
+            pos: 0.0,
+            beat: 0,
+            heard: false,
+            rng: 0x9e37_79b9,
+            block: vec![0.0; max_frames * SOURCE_CHANNELS],
+        }
+    }
+
+    /// Render `frames` of click; `None` while nobody listens (`heard` is false). The bar starts
+    /// over on beat one whenever the first listener turns it on.
+    pub fn render(&mut self, frames: usize, controls: &ClickControls, heard: bool) -> Option<&[f32]> {
+        if !heard {
+            self.heard = false;
+            return None;
+        }
+        if !self.heard {
+            self.heard = true;
+            self.pos = 0.0;
+            self.beat = 0;
+        }
+
+        let bpm = controls.bpm.load().clamp(MIN_BPM, MAX_BPM);
+        let period = self.sample_rate as f64 * 60.0 / bpm as f64;
+        let beats = controls.beats.load(Ordering::Relaxed).clamp(1, MAX_BEATS_PER_BAR);
+        let accents = controls.accents.load(Ordering::Relaxed);
+        let sound = ClickSound::from_index(controls.sound.load(Ordering::Relaxed));
+        let level = 10f32.powf(controls.level_db.load().min(0.0) / 20.0);
+
+        let total = frames * SOURCE_CHANNELS;
+        // Sized in `new`; only grows if a device hands over more than it announced
+        if self.block.len() < total { self.block.resize(total, 0.0); }
+        for frame in self.block[..total].chunks_exact_mut(SOURCE_CHANNELS) {
+            if self.pos >= period {
+                self.pos -= period;
+                self.beat += 1;
+            }
+            // The bar may have been shortened under the current beat
+            if self.beat >= beats {
+                self.beat = 0;
+            }
+            let accented = accents & (1 << self.beat) != 0;
+            let t = self.pos as f32 / self.sample_rate;
+            let value = if t < CLICK_SECONDS { voice(sound, t, accented, &mut self.rng) } else { 0.0 };
+            frame.fill(value * level * if accented { 1.0 } else { UNACCENTED_GAIN });
+            self.pos += 1.0;
+        }
+        controls.beat.store(self.beat, Ordering::Relaxed);
+        Some(&self.block[..total])
+    }
+}
+
+// Value of `sound` `t` seconds into a beat
+fn voice(sound: ClickSound, t: f32, accented: bool, rng: &mut u32) -> f32 {
+    let attack = (t / ATTACK_SECONDS).min(1.0);
+    match sound {
+        ClickSound::Beep => {
+            let f = if accented { 1500.0 } else { 1000.0 };
+            attack * (TAU * f * t).sin() * (-t / 0.03).exp()
+        }
+        ClickSound::Woodblock => {
+            let f = if accented { 2400.0 } else { 1800.0 };
+            // An inharmonic partial makes it knock rather than beep
+            let tone = (TAU * f * t).sin() + 0.5 * (TAU * f * 2.7 * t).sin();
+            attack * tone / 1.5 * (-t / 0.012).exp()
+        }
+        ClickSound::Tick => {
+            // xorshift32 noise
+            *rng ^= *rng << 13;
+            *rng ^= *rng >> 17;
+            *rng ^= *rng << 5;
+            let white = *rng as f32 / u32::MAX as f32 * 2.0 - 1.0;
+            white * (-t / 0.004).exp()
+        }
+    }
+}
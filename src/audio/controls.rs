@@ -0,0 +1,302 @@
+use crate::audio::matrix::{RouteMatrix, MAX_ROUTE_CHANNELS};
+use crate::audio::meters::Meters;
+use crate::audio::pads::PadBank;
+use crate::audio::player::FilePlayer;
+use crate::audio::probe::Probe;
+use crate::audio::tap::BlockTap;
+use crate::dsp::eq::{self, Band};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+/// f32 stored as raw bits, for parameters and readings shared with audio or worker threads.
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed)
+    }
+}
+
+/// Processing parameters of one input slot, applied by the engine before the listen toggle.
+pub struct InputControls {
+    /// Low/mid/high gain in dB; `eq::MIN_DB` and below is a full kill.
+    pub eq_gain_db: [AtomicF32; 3],
+    /// Kill switches, independent of the knob position (for hotkeys).
+    pub eq_kill: [AtomicBool; 3],
+    /// Bipolar filter knob: -1 = low-pass fully closed, 0 = off, 1 = high-pass fully closed.
+    pub filter: AtomicF32,
+    /// Filter resonance, 0..1.
+    pub filter_resonance: AtomicF32,
+    pub gate_enabled: AtomicBool,
+    pub gate_threshold_db: AtomicF32,
+    /// How far below the threshold the level must fall before the gate closes.
+    pub gate_hysteresis_db: AtomicF32,
+    pub gate_attack_ms: AtomicF32,
+    pub gate_hold_ms: AtomicF32,
+    pub gate_release_ms: AtomicF32,
+    /// Whether the gate currently lets signal through; written by the engine.
+    pub gate_open: AtomicBool,
+    /// Which input channels reach which output channels.
+    pub route: RouteMatrix,
+    /// Delay applied after processing, to line this input up with slower sources.
+    pub delay_ms: AtomicF32,
+    /// How long the samples just mixed waited in the input's ring; written by the engine.
+    pub queue_ms: AtomicF32,
+}
+
+impl InputControls {
+    pub fn new() -> Self {
+        Self {
+            eq_gain_db: [AtomicF32::new(0.0), AtomicF32::new(0.0), AtomicF32::new(0.0)],
+            eq_kill: Default::default(),
+            filter: AtomicF32::new(0.0),
+            filter_resonance: AtomicF32::new(0.0),
+            gate_enabled: AtomicBool::new(false),
+            gate_threshold_db: AtomicF32::new(-50.0),
+            gate_hysteresis_db: AtomicF32::new(6.0),
+            gate_attack_ms: AtomicF32::new(1.0),
+            gate_hold_ms: AtomicF32::new(50.0),
+            gate_release_ms: AtomicF32::new(200.0),
+            gate_open: AtomicBool::new(true),
+            route: RouteMatrix::new(),
+            delay_ms: AtomicF32::new(0.0),
+            queue_ms: AtomicF32::new(0.0),
+        }
+    }
+
+    /// Linear gain the EQ should move towards for `band`.
+    pub fn eq_target(&self, band: Band) -> f32 {
+        let i = band as usize;
+        if self.eq_kill[i].load(Ordering::Relaxed) {
+            return 0.0;
+        }
+        eq::gain_from_db(self.eq_gain_db[i].load())
+    }
+}
+
+/// Talkover: one input (the microphone) ducks all the others while it is above threshold.
+pub struct TalkoverControls {
+    pub enabled: AtomicBool,
+    /// Input slot acting as the microphone.
+    pub source: AtomicUsize,
+    pub threshold_db: AtomicF32,
+    /// Attenuation of the other inputs while ducking (negative dB).
+    pub depth_db: AtomicF32,
+    pub attack_ms: AtomicF32,
+    pub release_ms: AtomicF32,
+    /// Attenuation currently applied (0 dB when not ducking); written by the engine.
+    pub reduction_db: AtomicF32,
+}
+
+impl TalkoverControls {
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            source: AtomicUsize::new(1),
+            threshold_db: AtomicF32::new(-36.0),
+            depth_db: AtomicF32::new(-15.0),
+            attack_ms: AtomicF32::new(20.0),
+            release_ms: AtomicF32::new(500.0),
+            reduction_db: AtomicF32::new(0.0),
+        }
+    }
+}
+
+/// How many outputs can run at once: the main output plus extra copies of its mix.
+pub const MAX_OUTPUTS: usize = 3;
+/// Range of volume trims (per output, and per input on a cue bus).
+pub const MIN_TRIM_DB: f32 = -60.0;
+pub const MAX_TRIM_DB: f32 = 12.0;
+
+/// Per-output settings, by output slot (0 is the main output).
+pub struct OutputControls {
+    pub volume_db: AtomicF32,
+    /// Extra delay so outputs with less latency line up with the slowest one.
+    pub delay_ms: AtomicF32,
+}
+
+impl OutputControls {
+    pub fn new() -> Self {
+        Self { volume_db: AtomicF32::new(0.0), delay_ms: AtomicF32::new(0.0) }
+    }
+}
+
+/// How many cue buses can run besides the main mix, e.g. a second DJ's headphones.
+pub const MAX_CUE_BUSES: usize = 2;
+
+/// An independent mix of the same inputs (after their processing) with its own listen
+/// toggles, input gains and output.
+pub struct CueBusControls {
+    /// Listen flag by input slot (0 = A, 1 = B).
+    pub listen: [AtomicBool; 2],
+    /// Input gain on this bus only, in dB.
+    pub gain_db: [AtomicF32; 2],
+    /// Sample pads are heard on this bus.
+    pub listen_pads: AtomicBool,
+    /// The metronome click is heard on this bus.
+    pub listen_click: AtomicBool,
+    pub output: OutputControls,
+}
+
+impl CueBusControls {
+    pub fn new() -> Self {
+        Self {
+            listen: Default::default(),
+            gain_db: [AtomicF32::new(0.0), AtomicF32::new(0.0)],
+            listen_pads: AtomicBool::new(false),
+            listen_click: AtomicBool::new(false),
+            output: OutputControls::new(),
+        }
+    }
+
+    pub fn toggle(&self, slot: usize) {
+        self.listen[slot].fetch_xor(true, Ordering::Relaxed);
+    }
+}
+
+/// Test signal generator of one input slot, used while the slot's source is the generator.
+pub struct GeneratorControls {
+    /// `GeneratorKind` by index.
+    pub kind: AtomicUsize,
+    /// Tone frequency of the sine and the identification beeps, in Hz.
+    pub frequency: AtomicF32,
+    /// Peak level in dBFS.
+    pub level_db: AtomicF32,
+    pub sweep_start: AtomicF32,
+    pub sweep_end: AtomicF32,
+    pub sweep_seconds: AtomicF32,
+}
+
+impl GeneratorControls {
+    pub fn new() -> Self {
+        Self {
+            kind: AtomicUsize::new(0),
+            frequency: AtomicF32::new(1000.0),
+            level_db: AtomicF32::new(-18.0),
+            sweep_start: AtomicF32::new(20.0),
+            sweep_end: AtomicF32::new(20_000.0),
+            sweep_seconds: AtomicF32::new(10.0),
+        }
+    }
+}
+
+/// Metronome click; cue buses turn it on with their own `listen_click`.
+pub struct ClickControls {
+    /// Heard on the main mix.
+    pub listen: AtomicBool,
+    pub bpm: AtomicF32,
+    /// Beats per bar.
+    pub beats: AtomicUsize,
+    /// Accented beats, one bit per beat (bit 0 = beat one).
+    pub accents: AtomicUsize,
+    /// `ClickSound` by index.
+    pub sound: AtomicUsize,
+    /// Level of accented beats in dBFS.
+    pub level_db: AtomicF32,
+    /// Beat being played (from 0), as counted by the render path.
+    pub beat: AtomicUsize,
+}
+
+impl ClickControls {
+    pub fn new() -> Self {
+        Self {
+            listen: AtomicBool::new(false),
+            bpm: AtomicF32::new(120.0),
+            beats: AtomicUsize::new(4),
+            accents: AtomicUsize::new(1),
+            sound: AtomicUsize::new(0),
+            level_db: AtomicF32::new(-12.0),
+            beat: AtomicUsize::new(0),
+        }
+    }
+
+    /// Somebody listens to the click: the main mix or any cue bus.
+    pub fn heard(&self, cue_buses: &[CueBusControls]) -> bool {
+        self.listen.load(Ordering::Relaxed) || cue_buses.iter().any(|bus| bus.listen_click.load(Ordering::Relaxed))
+    }
+}
+
+/// Live mixing parameters shared between the UI, the remote API and the audio threads.
+/// The render path reads this every block, so it only touches atomics and `try_lock`s.
+pub struct MixControls {
+    pub listen_a: AtomicBool,
+    pub listen_b: AtomicBool,
+    pub meters: Meters,
+    /// Mixed output blocks before format conversion, with the mix's own channels (not the
+    /// silent ones when the mix goes to a subset of the device's channels).
+    pub output_tap: Arc<BlockTap>,
+    /// Raw input blocks by slot, as converted by the capture threads (pre-gain, pre-listen).
+    pub input_taps: [Arc<BlockTap>; 2],
+    /// Per-input gate, EQ, filter and routing, by slot.
+    pub inputs: [InputControls; 2],
+    pub talkover: TalkoverControls,
+    pub outputs: [OutputControls; MAX_OUTPUTS],
+    pub cue_buses: [CueBusControls; MAX_CUE_BUSES],
+    /// Measurement signal that replaces the main mix while it plays.
+    pub probe: Probe,
+    /// Test signal generators, by input slot.
+    pub generators: [GeneratorControls; 2],
+    /// File players, by input slot.
+    pub players: [FilePlayer; 2],
+    pub pads: PadBank,
+    pub click: ClickControls,
+}
+
+impl MixControls {
+    pub fn new() -> Self {
+        Self {
+            listen_a: AtomicBool::new(false),
+            listen_b: AtomicBool::new(false),
+            meters: Meters::new(),
+            output_tap: Arc::new(BlockTap::new()),
+            input_taps: [Arc::new(BlockTap::new()), Arc::new(BlockTap::new())],
+            inputs: [InputControls::new(), InputControls::new()],
+            talkover: TalkoverControls::new(),
+            outputs: std::array::from_fn(|_| OutputControls::new()),
+            cue_buses: std::array::from_fn(|_| CueBusControls::new()),
+            probe: Probe::new(),
+            generators: [GeneratorControls::new(), GeneratorControls::new()],
+            players: [FilePlayer::new(), FilePlayer::new()],
+            pads: PadBank::new(),
+            click: ClickControls::new(),
+        }
+    }
+
+    /// Listen flag by input slot (0 = A, 1 = B).
+    pub fn listen(&self, idx: usize) -> &AtomicBool {
+        if idx == 0 { &self.listen_a } else { &self.listen_b }
+    }
+
+    /// Input and output channel counts the routing matrix of `slot` is shown with: the running
+    /// formats, or stereo while audio is stopped.
+    pub fn route_channels(&self, slot: usize) -> (usize, usize) {
+        let channels = |tap: &BlockTap| tap.format().map(|f| (f.channels as usize).min(MAX_ROUTE_CHANNELS)).unwrap_or(2);
+        (channels(&self.input_taps[slot]), channels(&self.output_tap))
+    }
+
+    /// Estimated time from input `slot` to the main output's speakers, in ms: capture block,
+    /// ring queue, the input's delay, render block and the main output's delay. Converter and
+    /// driver latency the devices don't report is not included; `None` while stopped.
+    pub fn input_latency_ms(&self, slot: usize) -> Option<f32> {
+        let capture = self.input_taps[slot].block_ms()?;
+        let render = self.output_tap.block_ms()?;
+        let input = &self.inputs[slot];
+        Some(capture + input.queue_ms.load() + input.delay_ms.load() + render + self.outputs[0].delay_ms.load())
+    }
+
+    pub fn toggle_a(&self) {
+        self.listen_a.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn toggle_b(&self) {
+        self.listen_b.fetch_xor(true, Ordering::Relaxed);
+    }
+}
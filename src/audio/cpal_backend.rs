@@ -1,8 +1,12 @@
-use crate::audio::backend::{AudioBackend, BackendError, DeviceEntry, Mode};
+use crate::audio::backend::{AudioBackend, BackendError, ChannelRange, DeviceDescription, DeviceEntry, InputSelection, Mode, OutputSelection, COMMON_SAMPLE_RATES};
+use crate::audio::controls::{MixControls, MAX_CUE_BUSES, MAX_OUTPUTS};
+use crate::audio::engine::{CaptureSink, CueBusMix, EngineInput, MixEngine, OutputFeed, OutputParams, OutputStage, RenderSource};
+use crate::audio::source::{SourceThread, SOURCE_CHANNELS};
+use crate::audio::tap::{BlockTap, TapFormat};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{SampleFormat, StreamConfig};
-use ringbuf::HeapRb;
-use std::sync::{Arc, atomic::AtomicBool, atomic::Ordering};
+use cpal::{SampleFormat, StreamConfig, SupportedBufferSize, SupportedStreamConfigRange};
+use ringbuf::{HeapProducer, HeapRb};
+use std::sync::Arc;
 
 const BUFFER_SIZE: usize = 16384;
 
@@ -10,6 +14,8 @@ pub struct CpalBackend {
     host: cpal::Host,
     // keep streams alive
     active_streams: Vec<cpal::Stream>,
+    // Software sources feeding input slots
+    sources: Vec<SourceThread>,
     // cached devices corresponding to enumerate_devices ordering (one per unique friendly name)
     devices: Vec<cpal::Device>,
 }
@@ -17,7 +23,7 @@ pub struct CpalBackend {
 impl CpalBackend {
     pub fn new() -> Result<Self, BackendError> {
         let host = cpal::default_host();
-        Ok(Self { host, active_streams: Vec::new(), devices: Vec::new() })
+        Ok(Self { host, active_streams: Vec::new(), sources: Vec::new(), devices: Vec::new() })
     }
 
     fn build_stream_config_from_device(device: &cpal::Device) -> Result<StreamConfig, BackendError> {
@@ -25,6 +31,99 @@ impl CpalBackend {
             .map_err(|e| BackendError::InitError(format!("Failed to get default config: {}", e)))?;
         Ok(cfg.into())
     }
+
+    // Build and play one input stream for a device, feeding one sink per slot that uses it
+    // (two for virtual inputs); returns each slot's channel count, or `None` if the device
+    // has gone away
+    fn start_input(&mut self, idx: usize, slots: Vec<(HeapProducer<f32>, Arc<BlockTap>, Option<ChannelRange>)>) -> Result<Option<Vec<usize>>, BackendError> {
+        let device = match self.devices.get(idx) {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+        let cfg = device.default_input_config().map_err(|e| BackendError::StartError(format!("Failed to get default input config: {}", e)))?;
+        let stream_cfg: StreamConfig = cfg.clone().into();
+        let device_channels = stream_cfg.channels as usize;
+
+        let mut channels = Vec::with_capacity(slots.len());
+        let mut sinks = Vec::with_capacity(slots.len());
+        for (producer, tap, range) in slots {
+            if let Some(range) = range { range.validate(device_channels)?; }
+            let count = range.map(|r| r.count as usize).unwrap_or(device_channels);
+            tap.set_format(Some(TapFormat { sample_rate: stream_cfg.sample_rate.0, channels: count as u16 }));
+            sinks.push(CaptureSink::new(producer, tap, BUFFER_SIZE).select(device_channels, range));
+            channels.push(count);
+        }
+
+        let err_fn = move |err| eprintln!("Input stream error: {:?}", err);
+        let stream = match cfg.sample_format() {
+            SampleFormat::F32 => device.build_input_stream(&stream_cfg, move |data: &[f32], _| for sink in sinks.iter_mut() { sink.push(data, |s| s) }, err_fn, None),
+            SampleFormat::I16 => device.build_input_stream(&stream_cfg, move |data: &[i16], _| for sink in sinks.iter_mut() { sink.push(data, |s| s as f32 / 32768.0) }, err_fn, None),
+            SampleFormat::U16 => device.build_input_stream(&stream_cfg, move |data: &[u16], _| for sink in sinks.iter_mut() { sink.push(data, |s| (s as f32 - 32768.0) / 32768.0) }, err_fn, None),
+            _ => return Err(BackendError::StartError("Unsupported input sample format".into())),
+        }.map_err(|e| BackendError::StartError(format!("Failed to build input stream: {}", e)))?;
+
+        stream.play().map_err(|e| BackendError::StartError(format!("Failed to play input stream: {}", e)))?;
+        self.active_streams.push(stream);
+        Ok(Some(channels))
+    }
+
+    // Default output config of a device; the render callbacks only write f32
+    fn output_config(&self, idx: usize) -> Result<StreamConfig, BackendError> {
+        let device = self.devices.get(idx).ok_or_else(|| BackendError::StartError("Selected output device not found".into()))?;
+        let cfg = device.default_output_config().map_err(|e| BackendError::StartError(format!("Failed to get default output config: {}", e)))?;
+        if cfg.sample_format() != SampleFormat::F32 {
+            return Err(BackendError::StartError("Only f32 output sample format supported in prototype".into()));
+        }
+        Ok(cfg.into())
+    }
+
+    // Largest block the device's output callback may ask for; cpal only reports a range (if
+    // that), so this is capped at the ring size
+    fn max_output_frames(&self, idx: usize) -> usize {
+        let cfg = self.devices.get(idx).and_then(|d| d.default_output_config().ok());
+        match cfg.as_ref().map(|c| c.buffer_size()) {
+            Some(SupportedBufferSize::Range { max, .. }) => (*max as usize).clamp(1, BUFFER_SIZE),
+            _ => BUFFER_SIZE,
+        }
+    }
+
+    // Build and play an output stream pulling its blocks from `source`
+    fn start_output<R: RenderSource + 'static>(&mut self, idx: usize, stream_cfg: &StreamConfig, mut source: R) -> Result<(), BackendError> {
+        let device = self.devices.get(idx).ok_or_else(|| BackendError::StartError("Selected output device not found".into()))?;
+        let channels = stream_cfg.channels as usize;
+        let stream = device.build_output_stream(
+            stream_cfg,
+            move |data: &mut [f32], _| {
+                let frames = data.len() / channels;
+                let rendered = source.render(frames);
+                data[..rendered.len()].copy_from_slice(rendered);
+            },
+            move |err| eprintln!("Output stream error: {:?}", err),
+            None,
+        ).map_err(|e| BackendError::StartError(format!("Failed to build output stream: {}", e)))?;
+
+        stream.play().map_err(|e| BackendError::StartError(format!("Failed to play output stream: {}", e)))?;
+        self.active_streams.push(stream);
+        Ok(())
+    }
+
+    // Collapse cpal's supported config ranges into (rates, channel counts, formats)
+    fn summarize_configs(ranges: &[SupportedStreamConfigRange]) -> (Vec<u32>, Vec<u16>, Vec<String>) {
+        let mut rates = Vec::new();
+        let mut channels = Vec::new();
+        let mut formats = Vec::new();
+        for r in ranges {
+            for &rate in COMMON_SAMPLE_RATES.iter() {
+                if rate >= r.min_sample_rate().0 && rate <= r.max_sample_rate().0 && !rates.contains(&rate) { rates.push(rate); }
+            }
+            if !channels.contains(&r.channels()) { channels.push(r.channels()); }
+            let fmt = r.sample_format().to_string();
+            if !formats.contains(&fmt) { formats.push(fmt); }
+        }
+        rates.sort();
+        channels.sort();
+        (rates, channels, formats)
+    }
 }
 
 impl AudioBackend for CpalBackend {
@@ -53,9 +152,36 @@ impl AudioBackend for CpalBackend {
         }
     }
 
-    fn start(&mut self, input_a: Option<usize>, input_b: Option<usize>, output: Option<usize>, listen_a: Arc<AtomicBool>, listen_b: Arc<AtomicBool>) -> Result<(), BackendError> {
+    fn describe_devices(&self) -> Result<Vec<DeviceDescription>, BackendError> {
+        let entries = self.enumerate_devices()?;
+        let mut out: Vec<DeviceDescription> = entries.iter().enumerate().map(|(i, e)| DeviceDescription::from_entry(i, e)).collect();
+
+        let devices = self.host.devices().map_err(|e| BackendError::InitError(format!("Failed to enumerate devices: {}", e)))?;
+        for device in devices {
+            let name = device.name().unwrap_or_else(|_| "Unknown Device".to_string());
+            let mut ranges: Vec<SupportedStreamConfigRange> = Vec::new();
+            if let Ok(iter) = device.supported_input_configs() { ranges.extend(iter); }
+            if let Ok(iter) = device.supported_output_configs() { ranges.extend(iter); }
+            let (rates, channels, formats) = CpalBackend::summarize_configs(&ranges);
+
+            // Both the SHARED and EXCLUSIVE entries map to the same cpal device
+            for desc in out.iter_mut() {
+                if desc.name == format!("{} (SHARED)", name) || desc.name == format!("{} (EXCLUSIVE)", name) {
+                    if !desc.sample_rates.is_empty() { continue; }
+                    desc.sample_rates = rates.clone();
+                    desc.channel_counts = channels.clone();
+                    desc.formats = formats.clone();
+                }
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn start(&mut self, input_a: Option<InputSelection>, input_b: Option<InputSelection>, outputs: &[Option<OutputSelection>], cue_buses: &[Option<OutputSelection>], controls: Arc<MixControls>) -> Result<(), BackendError> {
         // Clear any existing streams
         self.active_streams.clear();
+        self.sources.clear();
 
         // Rebuild devices vector aligned with unique friendly names
         self.devices.clear();
@@ -74,176 +200,109 @@ impl AudioBackend for CpalBackend {
             opt_idx.map(|i| i / 2)
         };
 
-        let in_a_dev = map_index(input_a);
-        let in_b_dev = map_index(input_b);
-        let out_dev = map_index(output);
+        let in_a_dev = map_index(input_a.and_then(|s| s.device()));
+        let in_b_dev = map_index(input_b.and_then(|s| s.device()));
 
         // Prepare ring buffers for each input
         let rb_a = HeapRb::<f32>::new(BUFFER_SIZE);
-        let (mut prod_a, mut cons_a) = rb_a.split();
+        let (prod_a, cons_a) = rb_a.split();
 
         let rb_b = HeapRb::<f32>::new(BUFFER_SIZE);
-        let (mut prod_b, mut cons_b) = rb_b.split();
-
-        // Create input streams
-        if let Some(idx) = in_a_dev {
-            if let Some(device) = self.devices.get(idx) {
-                let cfg = device.default_input_config().map_err(|e| BackendError::StartError(format!("Failed to get default input config: {}", e)))?;
-                let stream_cfg: StreamConfig = cfg.clone().into();
-                match cfg.sample_format() {
-                    SampleFormat::F32 => {
-                        let mut prod = prod_a; // move producer into closure
-                        let stream = device.build_input_stream(
-                            &stream_cfg,
-                            move |data: &[f32], _| {
-                                for &s in data { let _ = prod.push(s); }
-                            },
-                            move |err| eprintln!("Input stream error: {:?}", err),
-                            None,
-                        ).map_err(|e| BackendError::StartError(format!("Failed to build input stream: {}", e)))?;
-                        stream.play().map_err(|e| BackendError::StartError(format!("Failed to play input stream: {}", e)))?;
-                        self.active_streams.push(stream);
-                    }
-                    SampleFormat::I16 => {
-                        let mut prod = prod_a;
-                        let stream = device.build_input_stream(
-                            &stream_cfg,
-                            move |data: &[i16], _| {
-                                for &s in data {
-                                    let f = (s as f32) / 32768.0;
-                                    let _ = prod.push(f);
-                                }
-                            },
-                            move |err| eprintln!("Input stream error: {:?}", err),
-                            None,
-                        ).map_err(|e| BackendError::StartError(format!("Failed to build input stream: {}", e)))?;
-                        stream.play().map_err(|e| BackendError::StartError(format!("Failed to play input stream: {}", e)))?;
-                        self.active_streams.push(stream);
-                    }
-                    SampleFormat::U16 => {
-                        let mut prod = prod_a;
-                        let stream = device.build_input_stream(
-                            &stream_cfg,
-                            move |data: &[u16], _| {
-                                for &s in data {
-                                    let f = (s as f32 - 32768.0) / 32768.0;
-                                    let _ = prod.push(f);
-                                }
-                            },
-                            move |err| eprintln!("Input stream error: {:?}", err),
-                            None,
-                        ).map_err(|e| BackendError::StartError(format!("Failed to build input stream: {}", e)))?;
-                        stream.play().map_err(|e| BackendError::StartError(format!("Failed to play input stream: {}", e)))?;
-                        self.active_streams.push(stream);
-                    }
-                    _ => {
-                        return Err(BackendError::StartError("Unsupported input sample format".into()));
-                    }
-                }
+        let (prod_b, cons_b) = rb_b.split();
+
+        // Unused slots must not look recordable
+        controls.input_taps[0].set_format(None);
+        controls.input_taps[1].set_format(None);
+
+        // Channel counts of the opened inputs, needed by the mixing engine. Both slots on the
+        // same device share one stream (virtual inputs). Slots without a device keep their
+        // ring for a software source
+        let mut slot_a = Some((prod_a, controls.input_taps[0].clone(), input_a.and_then(|s| s.channels)));
+        let mut slot_b = Some((prod_b, controls.input_taps[1].clone(), input_b.and_then(|s| s.channels)));
+        let (in_a_channels, in_b_channels) = match (in_a_dev, in_b_dev) {
+            (Some(a), Some(b)) if a == b => match self.start_input(a, slot_a.take().into_iter().chain(slot_b.take()).collect())? {
+                Some(channels) => (Some(channels[0]), Some(channels[1])),
+                None => (None, None),
+            },
+            (a, b) => {
+                let a = match a { Some(idx) => self.start_input(idx, slot_a.take().into_iter().collect())?.map(|c| c[0]), None => None };
+                let b = match b { Some(idx) => self.start_input(idx, slot_b.take().into_iter().collect())?.map(|c| c[0]), None => None };
+                (a, b)
             }
+        };
+
+        // Open every output's config first: the mix takes its width and sample rate from the
+        // main output
+        let mut opened = Vec::new();
+        let buses = cue_buses.iter().take(MAX_CUE_BUSES).enumerate().map(|(bus, s)| (OutputParams::CueBus(bus), s));
+        for (slot, selection) in outputs.iter().take(MAX_OUTPUTS).enumerate().map(|(slot, s)| (OutputParams::Output(slot), s)).chain(buses) {
+            let selection = match selection {
+                Some(s) => s,
+                None if matches!(slot, OutputParams::Output(0)) => return Err(BackendError::StartError("No output device selected".into())),
+                None => continue,
+            };
+            let idx = selection.device / 2;
+            let stream_cfg = self.output_config(idx)?;
+            if let Some(range) = selection.channels { range.validate(stream_cfg.channels as usize)?; }
+            opened.push((slot, idx, stream_cfg, selection.channels));
         }
+        let (_, main_idx, main_cfg, main_range) = match opened.first() {
+            Some(main) => main.clone(),
+            None => return Err(BackendError::StartError("No output device selected".into())),
+        };
+        let sample_rate = main_cfg.sample_rate.0;
+        let mix_channels = main_range.map(|r| r.count).unwrap_or(main_cfg.channels);
+        controls.output_tap.set_format(Some(TapFormat { sample_rate, channels: mix_channels }));
+        let mix_channels = mix_channels as usize;
 
-        if let Some(idx) = in_b_dev {
-            if let Some(device) = self.devices.get(idx) {
-                let cfg = device.default_input_config().map_err(|e| BackendError::StartError(format!("Failed to get default input config: {}", e)))?;
-                let stream_cfg: StreamConfig = cfg.clone().into();
-                match cfg.sample_format() {
-                    SampleFormat::F32 => {
-                        let mut prod = prod_b; // move producer into closure
-                        let stream = device.build_input_stream(
-                            &stream_cfg,
-                            move |data: &[f32], _| {
-                                for &s in data { let _ = prod.push(s); }
-                            },
-                            move |err| eprintln!("Input stream error: {:?}", err),
-                            None,
-                        ).map_err(|e| BackendError::StartError(format!("Failed to build input stream: {}", e)))?;
-                        stream.play().map_err(|e| BackendError::StartError(format!("Failed to play input stream: {}", e)))?;
-                        self.active_streams.push(stream);
-                    }
-                    SampleFormat::I16 => {
-                        let mut prod = prod_b;
-                        let stream = device.build_input_stream(
-                            &stream_cfg,
-                            move |data: &[i16], _| {
-                                for &s in data {
-                                    let f = (s as f32) / 32768.0;
-                                    let _ = prod.push(f);
-                                }
-                            },
-                            move |err| eprintln!("Input stream error: {:?}", err),
-                            None,
-                        ).map_err(|e| BackendError::StartError(format!("Failed to build input stream: {}", e)))?;
-                        stream.play().map_err(|e| BackendError::StartError(format!("Failed to play input stream: {}", e)))?;
-                        self.active_streams.push(stream);
-                    }
-                    SampleFormat::U16 => {
-                        let mut prod = prod_b;
-                        let stream = device.build_input_stream(
-                            &stream_cfg,
-                            move |data: &[u16], _| {
-                                for &s in data {
-                                    let f = (s as f32 - 32768.0) / 32768.0;
-                                    let _ = prod.push(f);
-                                }
-                            },
-                            move |err| eprintln!("Input stream error: {:?}", err),
-                            None,
-                        ).map_err(|e| BackendError::StartError(format!("Failed to build input stream: {}", e)))?;
-                        stream.play().map_err(|e| BackendError::StartError(format!("Failed to play input stream: {}", e)))?;
-                        self.active_streams.push(stream);
-                    }
-                    _ => {
-                        return Err(BackendError::StartError("Unsupported input sample format".into()));
-                    }
+        // Software sources run at the output's rate, so they start once it is known
+        let mut source_channels = [None, None];
+        for (slot, (selection, ring)) in [(input_a, slot_a), (input_b, slot_b)].into_iter().enumerate() {
+            if let (Some(selection), Some((producer, tap, _))) = (selection, ring) {
+                if let Some(source) = SourceThread::start(selection.source, slot, producer, tap, controls.clone(), sample_rate) {
+                    self.sources.push(source);
+                    source_channels[slot] = Some(SOURCE_CHANNELS);
                 }
             }
         }
+        let in_a_channels = in_a_channels.or(source_channels[0]);
+        let in_b_channels = in_b_channels.or(source_channels[1]);
 
-        // Create output stream that mixes from both consumers
-        let idx = match out_dev {
-            Some(i) => i,
-            None => return Err(BackendError::StartError("No output device selected".into())),
-        };
-        if let Some(device) = self.devices.get(idx) {
-                let cfg = device.default_output_config().map_err(|e| BackendError::StartError(format!("Failed to get default output config: {}", e)))?;
-                let stream_cfg: StreamConfig = cfg.clone().into();
-
-                match cfg.sample_format() {
-                    SampleFormat::F32 => {
-                        let mut cons_a = cons_a; // move consumer into closure
-                        let mut cons_b = cons_b; // move consumer into closure
-                        let channels = stream_cfg.channels as usize;
-
-                        let stream = device.build_output_stream(
-                            &stream_cfg,
-                            move |data: &mut [f32], _| {
-                                let use_a = listen_a.load(Ordering::Relaxed);
-                                let use_b = listen_b.load(Ordering::Relaxed);
-                                for frame in data.chunks_mut(channels) {
-                                    let sample_a = if use_a { cons_a.pop().unwrap_or(0.0) } else { let _ = cons_a.pop(); 0.0 };
-                                    let sample_b = if use_b { cons_b.pop().unwrap_or(0.0) } else { let _ = cons_b.pop(); 0.0 };
-                                    let mixed = sample_a + sample_b;
-                                    for sample in frame.iter_mut() { *sample = mixed; }
-                                }
-                            },
-                            move |err| eprintln!("Output stream error: {:?}", err),
-                            None,
-                        ).map_err(|e| BackendError::StartError(format!("Failed to build output stream: {}", e)))?;
-
-                        stream.play().map_err(|e| BackendError::StartError(format!("Failed to play output stream: {}", e)))?;
-                        self.active_streams.push(stream);
-                    }
-                    SampleFormat::I16 | SampleFormat::U16 => {
-                        return Err(BackendError::StartError("Only f32 output sample format supported in prototype".into()));
-                    }
-                    _ => {
-                        return Err(BackendError::StartError("Only f32 output sample format supported in prototype".into()));
-                    }
-                }
-            } else {
-                return Err(BackendError::StartError("Selected output device not found".into()));
+        // Extra outputs play a copy of the mix, cue buses their own mix, each through a ring
+        let mut feeds = Vec::new();
+        let mut bus_mixes = Vec::new();
+        let mut extra = Vec::new();
+        for (slot, idx, stream_cfg, range) in opened.into_iter().skip(1) {
+            if stream_cfg.sample_rate.0 != sample_rate {
+                return Err(BackendError::StartError(format!(
+                    "{} sample rate mismatch ({} Hz vs main output {} Hz)", slot.label(), stream_cfg.sample_rate.0, sample_rate
+                )));
             }
+            let channels = match slot {
+                OutputParams::Output(_) => mix_channels,
+                OutputParams::CueBus(_) => range.map(|r| r.count).unwrap_or(stream_cfg.channels) as usize,
+            };
+            let (producer, consumer) = HeapRb::<f32>::new(BUFFER_SIZE * channels).split();
+            match slot {
+                OutputParams::Output(_) => feeds.push(producer),
+                OutputParams::CueBus(bus) => bus_mixes.push(CueBusMix::new(bus, channels, producer, sample_rate)),
+            }
+            let stage = OutputStage::new(slot, channels, stream_cfg.channels as usize, range, sample_rate);
+            extra.push((idx, stream_cfg, OutputFeed::new(controls.clone(), consumer, stage)));
+        }
+
+        // Engine owns the consumers; it is moved into the main output's callback
+        let engine = MixEngine::new(controls.clone(), vec![
+            in_a_channels.map(|ch| EngineInput::new(cons_a, ch, sample_rate)),
+            in_b_channels.map(|ch| EngineInput::new(cons_b, ch, sample_rate)),
+        ], OutputStage::new(OutputParams::Output(0), mix_channels, main_cfg.channels as usize, main_range, sample_rate), sample_rate, self.max_output_frames(main_idx))
+            .with_feeds(feeds)
+            .with_cue_buses(bus_mixes);
+
+        for (idx, stream_cfg, feed) in extra {
+            self.start_output(idx, &stream_cfg, feed)?;
+        }
+        self.start_output(main_idx, &main_cfg, engine)?;
 
         Ok(())
     }
@@ -251,6 +310,7 @@ impl AudioBackend for CpalBackend {
     fn stop(&mut self) -> Result<(), BackendError> {
         // Dropping streams will stop audio
         self.active_streams.clear();
+        self.sources.clear();
         Ok(())
     }
 }
@@ -0,0 +1,491 @@
+// Mixing engine shared by all backends.
+//
+// Capture threads/callbacks push interleaved f32 samples into one ring buffer per input
+// (through a `CaptureSink`; a device split into virtual inputs feeds one sink per slot);
+// the render path asks the engine for `frames` frames of mixed output and only has to
+// convert the returned f32 block into the device's sample format.
+
+use crate::audio::backend::ChannelRange;
+use crate::audio::click::Click;
+use crate::audio::controls::MixControls;
+use crate::audio::matrix::MAX_ROUTE_CHANNELS;
+use crate::audio::pads::{self, PadVoices};
+use crate::audio::tap::BlockTap;
+use crate::dsp::delay::DelayLine;
+use crate::dsp::ducker::Ducker;
+use crate::dsp::eq::ThreeBandEq;
+use crate::dsp::filter::SweepFilter;
+use crate::dsp::gate::NoiseGate;
+use crate::dsp::Smoother;
+use ringbuf::{HeapConsumer, HeapProducer};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+/// Capture-side end of an input: each converted block goes to the engine's ring and to the
+/// input's tap (multitrack recording), before any gain or listen toggle is applied.
+pub struct CaptureSink {
+    producer: HeapProducer<f32>,
+    tap: Arc<BlockTap>,
+    block: Vec<f32>,
+    // Device channel count and the channels this sink keeps, for virtual inputs
+    select: Option<(usize, ChannelRange)>,
+}
+
+impl CaptureSink {
+    /// `capacity` is the largest expected block in samples; bigger blocks still work but
+    /// allocate once on the capture thread.
+    pub fn new(producer: HeapProducer<f32>, tap: Arc<BlockTap>, capacity: usize) -> Self {
+        Self { producer, tap, block: Vec::with_capacity(capacity), select: None }
+    }
+
+    /// Keep only `channels` of each `device_channels`-wide frame; `None` keeps them all.
+    /// The range must fit the device (see `ChannelRange::validate`).
+    pub fn select(mut self, device_channels: usize, channels: Option<ChannelRange>) -> Self {
+        self.select = channels.map(|range| (device_channels.max(1), range));
+        self
+    }
+
+    /// Convert a block of device samples and hand it on. Never blocks: samples that don't
+    /// fit the ring are dropped, like the per-sample pushes this replaces.
+    pub fn push<T: Copy>(&mut self, samples: &[T], convert: impl Fn(T) -> f32) {
+        self.block.clear();
+        match self.select {
+            Some((device_channels, range)) => {
+                for frame in samples.chunks_exact(device_channels) {
+                    self.block.extend(frame[range.first as usize..range.end()].iter().map(|&s| convert(s)));
+                }
+            }
+            None => self.block.extend(samples.iter().map(|&s| convert(s))),
+        }
+        self.flush();
+    }
+
+    /// `len` is in device samples, like the blocks given to `push`.
+    pub fn push_silence(&mut self, len: usize) {
+        let len = match self.select {
+            Some((device_channels, range)) => len / device_channels * range.count as usize,
+            None => len,
+        };
+        self.block.clear();
+        self.block.resize(len, 0.0);
+        self.flush();
+    }
+
+    /// Samples waiting in the ring for the render path.
+    pub fn queued(&self) -> usize {
+        self.producer.len()
+    }
+
+    fn flush(&mut self) {
+        self.producer.push_slice(&self.block);
+        self.tap.push(&self.block);
+    }
+}
+
+/// Upper bound of an input's alignment delay.
+pub const MAX_INPUT_DELAY_MS: f32 = 1000.0;
+
+/// One input as seen by the render path.
+pub struct EngineInput {
+    consumer: HeapConsumer<f32>,
+    channels: usize,
+    sample_rate: u32,
+    // Interleaved samples of the current block (input channel layout)
+    block: Vec<f32>,
+    gate: NoiseGate,
+    eq: ThreeBandEq,
+    filter: SweepFilter,
+    delay: DelayLine,
+    // Routing gains for the current block, copied from the controls; `custom` is false
+    // while the default mapping applies
+    route: [[f32; MAX_ROUTE_CHANNELS]; MAX_ROUTE_CHANNELS],
+    custom: bool,
+}
+
+impl EngineInput {
+    pub fn new(consumer: HeapConsumer<f32>, channels: usize, sample_rate: u32) -> Self {
+        let channels = channels.max(1);
+        Self {
+            consumer,
+            channels,
+            sample_rate,
+            block: Vec::new(),
+            gate: NoiseGate::new(sample_rate, channels),
+            eq: ThreeBandEq::new(sample_rate, channels),
+            filter: SweepFilter::new(sample_rate, channels),
+            delay: DelayLine::new((MAX_INPUT_DELAY_MS / 1000.0 * sample_rate as f32) as usize, channels),
+            route: [[0.0; MAX_ROUTE_CHANNELS]; MAX_ROUTE_CHANNELS],
+            custom: false,
+        }
+    }
+
+    /// Add `frames` frames of the current block to `out`, scaled by `gain` (called once per
+    /// frame, in order).
+    fn mix_into(&self, out: &mut [f32], out_channels: usize, frames: usize, mut gain: impl FnMut(usize) -> f32) {
+        let in_channels = self.channels;
+        for f in 0..frames {
+            let frame = &self.block[f * in_channels..(f + 1) * in_channels];
+            let gain = gain(f);
+            let out_frame = &mut out[f * out_channels..(f + 1) * out_channels];
+            if self.custom {
+                // Channels beyond the matrix stay silent
+                for (in_ch, &x) in frame.iter().enumerate().take(MAX_ROUTE_CHANNELS) {
+                    let x = gain * x;
+                    for (o, &g) in out_frame.iter_mut().zip(self.route[in_ch].iter()) {
+                        *o += g * x;
+                    }
+                }
+            } else {
+                for (ch, o) in out_frame.iter_mut().enumerate() {
+                    *o += gain * if ch < in_channels { frame[ch] } else { frame[0] };
+                }
+            }
+        }
+    }
+}
+
+/// What a render thread/callback pulls its device blocks from.
+pub trait RenderSource: Send {
+    /// The next `frames` frames, interleaved with the device's channel count. Values are not
+    /// clamped; integer render paths clamp while converting.
+    fn render(&mut self, frames: usize) -> &[f32];
+}
+
+/// Upper bound of an output's latency compensation.
+pub const MAX_OUTPUT_DELAY_MS: f32 = 500.0;
+// Volume changes are smoothed over this long
+const VOLUME_SMOOTHING_SECONDS: f32 = 0.02;
+// An extra output lets its feed fall at most this far behind before catching up (device
+// clocks drift apart and nothing is resampled)
+const MAX_FEED_BACKLOG_MS: usize = 40;
+
+/// Which settings an `OutputStage` follows.
+#[derive(Debug, Clone, Copy)]
+pub enum OutputParams {
+    /// `MixControls::outputs` entry of an output playing the main mix.
+    Output(usize),
+    /// Output of a cue bus (`MixControls::cue_buses`).
+    CueBus(usize),
+}
+
+impl OutputParams {
+    /// Name for messages, e.g. "Output 2" or "Cue bus 1".
+    pub fn label(&self) -> String {
+        match *self {
+            OutputParams::Output(slot) => format!("Output {}", slot + 1),
+            OutputParams::CueBus(bus) => format!("Cue bus {}", bus + 1),
+        }
+    }
+}
+
+/// Last stage of one output: volume, latency compensation and placement on the device's
+/// channels. Device channels beyond the mix (or the destination range) repeat mix channel 0.
+pub struct OutputStage {
+    params: OutputParams,
+    mix_channels: usize,
+    device_channels: usize,
+    destination: Option<ChannelRange>,
+    sample_rate: u32,
+    volume: Smoother,
+    // Ring of past mix frames, long enough for MAX_OUTPUT_DELAY_MS
+    delay: Vec<f32>,
+    delay_frames: usize,
+    delay_pos: usize,
+    block: Vec<f32>,
+}
+
+impl OutputStage {
+    /// With `destination` only the range's channels are written and the rest of each frame
+    /// is silence; the range must fit the device (see `ChannelRange::validate`).
+    pub fn new(params: OutputParams, mix_channels: usize, device_channels: usize, destination: Option<ChannelRange>, sample_rate: u32) -> Self {
+        let mix_channels = mix_channels.max(1);
+        let delay_frames = (MAX_OUTPUT_DELAY_MS / 1000.0 * sample_rate as f32) as usize + 1;
+        Self {
+            params,
+            mix_channels,
+            device_channels: device_channels.max(1),
+            destination,
+            sample_rate,
+            volume: Smoother::new(1.0, VOLUME_SMOOTHING_SECONDS, sample_rate),
+            delay: vec![0.0; delay_frames * mix_channels],
+            delay_frames,
+            delay_pos: 0,
+            block: Vec::new(),
+        }
+    }
+
+    /// Channels of the mix this output expects.
+    pub fn mix_channels(&self) -> usize {
+        self.mix_channels
+    }
+
+    fn process(&mut self, mix: &[f32], frames: usize, controls: &MixControls) -> &[f32] {
+        let params = match self.params {
+            OutputParams::Output(slot) => &controls.outputs[slot],
+            OutputParams::CueBus(bus) => &controls.cue_buses[bus].output,
+        };
+        let gain = 10f32.powf(params.volume_db.load() / 20.0);
+        let delay_ms = params.delay_ms.load().clamp(0.0, MAX_OUTPUT_DELAY_MS);
+        let delay = ((delay_ms / 1000.0 * self.sample_rate as f32) as usize).min(self.delay_frames - 1);
+
+        let (mix_channels, device_channels) = (self.mix_channels, self.device_channels);
+        let total = frames * device_channels;
+        if self.block.len() < total { self.block.resize(total, 0.0); }
+        let (first, end) = match self.destination {
+            Some(range) => (range.first as usize, range.end()),
+            None => (0, device_channels),
+        };
+
+        for (f, out) in self.block[..total].chunks_exact_mut(device_channels).enumerate() {
+            let write = self.delay_pos * mix_channels;
+            self.delay[write..write + mix_channels].copy_from_slice(&mix[f * mix_channels..(f + 1) * mix_channels]);
+            let read = (self.delay_pos + self.delay_frames - delay) % self.delay_frames * mix_channels;
+            self.delay_pos = (self.delay_pos + 1) % self.delay_frames;
+
+            let g = self.volume.next(gain);
+            let frame = &self.delay[read..read + mix_channels];
+            out.fill(0.0);
+            for (i, o) in out[first..end].iter_mut().enumerate() {
+                *o = g * if i < mix_channels { frame[i] } else { frame[0] };
+            }
+        }
+        &self.block[..total]
+    }
+}
+
+/// Render side of an extra output or a cue bus: plays a mix the engine produced for it
+/// through its own stage.
+pub struct OutputFeed {
+    controls: Arc<MixControls>,
+    consumer: HeapConsumer<f32>,
+    stage: OutputStage,
+    block: Vec<f32>,
+    max_backlog: usize,
+}
+
+impl OutputFeed {
+    /// `consumer` receives the mix from `MixEngine::with_feeds` or a `CueBusMix`.
+    pub fn new(controls: Arc<MixControls>, consumer: HeapConsumer<f32>, stage: OutputStage) -> Self {
+        let max_backlog = stage.sample_rate as usize * MAX_FEED_BACKLOG_MS / 1000 * stage.mix_channels;
+        Self { controls, consumer, stage, block: Vec::new(), max_backlog }
+    }
+}
+
+impl RenderSource for OutputFeed {
+    fn render(&mut self, frames: usize) -> &[f32] {
+        let mix_channels = self.stage.mix_channels;
+        let total = frames * mix_channels;
+        if self.block.len() < total { self.block.resize(total, 0.0); }
+
+        // Catch up (whole frames) if this device consumes slower than the main output
+        let backlog = self.consumer.len();
+        if backlog > total + self.max_backlog {
+            let excess = backlog - total - self.max_backlog / 2;
+            self.consumer.skip(excess - excess % mix_channels);
+        }
+        let block = &mut self.block[..total];
+        let popped = self.consumer.pop_slice(block);
+        for s in block[popped..].iter_mut() { *s = 0.0; }
+
+        self.stage.process(&self.block[..total], frames, &self.controls)
+    }
+}
+
+/// Engine side of a cue bus: mixes the processed inputs with the bus's own listen toggles
+/// and gains, and hands the result to the bus's `OutputFeed`.
+pub struct CueBusMix {
+    bus: usize,
+    channels: usize,
+    producer: HeapProducer<f32>,
+    gains: [Smoother; 2],
+    block: Vec<f32>,
+}
+
+impl CueBusMix {
+    /// `channels` is the width of the bus mix (its `OutputStage`'s `mix_channels`).
+    pub fn new(bus: usize, channels: usize, producer: HeapProducer<f32>, sample_rate: u32) -> Self {
+        Self {
+            bus,
+            channels: channels.max(1),
+            producer,
+            gains: [Smoother::new(1.0, VOLUME_SMOOTHING_SECONDS, sample_rate); 2],
+            block: Vec::new(),
+        }
+    }
+}
+
+pub struct MixEngine {
+    controls: Arc<MixControls>,
+    // Slot 0 is input A, slot 1 is input B; `None` when nothing is selected
+    inputs: Vec<Option<EngineInput>>,
+    out_channels: usize,
+    block: Vec<f32>,
+    // The main output, and copies of the mix for extra outputs
+    output: OutputStage,
+    feeds: Vec<HeapProducer<f32>>,
+    cue_buses: Vec<CueBusMix>,
+    pads: PadVoices,
+    click: Click,
+    ducker: Ducker,
+    // Per-frame talkover gain for the current block
+    duck_gains: Vec<f32>,
+    // Largest block the output hands over, which every buffer is sized for up front
+    max_frames: usize,
+}
+
+impl MixEngine {
+    /// The mix has `output.mix_channels()` channels and goes out through `output`, which asks
+    /// for at most `max_frames` frames at a time; the render path doesn't allocate up to that.
+    pub fn new(controls: Arc<MixControls>, mut inputs: Vec<Option<EngineInput>>, output: OutputStage, sample_rate: u32, max_frames: usize) -> Self {
+        for input in inputs.iter_mut().flatten() {
+            input.block = vec![0.0; max_frames * input.channels];
+        }
+        Self {
+            controls,
+            inputs,
+            out_channels: output.mix_channels(),
+            block: vec![0.0; max_frames * output.mix_channels()],
+            output,
+            feeds: Vec::new(),
+            cue_buses: Vec::new(),
+            pads: PadVoices::new(sample_rate, max_frames),
+            click: Click::new(sample_rate, max_frames),
+            ducker: Ducker::new(sample_rate),
+            duck_gains: vec![1.0; max_frames],
+            max_frames,
+        }
+    }
+
+    /// Also push every mixed block to these rings, one per extra output (see `OutputFeed`).
+    pub fn with_feeds(mut self, feeds: Vec<HeapProducer<f32>>) -> Self {
+        self.feeds = feeds;
+        self
+    }
+
+    /// Also mix these cue buses from the same inputs.
+    pub fn with_cue_buses(mut self, mut cue_buses: Vec<CueBusMix>) -> Self {
+        for bus in cue_buses.iter_mut() {
+            bus.block = vec![0.0; self.max_frames * bus.channels];
+        }
+        self.cue_buses = cue_buses;
+        self
+    }
+}
+
+impl RenderSource for MixEngine {
+    /// Mix the next `frames` frames for the main output, feeding the extra outputs on the way.
+    fn render(&mut self, frames: usize) -> &[f32] {
+        let out_channels = self.out_channels;
+        let total = frames * out_channels;
+        // Sized for the device's largest block in `new`; only grows if a device hands over
+        // more than it announced
+        if self.block.len() < total { self.block.resize(total, 0.0); }
+        if self.duck_gains.len() < frames { self.duck_gains.resize(frames, 1.0); }
+        let out = &mut self.block[..total];
+        for s in out.iter_mut() { *s = 0.0; }
+
+        // Pull and process every input first: talkover needs the microphone's block before
+        // anything is mixed
+        for (idx, slot) in self.inputs.iter_mut().enumerate() {
+            let meter = self.controls.meters.input(idx);
+            let input = match slot {
+                Some(input) => input,
+                None => { meter.clear(); continue; }
+            };
+
+            // Always drain the ring so a muted input doesn't fall behind
+            let in_channels = input.channels;
+            let in_total = frames * in_channels;
+            if input.block.len() < in_total { input.block.resize(in_total, 0.0); }
+            let block = &mut input.block[..in_total];
+            let waiting = input.consumer.len();
+            let popped = input.consumer.pop_slice(block);
+            for s in block[popped..].iter_mut() { *s = 0.0; }
+
+            // Processing runs whether or not the input is heard, so toggling LISTEN never
+            // catches a filter mid-transient
+            let params = &self.controls.inputs[idx];
+            input.gate.process(block, params);
+            input.eq.process(block, params);
+            input.filter.process(block, params);
+            let delay_ms = params.delay_ms.load().clamp(0.0, MAX_INPUT_DELAY_MS);
+            input.delay.process(block, (delay_ms / 1000.0 * input.sample_rate as f32) as usize);
+            params.queue_ms.store(waiting as f32 / in_channels as f32 / input.sample_rate as f32 * 1000.0);
+            meter.update(block, in_channels);
+            input.custom = params.route.snapshot(&mut input.route);
+        }
+
+        let talkover = &self.controls.talkover;
+        let key_slot = talkover.source.load(Ordering::Relaxed);
+        let key = self.inputs.get(key_slot).and_then(|s| s.as_ref()).map(|i| (&i.block[..frames * i.channels], i.channels));
+        let duck_gains = &mut self.duck_gains[..frames];
+        self.ducker.process(key, duck_gains, talkover);
+
+        for (idx, slot) in self.inputs.iter_mut().enumerate() {
+            let input = match slot {
+                Some(input) if self.controls.listen(idx).load(Ordering::Relaxed) => input,
+                _ => continue,
+            };
+            if idx != key_slot {
+                input.mix_into(out, out_channels, frames, |f| duck_gains[f]);
+            } else {
+                input.mix_into(out, out_channels, frames, |_| 1.0);
+            }
+        }
+
+        // Sample pads go on top of the mix, not ducked by talkover
+        let pad_block = self.pads.render(frames, &self.controls.pads);
+        if let Some(block) = pad_block {
+            if self.controls.pads.listen.load(Ordering::Relaxed) {
+                pads::mix_stereo(block, out, out_channels);
+            }
+        }
+        let click = &self.controls.click;
+        let click_block = self.click.render(frames, click, click.heard(&self.controls.cue_buses));
+        if let Some(block) = click_block {
+            if click.listen.load(Ordering::Relaxed) {
+                pads::mix_stereo(block, out, out_channels);
+            }
+        }
+
+        // Cue buses hear the inputs without talkover: ducking is for the main mix
+        for bus in self.cue_buses.iter_mut() {
+            let params = &self.controls.cue_buses[bus.bus];
+            let channels = bus.channels;
+            let bus_total = frames * channels;
+            if bus.block.len() < bus_total { bus.block.resize(bus_total, 0.0); }
+            let bus_out = &mut bus.block[..bus_total];
+            for s in bus_out.iter_mut() { *s = 0.0; }
+            for (idx, slot) in self.inputs.iter().enumerate() {
+                let target = 10f32.powf(params.gain_db[idx].load() / 20.0);
+                let smoother = &mut bus.gains[idx];
+                match slot {
+                    Some(input) if params.listen[idx].load(Ordering::Relaxed) => {
+                        input.mix_into(bus_out, channels, frames, |_| smoother.next(target));
+                    }
+                    _ => { smoother.next(target); }
+                }
+            }
+            if let Some(block) = pad_block {
+                if params.listen_pads.load(Ordering::Relaxed) {
+                    pads::mix_stereo(block, bus_out, channels);
+                }
+            }
+            if let Some(block) = click_block {
+                if params.listen_click.load(Ordering::Relaxed) {
+                    pads::mix_stereo(block, bus_out, channels);
+                }
+            }
+            bus.producer.push_slice(bus_out);
+        }
+
+        self.controls.probe.render(out, out_channels);
+        self.controls.meters.output.update(out, out_channels);
+        self.controls.output_tap.push(out);
+        for feed in self.feeds.iter_mut() {
+            feed.push_slice(out);
+        }
+
+        self.output.process(&self.block[..total], frames, &self.controls)
+    }
+}
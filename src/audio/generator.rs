@@ -0,0 +1,168 @@
+// Built-in test signals that can feed an input slot instead of a device, for checking
+// routing, levels and speakers without a source at hand. Output is always stereo.
+
+use crate::audio::controls::GeneratorControls;
+use std::f32::consts::TAU;
+use std::sync::atomic::Ordering;
+
+/// Channels every generator produces.
+pub const GENERATOR_CHANNELS: usize = 2;
+pub const MIN_GENERATOR_HZ: f32 = 20.0;
+pub const MAX_GENERATOR_HZ: f32 = 20_000.0;
+pub const MIN_GENERATOR_LEVEL_DB: f32 = -60.0;
+pub const MAX_SWEEP_SECONDS: f32 = 60.0;
+
+// Channel identification: one beep on the left, then two on the right, every cycle
+const ID_CYCLE_SECONDS: f32 = 2.0;
+const ID_BEEP_SECONDS: f32 = 0.25;
+const ID_BEEPS: [(usize, f32); 3] = [(0, 0.0), (1, 0.6), (1, 0.95)];
+// Fade in/out of each beep, so they don't click
+const ID_RAMP_SECONDS: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorKind {
+    Sine,
+    WhiteNoise,
+    PinkNoise,
+    /// Logarithmic sweep from the start to the end frequency, repeating.
+    Sweep,
+    /// Beeps at the generator frequency: one on the left, then two on the right.
+    ChannelId,
+}
+
+impl GeneratorKind {
+    pub const ALL: [GeneratorKind; 5] = [Self::Sine, Self::WhiteNoise, Self::PinkNoise, Self::Sweep, Self::ChannelId];
+
+    pub fn from_index(index: usize) -> Self {
+        Self::ALL.get(index).copied().unwrap_or(Self::Sine)
+    }
+
+    pub fn index(self) -> usize {
+        Self::ALL.iter().position(|&k| k == self).unwrap_or(0)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Sine => "Sine",
+            Self::WhiteNoise => "White noise",
+            Self::PinkNoise => "Pink noise",
+            Self::Sweep => "Sweep",
+            Self::ChannelId => "L/R identification",
+        }
+    }
+
+    /// Name used by the config file and the API.
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Sine => "sine",
+            Self::WhiteNoise => "white",
+            Self::PinkNoise => "pink",
+            Self::Sweep => "sweep",
+            Self::ChannelId => "channel_id",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|k| k.key() == key)
+    }
+}
+
+pub struct SignalGenerator {
+    sample_rate: f32,
+    // Oscillator phase in cycles (0..1)
+    phase: f32,
+    // Seconds into the current sweep or identification cycle
+    time: f32,
+    rng: u32,
+    // Paul Kellet's pink noise filter state, per channel
+    pink: [[f32; 7]; GENERATOR_CHANNELS],
+}
+
+impl SignalGenerator {
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate: sample_rate.max(1) as f32, phase: 0.0, time: 0.0, rng: 0x2545_f491, pink: [[0.0; 7]; GENERATOR_CHANNELS] }
+    }
+
+    /// Fill an interleaved stereo block with the signal `controls` currently describe.
+    /// The level is the peak of tones; noise peaks at about the same level.
+    pub fn fill(&mut self, block: &mut [f32], controls: &GeneratorControls) {
+        let kind = GeneratorKind::from_index(controls.kind.load(Ordering::Relaxed));
+        let amp = 10f32.powf(controls.level_db.load().min(0.0) / 20.0);
+        let frequency = controls.frequency.load().clamp(MIN_GENERATOR_HZ, MAX_GENERATOR_HZ);
+        let dt = 1.0 / self.sample_rate;
+        for frame in block.chunks_exact_mut(GENERATOR_CHANNELS) {
+            match kind {
+                GeneratorKind::Sine => frame.fill(amp * self.oscillate(frequency)),
+                GeneratorKind::WhiteNoise => {
+                    for s in frame.iter_mut() {
+                        *s = amp * self.white();
+                    }
+                }
+                GeneratorKind::PinkNoise => {
+                    for (ch, s) in frame.iter_mut().enumerate() {
+                        let white = self.white();
+                        *s = amp * self.pink(ch, white);
+                    }
+                }
+                GeneratorKind::Sweep => {
+                    let start = controls.sweep_start.load().clamp(MIN_GENERATOR_HZ, MAX_GENERATOR_HZ);
+                    let end = controls.sweep_end.load().clamp(MIN_GENERATOR_HZ, MAX_GENERATOR_HZ);
+                    let length = controls.sweep_seconds.load().clamp(0.1, MAX_SWEEP_SECONDS);
+                    if self.time >= length {
+                        self.time = 0.0;
+                    }
+                    let f = start * (end / start).powf(self.time / length);
+                    frame.fill(amp * self.oscillate(f));
+                    self.time += dt;
+                }
+                GeneratorKind::ChannelId => {
+                    if self.time >= ID_CYCLE_SECONDS {
+                        self.time = 0.0;
+                    }
+                    let tone = amp * self.oscillate(frequency);
+                    frame.fill(0.0);
+                    for &(ch, start) in &ID_BEEPS {
+                        frame[ch] += tone * envelope(self.time - start);
+                    }
+                    self.time += dt;
+                }
+            }
+        }
+    }
+
+    fn oscillate(&mut self, frequency: f32) -> f32 {
+        let value = (self.phase * TAU).sin();
+        self.phase = (self.phase + frequency / self.sample_rate).fract();
+        value
+    }
+
+    // Uniform in -1..1 (xorshift32)
+    fn white(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    fn pink(&mut self, ch: usize, white: f32) -> f32 {
+        let b = &mut self.pink[ch];
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let out = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        // Usual normalisation of this filter, peaking near full scale
+        out * 0.11
+    }
+}
+
+// Gain of a beep `t` seconds after it starts
+fn envelope(t: f32) -> f32 {
+    if !(0.0..ID_BEEP_SECONDS).contains(&t) {
+        return 0.0;
+    }
+    (t / ID_RAMP_SECONDS).min((ID_BEEP_SECONDS - t) / ID_RAMP_SECONDS).min(1.0)
+}
@@ -0,0 +1,106 @@
+// Input-to-output channel routing.
+//
+// Each input slot has a matrix of gains from its channels to the output channels. Until it
+// is edited the matrix follows the default mapping (input channel `ch` to output channel
+// `ch`, output channels beyond the input's repeat input channel 0), which is also what the
+// engine uses for channels outside the matrix.
+
+use crate::audio::controls::AtomicF32;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Largest input/output channel count the matrix can address.
+pub const MAX_ROUTE_CHANNELS: usize = 8;
+/// Highest crosspoint gain (linear, about +6 dB).
+pub const MAX_ROUTE_GAIN: f32 = 2.0;
+
+/// Gains of the default mapping.
+pub fn default_gain(in_ch: usize, out_ch: usize, in_channels: usize) -> f32 {
+    if in_ch == out_ch || (out_ch >= in_channels && in_ch == 0) { 1.0 } else { 0.0 }
+}
+
+pub struct RouteMatrix {
+    custom: AtomicBool,
+    // [input channel][output channel]
+    gains: [[AtomicF32; MAX_ROUTE_CHANNELS]; MAX_ROUTE_CHANNELS],
+}
+
+impl RouteMatrix {
+    pub fn new() -> Self {
+        Self {
+            custom: AtomicBool::new(false),
+            gains: std::array::from_fn(|_| std::array::from_fn(|_| AtomicF32::new(0.0))),
+        }
+    }
+
+    /// False while the default mapping applies.
+    pub fn is_custom(&self) -> bool {
+        self.custom.load(Ordering::Relaxed)
+    }
+
+    /// Effective gain of one crosspoint for an input with `in_channels` channels.
+    pub fn gain(&self, in_ch: usize, out_ch: usize, in_channels: usize) -> f32 {
+        if !self.is_custom() || in_ch >= MAX_ROUTE_CHANNELS || out_ch >= MAX_ROUTE_CHANNELS {
+            return default_gain(in_ch, out_ch, in_channels);
+        }
+        self.gains[in_ch][out_ch].load()
+    }
+
+    /// Set one crosspoint; the first edit starts from the default mapping.
+    pub fn set_gain(&self, in_ch: usize, out_ch: usize, gain: f32, in_channels: usize) {
+        if in_ch >= MAX_ROUTE_CHANNELS || out_ch >= MAX_ROUTE_CHANNELS {
+            return;
+        }
+        if !self.is_custom() {
+            for (i, row) in self.gains.iter().enumerate() {
+                for (o, g) in row.iter().enumerate() {
+                    g.store(default_gain(i, o, in_channels));
+                }
+            }
+            self.custom.store(true, Ordering::Relaxed);
+        }
+        self.gains[in_ch][out_ch].store(gain.clamp(0.0, MAX_ROUTE_GAIN));
+    }
+
+    /// Back to the default mapping.
+    pub fn reset(&self) {
+        self.custom.store(false, Ordering::Relaxed);
+    }
+
+    /// Effective gains as rows of input channels, e.g. for the config file.
+    pub fn to_rows(&self, in_channels: usize, out_channels: usize) -> Vec<Vec<f32>> {
+        (0..in_channels).map(|i| (0..out_channels).map(|o| self.gain(i, o, in_channels)).collect()).collect()
+    }
+
+    /// Replace the matrix with `rows` (one row per input channel, one gain per output
+    /// channel); crosspoints not listed are off.
+    pub fn load_rows(&self, rows: &[Vec<f32>]) -> Result<(), String> {
+        if rows.len() > MAX_ROUTE_CHANNELS || rows.iter().any(|r| r.len() > MAX_ROUTE_CHANNELS) {
+            return Err(format!("Routing matrix is limited to {} x {} channels", MAX_ROUTE_CHANNELS, MAX_ROUTE_CHANNELS));
+        }
+        if rows.iter().flatten().any(|g| !g.is_finite() || *g < 0.0) {
+            return Err("Routing gains must be non-negative numbers".into());
+        }
+        for (i, row) in self.gains.iter().enumerate() {
+            for (o, g) in row.iter().enumerate() {
+                let value = rows.get(i).and_then(|r| r.get(o)).copied().unwrap_or(0.0);
+                g.store(value.min(MAX_ROUTE_GAIN));
+            }
+        }
+        self.custom.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Copy the current gains for the render path; false (and `out` untouched) while the
+    /// default mapping applies.
+    pub fn snapshot(&self, out: &mut [[f32; MAX_ROUTE_CHANNELS]; MAX_ROUTE_CHANNELS]) -> bool {
+        if !self.is_custom() {
+            return false;
+        }
+        for (row, gains) in out.iter_mut().zip(self.gains.iter()) {
+            for (g, gain) in row.iter_mut().zip(gains.iter()) {
+                *g = gain.load();
+            }
+        }
+        true
+    }
+}
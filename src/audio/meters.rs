@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+/// Channels metered individually; further channels only count towards the overall peak.
+pub const MAX_METER_CHANNELS: usize = 8;
+
+/// Levels of one signal, written by an audio thread once per block and read by UI/API threads.
+/// f32 values are stored as raw bits so no locking is needed on the audio side.
+pub struct LevelMeter {
+    // Overall block peak across all channels
+    peak: AtomicU32,
+    channels: AtomicUsize,
+    // Per channel: block peak, block RMS, and the highest peak since the UI last looked
+    channel_peak: [AtomicU32; MAX_METER_CHANNELS],
+    channel_rms: [AtomicU32; MAX_METER_CHANNELS],
+    peak_since_take: [AtomicU32; MAX_METER_CHANNELS],
+    // Latched when a sample reaches full scale, until `clear_clip`
+    clipped: AtomicBool,
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        Self {
+            peak: AtomicU32::new(0.0f32.to_bits()),
+            channels: AtomicUsize::new(0),
+            channel_peak: Default::default(),
+            channel_rms: Default::default(),
+            peak_since_take: Default::default(),
+            clipped: AtomicBool::new(false),
+        }
+    }
+
+    /// Real-time side: measure one interleaved block.
+    pub fn update(&self, block: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        let metered = channels.min(MAX_METER_CHANNELS);
+        let mut peak = [0.0f32; MAX_METER_CHANNELS];
+        let mut sum_sq = [0.0f32; MAX_METER_CHANNELS];
+        let mut overall = 0.0f32;
+        for frame in block.chunks_exact(channels) {
+            for (ch, &s) in frame.iter().enumerate() {
+                let a = s.abs();
+                overall = overall.max(a);
+                if ch < metered {
+                    peak[ch] = peak[ch].max(a);
+                    sum_sq[ch] += s * s;
+                }
+            }
+        }
+
+        let frames = (block.len() / channels).max(1) as f32;
+        for ch in 0..metered {
+            self.channel_peak[ch].store(peak[ch].to_bits(), Ordering::Relaxed);
+            self.channel_rms[ch].store((sum_sq[ch] / frames).sqrt().to_bits(), Ordering::Relaxed);
+            // Non-negative floats order the same as their bit patterns
+            self.peak_since_take[ch].fetch_max(peak[ch].to_bits(), Ordering::Relaxed);
+        }
+        self.channels.store(metered, Ordering::Relaxed);
+        self.peak.store(overall.to_bits(), Ordering::Relaxed);
+        if overall >= 1.0 {
+            self.clipped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Mark the signal as absent (slot not in use).
+    pub fn clear(&self) {
+        self.channels.store(0, Ordering::Relaxed);
+        self.peak.store(0.0f32.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Absolute, linear peak of the most recent block.
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    /// Peak in dBFS, floored at -120 dB so silence stays a finite number.
+    pub fn peak_db(&self) -> f32 {
+        linear_to_db(self.peak())
+    }
+
+    /// Number of individually metered channels (0 while nothing is running).
+    pub fn channels(&self) -> usize {
+        self.channels.load(Ordering::Relaxed)
+    }
+
+    pub fn channel_peak(&self, ch: usize) -> f32 {
+        f32::from_bits(self.channel_peak[ch].load(Ordering::Relaxed))
+    }
+
+    pub fn channel_rms(&self, ch: usize) -> f32 {
+        f32::from_bits(self.channel_rms[ch].load(Ordering::Relaxed))
+    }
+
+    /// Highest peak on `ch` since the previous call, so a slow reader misses no transients.
+    /// Meant for a single reader (the UI).
+    pub fn take_peak(&self, ch: usize) -> f32 {
+        f32::from_bits(self.peak_since_take[ch].swap(0.0f32.to_bits(), Ordering::Relaxed))
+    }
+
+    pub fn clipped(&self) -> bool {
+        self.clipped.load(Ordering::Relaxed)
+    }
+
+    pub fn clear_clip(&self) {
+        self.clipped.store(false, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.clear();
+        for ch in 0..MAX_METER_CHANNELS {
+            self.channel_peak[ch].store(0.0f32.to_bits(), Ordering::Relaxed);
+            self.channel_rms[ch].store(0.0f32.to_bits(), Ordering::Relaxed);
+            self.peak_since_take[ch].store(0.0f32.to_bits(), Ordering::Relaxed);
+        }
+        self.clear_clip();
+    }
+}
+
+pub fn linear_to_db(value: f32) -> f32 {
+    20.0 * value.abs().max(1e-6).log10()
+}
+
+/// Levels for both inputs (pre-listen) and the mixed output.
+pub struct Meters {
+    pub input_a: LevelMeter,
+    pub input_b: LevelMeter,
+    pub output: LevelMeter,
+}
+
+impl Meters {
+    pub fn new() -> Self {
+        Self { input_a: LevelMeter::new(), input_b: LevelMeter::new(), output: LevelMeter::new() }
+    }
+
+    /// Input meter by slot (0 = A, 1 = B).
+    pub fn input(&self, idx: usize) -> &LevelMeter {
+        if idx == 0 { &self.input_a } else { &self.input_b }
+    }
+
+    pub fn reset(&self) {
+        self.input_a.reset();
+        self.input_b.reset();
+        self.output.reset();
+    }
+}
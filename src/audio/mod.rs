@@ -1,7 +1,22 @@
 pub mod backend;
+pub mod click;
+pub mod controls;
 pub mod cpal_backend;
+pub mod engine;
+pub mod generator;
+pub mod matrix;
+pub mod pads;
+pub mod meters;
+pub mod params;
+pub mod player;
+pub mod probe;
+pub mod source;
+pub mod tap;
 pub mod wasapi_backend;
 
 pub use backend::*;
 pub use cpal_backend::CpalBackend;
 pub use wasapi_backend::WasapiBackend;
+
+/// Backend used by the GUI, headless mode and the remote API.
+pub type ActiveBackend = WasapiBackend;
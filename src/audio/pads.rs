@@ -0,0 +1,272 @@
+// Sample pads: a small bank of one-shots (airhorns, station IDs, count-ins) decoded into
+// memory and triggered from hotkeys, the API or parameters (`pad1` ..).
+//
+// The render path starts voices from a fixed pool, so triggering never allocates. Voices
+// hold the sample through an `Arc`; a sample replaced while a voice still plays it is parked
+// in `PadBank::retired` until the voice lets go, so it is never freed on the render thread.
+
+use crate::audio::controls::AtomicF32;
+use crate::audio::player;
+use crate::audio::source::SOURCE_CHANNELS;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+pub const MAX_PADS: usize = 8;
+/// Voices shared by all pads; when all are busy the oldest one is cut.
+pub const MAX_VOICES: usize = 16;
+pub const MAX_POLYPHONY: usize = 8;
+/// Choke groups are numbered from 1; a pad in group 0 chokes nothing.
+pub const CHOKE_GROUPS: usize = 4;
+/// Longest sample a pad takes.
+pub const MAX_PAD_SECONDS: f64 = 30.0;
+// Fade applied to a voice cut by its choke group or polyphony limit, so it doesn't click
+const RELEASE_SECONDS: f32 = 0.005;
+
+/// Decoded sample: interleaved stereo at its own rate.
+pub struct PadSample {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl PadSample {
+    pub fn seconds(&self) -> f32 {
+        (self.samples.len() / SOURCE_CHANNELS) as f32 / self.sample_rate.max(1) as f32
+    }
+}
+
+pub struct Pad {
+    sample: Mutex<Option<Arc<PadSample>>>,
+    file: Mutex<Option<PathBuf>>,
+    // Length of the loaded sample in seconds (0 while empty), so the UI never locks `sample`
+    length: AtomicF32,
+    pub gain_db: AtomicF32,
+    /// Choke group (0 = none): triggering a pad cuts every voice of its group.
+    pub choke: AtomicUsize,
+    /// Voices of this pad that may play at once; the oldest is cut beyond that.
+    pub polyphony: AtomicUsize,
+    // Triggers not yet picked up by the render path
+    triggers: AtomicUsize,
+    /// Voices playing this pad, as counted by the render path.
+    pub active: AtomicUsize,
+}
+
+impl Pad {
+    fn new() -> Self {
+        Self {
+            sample: Mutex::new(None),
+            file: Mutex::new(None),
+            length: AtomicF32::new(0.0),
+            gain_db: AtomicF32::new(0.0),
+            choke: AtomicUsize::new(0),
+            polyphony: AtomicUsize::new(1),
+            triggers: AtomicUsize::new(0),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.triggers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn file(&self) -> Option<PathBuf> {
+        self.file.lock().unwrap().clone()
+    }
+
+    /// Length of the loaded sample in seconds.
+    pub fn seconds(&self) -> Option<f32> {
+        Some(self.length.load()).filter(|&s| s > 0.0)
+    }
+}
+
+pub struct PadBank {
+    pub pads: [Pad; MAX_PADS],
+    /// Pads are heard on the main mix (cue buses have their own `listen_pads`).
+    pub listen: AtomicBool,
+    retired: Mutex<Vec<Arc<PadSample>>>,
+}
+
+impl PadBank {
+    pub fn new() -> Self {
+        Self { pads: std::array::from_fn(|_| Pad::new()), listen: AtomicBool::new(true), retired: Mutex::new(Vec::new()) }
+    }
+
+    /// Decode `path` into pad `idx`; `None` empties the pad.
+    pub fn load(&self, idx: usize, path: Option<&Path>) -> Result<(), String> {
+        let pad = self.pads.get(idx).ok_or_else(|| format!("no pad {}", idx + 1))?;
+        let sample = match path {
+            Some(path) => {
+                let (samples, sample_rate) = player::decode_file(path, MAX_PAD_SECONDS)?;
+                if samples.is_empty() {
+                    return Err(format!("No audio in {}", path.display()));
+                }
+                Some(Arc::new(PadSample { samples, sample_rate }))
+            }
+            None => None,
+        };
+        pad.length.store(sample.as_ref().map_or(0.0, |s| s.seconds()));
+        let old = std::mem::replace(&mut *pad.sample.lock().unwrap(), sample);
+        *pad.file.lock().unwrap() = path.map(Path::to_path_buf);
+
+        let mut retired = self.retired.lock().unwrap();
+        retired.extend(old);
+        // Only the list itself still holds these once their voices have finished
+        retired.retain(|s| Arc::strong_count(s) > 1);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Voice {
+    sample: Option<Arc<PadSample>>,
+    pad: usize,
+    // Read position in frames of the sample, fractional while resampling
+    pos: f64,
+    // Order voices were started in, to find the oldest
+    started: u64,
+    // Frames left of a release fade, when the voice is being cut
+    release: Option<usize>,
+}
+
+/// Render side of the pads: a fixed voice pool mixed into a stereo block.
+pub struct PadVoices {
+    sample_rate: u32,
+    voices: [Voice; MAX_VOICES],
+    counter: u64,
+    release_frames: usize,
+    block: Vec<f32>,
+}
+
+impl PadVoices {
+    /// `max_frames` is the largest block `render` will be asked for.
+    pub fn new(sample_rate: u32, max_frames: usize) -> Self {
+        Self {
+            sample_rate: sample_rate.max(1),
+            voices: Default::default(),
+            counter: 0,
+            release_frames: ((RELEASE_SECONDS * sample_rate as f32) as usize).max(1),
+            block: vec![0.0; max_frames * SOURCE_CHANNELS],
+        }
+    }
+
+    /// Start triggered pads and render `frames` stereo frames of every playing voice; `None`
+    /// when nothing plays.
+    pub fn render(&mut self, frames: usize, bank: &PadBank) -> Option<&[f32]> {
+        for (idx, pad) in bank.pads.iter().enumerate() {
+            let triggers = pad.triggers.swap(0, Ordering::Relaxed).min(MAX_POLYPHONY);
+            for _ in 0..triggers {
+                self.start(idx, bank);
+            }
+        }
+
+        let mut counts = [0usize; MAX_PADS];
+        let mut playing = false;
+        let total = frames * SOURCE_CHANNELS;
+        // Sized in `new`; only grows if a device hands over more than it announced
+        if self.block.len() < total { self.block.resize(total, 0.0); }
+        let out = &mut self.block[..total];
+        for s in out.iter_mut() { *s = 0.0; }
+
+        for voice in self.voices.iter_mut() {
+            let sample = match &voice.sample {
+                Some(sample) => sample,
+                None => continue,
+            };
+            let gain = 10f32.powf(bank.pads[voice.pad].gain_db.load() / 20.0);
+            let step = sample.sample_rate as f64 / self.sample_rate as f64;
+            let len = sample.samples.len() / SOURCE_CHANNELS;
+            let mut finished = false;
+            for frame in out.chunks_exact_mut(SOURCE_CHANNELS) {
+                let i = voice.pos as usize;
+                if i >= len {
+                    finished = true;
+                    break;
+                }
+                let fade = match voice.release.as_mut() {
+                    Some(0) => {
+                        finished = true;
+                        break;
+                    }
+                    Some(left) => {
+                        *left -= 1;
+                        *left as f32 / self.release_frames as f32
+                    }
+                    None => 1.0,
+                };
+                let t = (voice.pos - i as f64) as f32;
+                for (ch, o) in frame.iter_mut().enumerate() {
+                    let a = sample.samples[i * SOURCE_CHANNELS + ch];
+                    let b = if i + 1 < len { sample.samples[(i + 1) * SOURCE_CHANNELS + ch] } else { 0.0 };
+                    *o += (a + (b - a) * t) * gain * fade;
+                }
+                voice.pos += step;
+            }
+            if finished {
+                // The bank still holds the sample (or retired it), so this never frees it
+                voice.sample = None;
+            } else {
+                counts[voice.pad] += 1;
+                playing = true;
+            }
+        }
+        for (pad, count) in bank.pads.iter().zip(counts) {
+            pad.active.store(count, Ordering::Relaxed);
+        }
+        if playing { Some(&self.block[..total]) } else { None }
+    }
+
+    fn start(&mut self, idx: usize, bank: &PadBank) {
+        let pad = &bank.pads[idx];
+        let sample = match pad.sample.try_lock() {
+            Ok(sample) => match sample.as_ref() {
+                Some(sample) => sample.clone(),
+                None => return,
+            },
+            // Being replaced right now: try again next block rather than lose the trigger
+            Err(_) => {
+                pad.triggers.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        let group = pad.choke.load(Ordering::Relaxed);
+        if group != 0 {
+            for voice in self.voices.iter_mut().filter(|v| v.sample.is_some()) {
+                if bank.pads[voice.pad].choke.load(Ordering::Relaxed) == group {
+                    voice.release.get_or_insert(self.release_frames);
+                }
+            }
+        }
+        // Cut the oldest voices of this pad down to one below its limit
+        let limit = pad.polyphony.load(Ordering::Relaxed).clamp(1, MAX_POLYPHONY);
+        loop {
+            let sounding = self.voices.iter().filter(|v| v.sample.is_some() && v.pad == idx && v.release.is_none());
+            if sounding.clone().count() < limit {
+                break;
+            }
+            let oldest = sounding.map(|v| v.started).min();
+            if let Some(voice) = self.voices.iter_mut().find(|v| v.sample.is_some() && Some(v.started) == oldest) {
+                voice.release = Some(self.release_frames);
+            }
+        }
+
+        // A free voice, or else the oldest one
+        let slot = match self.voices.iter().position(|v| v.sample.is_none()) {
+            Some(slot) => slot,
+            None => (0..MAX_VOICES).min_by_key(|&i| self.voices[i].started).unwrap_or(0),
+        };
+        self.counter += 1;
+        // The Arc a stolen voice drops is still held by its pad or the retired list
+        self.voices[slot] = Voice { sample: Some(sample), pad: idx, pos: 0.0, started: self.counter, release: None };
+    }
+}
+
+/// Add a stereo block to an interleaved mix: channel 1 and 2 as they are, further channels
+/// repeat channel 1 (the default routing of a stereo input).
+pub fn mix_stereo(block: &[f32], out: &mut [f32], out_channels: usize) {
+    for (frame, out_frame) in block.chunks_exact(SOURCE_CHANNELS).zip(out.chunks_exact_mut(out_channels.max(1))) {
+        for (ch, o) in out_frame.iter_mut().enumerate() {
+            *o += frame[if ch < SOURCE_CHANNELS { ch } else { 0 }];
+        }
+    }
+}
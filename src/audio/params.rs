@@ -0,0 +1,262 @@
+// Mappable mixer parameters.
+//
+// Every knob or switch that a hotkey, the remote API or a MIDI controller may drive is
+// listed here under a stable id, with a normalised 0..1 value so a controller doesn't need
+// to know the unit:
+//
+//   a.eq.low, a.eq.mid, a.eq.high            band gain, 0 = kill .. 1 = +6 dB (0.8125 = 0 dB)
+//   a.eq.low.kill, a.eq.mid.kill, ...        kill switch (0/1, toggleable)
+//   a.filter                                 0 = low-pass closed .. 0.5 = off .. 1 = high-pass closed
+//   a.filter.res                             resonance 0..1
+//   a.gate                                   noise gate on/off (toggleable)
+//   a.gate.threshold                         0 = -80 dBFS .. 1 = 0 dBFS
+//   a.delay                                  alignment delay, 0 = none .. 1 = 1000 ms
+//
+// `b.` ids address input B the same way. Mix-wide parameters have no prefix:
+//
+//   talkover                                 talkover on/off (toggleable)
+//   talkover.threshold                       microphone threshold, 0 = -60 dBFS .. 1 = 0 dBFS
+//   talkover.depth                           0 = no ducking .. 1 = -40 dB
+//
+// Cue buses are numbered from 1 (`cue1.` is `MixControls::cue_buses[0]`):
+//
+//   cue1.listen.a, cue1.listen.b             listen toggle of an input on that bus (toggleable)
+//   cue1.gain.a, cue1.gain.b                 input gain on that bus, 0 = -60 dB .. 1 = +12 dB (0.8333 = 0 dB)
+//   cue1.listen.pads                         sample pads on that bus (toggleable)
+//   cue1.listen.click                        metronome click on that bus (toggleable)
+//
+// Sample pads are numbered from 1 as well:
+//
+//   pad1 .. pad8                             trigger (toggle, or any value from 0.5 up); reads 1 while playing
+//   pad1.gain                                pad gain, 0 = -60 dB .. 1 = +12 dB (0.8333 = 0 dB)
+//   pads.listen                              sample pads on the main mix (toggleable)
+//
+// The metronome click:
+//
+//   click.listen                             click on the main mix (toggleable)
+//   click.bpm                                tempo, 0 = 30 BPM .. 1 = 300 BPM
+//   click.level                              0 = -60 dBFS .. 1 = 0 dBFS
+
+use crate::audio::click::{MAX_BPM, MIN_BPM, MIN_CLICK_LEVEL_DB};
+use crate::audio::controls::{MixControls, MAX_CUE_BUSES, MAX_TRIM_DB, MIN_TRIM_DB};
+use crate::audio::engine::MAX_INPUT_DELAY_MS;
+use crate::audio::pads::MAX_PADS;
+use crate::dsp::eq::{self, Band};
+use crate::dsp::filter;
+use std::sync::atomic::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    EqGain(usize, Band),
+    EqKill(usize, Band),
+    Filter(usize),
+    FilterResonance(usize),
+    Gate(usize),
+    GateThreshold(usize),
+    Delay(usize),
+    Talkover,
+    TalkoverThreshold,
+    TalkoverDepth,
+    /// (bus, input slot)
+    CueListen(usize, usize),
+    CueGain(usize, usize),
+    /// (bus)
+    CuePads(usize),
+    PadTrigger(usize),
+    PadGain(usize),
+    PadsListen,
+    /// (bus)
+    CueClick(usize),
+    ClickListen,
+    ClickBpm,
+    ClickLevel,
+}
+
+const GATE_MIN_THRESHOLD_DB: f32 = -80.0;
+const TALKOVER_MIN_THRESHOLD_DB: f32 = -60.0;
+const TALKOVER_MAX_DEPTH_DB: f32 = -40.0;
+
+const SLOT_KEYS: [&str; 2] = ["a", "b"];
+
+impl Param {
+    /// Every parameter, in UI order.
+    pub fn all() -> Vec<Param> {
+        let mut params = Vec::new();
+        for slot in 0..SLOT_KEYS.len() {
+            params.extend(Band::ALL.map(|band| Param::EqGain(slot, band)));
+            params.extend(Band::ALL.map(|band| Param::EqKill(slot, band)));
+            params.push(Param::Filter(slot));
+            params.push(Param::FilterResonance(slot));
+            params.push(Param::Gate(slot));
+            params.push(Param::GateThreshold(slot));
+            params.push(Param::Delay(slot));
+        }
+        params.extend([Param::Talkover, Param::TalkoverThreshold, Param::TalkoverDepth]);
+        for bus in 0..MAX_CUE_BUSES {
+            params.extend((0..SLOT_KEYS.len()).map(|slot| Param::CueListen(bus, slot)));
+            params.extend((0..SLOT_KEYS.len()).map(|slot| Param::CueGain(bus, slot)));
+            params.push(Param::CuePads(bus));
+            params.push(Param::CueClick(bus));
+        }
+        for pad in 0..MAX_PADS {
+            params.extend([Param::PadTrigger(pad), Param::PadGain(pad)]);
+        }
+        params.push(Param::PadsListen);
+        params.extend([Param::ClickListen, Param::ClickBpm, Param::ClickLevel]);
+        params
+    }
+
+    pub fn id(&self) -> String {
+        match *self {
+            Param::EqGain(slot, band) => format!("{}.eq.{}", SLOT_KEYS[slot], band.key()),
+            Param::EqKill(slot, band) => format!("{}.eq.{}.kill", SLOT_KEYS[slot], band.key()),
+            Param::Filter(slot) => format!("{}.filter", SLOT_KEYS[slot]),
+            Param::FilterResonance(slot) => format!("{}.filter.res", SLOT_KEYS[slot]),
+            Param::Gate(slot) => format!("{}.gate", SLOT_KEYS[slot]),
+            Param::GateThreshold(slot) => format!("{}.gate.threshold", SLOT_KEYS[slot]),
+            Param::Delay(slot) => format!("{}.delay", SLOT_KEYS[slot]),
+            Param::Talkover => "talkover".to_string(),
+            Param::TalkoverThreshold => "talkover.threshold".to_string(),
+            Param::TalkoverDepth => "talkover.depth".to_string(),
+            Param::CueListen(bus, slot) => format!("cue{}.listen.{}", bus + 1, SLOT_KEYS[slot]),
+            Param::CueGain(bus, slot) => format!("cue{}.gain.{}", bus + 1, SLOT_KEYS[slot]),
+            Param::CuePads(bus) => format!("cue{}.listen.pads", bus + 1),
+            Param::PadTrigger(pad) => format!("pad{}", pad + 1),
+            Param::PadGain(pad) => format!("pad{}.gain", pad + 1),
+            Param::PadsListen => "pads.listen".to_string(),
+            Param::CueClick(bus) => format!("cue{}.listen.click", bus + 1),
+            Param::ClickListen => "click.listen".to_string(),
+            Param::ClickBpm => "click.bpm".to_string(),
+            Param::ClickLevel => "click.level".to_string(),
+        }
+    }
+
+    pub fn parse(id: &str) -> Option<Param> {
+        Param::all().into_iter().find(|p| p.id() == id)
+    }
+
+    /// Switches only take 0 or 1 and can be toggled.
+    pub fn is_switch(&self) -> bool {
+        matches!(self, Param::EqKill(..) | Param::Gate(..) | Param::Talkover | Param::CueListen(..) | Param::CuePads(..) | Param::PadTrigger(..) | Param::PadsListen | Param::CueClick(..) | Param::ClickListen)
+    }
+
+    /// Current value, normalised to 0..1.
+    pub fn get(&self, controls: &MixControls) -> f32 {
+        match *self {
+            Param::EqGain(slot, band) => {
+                let db = controls.inputs[slot].eq_gain_db[band as usize].load();
+                (db - eq::MIN_DB) / (eq::MAX_DB - eq::MIN_DB)
+            }
+            Param::EqKill(slot, band) => controls.inputs[slot].eq_kill[band as usize].load(Ordering::Relaxed) as u8 as f32,
+            Param::Filter(slot) => (controls.inputs[slot].filter.load() + 1.0) / 2.0,
+            Param::FilterResonance(slot) => controls.inputs[slot].filter_resonance.load(),
+            Param::Gate(slot) => controls.inputs[slot].gate_enabled.load(Ordering::Relaxed) as u8 as f32,
+            Param::GateThreshold(slot) => 1.0 - controls.inputs[slot].gate_threshold_db.load() / GATE_MIN_THRESHOLD_DB,
+            Param::Delay(slot) => controls.inputs[slot].delay_ms.load() / MAX_INPUT_DELAY_MS,
+            Param::Talkover => controls.talkover.enabled.load(Ordering::Relaxed) as u8 as f32,
+            Param::TalkoverThreshold => 1.0 - controls.talkover.threshold_db.load() / TALKOVER_MIN_THRESHOLD_DB,
+            Param::TalkoverDepth => controls.talkover.depth_db.load() / TALKOVER_MAX_DEPTH_DB,
+            Param::CueListen(bus, slot) => controls.cue_buses[bus].listen[slot].load(Ordering::Relaxed) as u8 as f32,
+            Param::CueGain(bus, slot) => (controls.cue_buses[bus].gain_db[slot].load() - MIN_TRIM_DB) / (MAX_TRIM_DB - MIN_TRIM_DB),
+            Param::CuePads(bus) => controls.cue_buses[bus].listen_pads.load(Ordering::Relaxed) as u8 as f32,
+            Param::PadTrigger(pad) => (controls.pads.pads[pad].active.load(Ordering::Relaxed) > 0) as u8 as f32,
+            Param::PadGain(pad) => (controls.pads.pads[pad].gain_db.load() - MIN_TRIM_DB) / (MAX_TRIM_DB - MIN_TRIM_DB),
+            Param::PadsListen => controls.pads.listen.load(Ordering::Relaxed) as u8 as f32,
+            Param::CueClick(bus) => controls.cue_buses[bus].listen_click.load(Ordering::Relaxed) as u8 as f32,
+            Param::ClickListen => controls.click.listen.load(Ordering::Relaxed) as u8 as f32,
+            Param::ClickBpm => (controls.click.bpm.load() - MIN_BPM) / (MAX_BPM - MIN_BPM),
+            Param::ClickLevel => 1.0 - controls.click.level_db.load() / MIN_CLICK_LEVEL_DB,
+        }
+    }
+
+    /// Set from a normalised value (clamped); switches turn on at 0.5 and above.
+    pub fn set(&self, controls: &MixControls, value: f32) {
+        let value = value.clamp(0.0, 1.0);
+        match *self {
+            Param::EqGain(slot, band) => {
+                controls.inputs[slot].eq_gain_db[band as usize].store(eq::MIN_DB + value * (eq::MAX_DB - eq::MIN_DB));
+            }
+            Param::EqKill(slot, band) => controls.inputs[slot].eq_kill[band as usize].store(value >= 0.5, Ordering::Relaxed),
+            Param::Filter(slot) => controls.inputs[slot].filter.store(value * 2.0 - 1.0),
+            Param::FilterResonance(slot) => controls.inputs[slot].filter_resonance.store(value),
+            Param::Gate(slot) => controls.inputs[slot].gate_enabled.store(value >= 0.5, Ordering::Relaxed),
+            Param::GateThreshold(slot) => controls.inputs[slot].gate_threshold_db.store((1.0 - value) * GATE_MIN_THRESHOLD_DB),
+            Param::Delay(slot) => controls.inputs[slot].delay_ms.store(value * MAX_INPUT_DELAY_MS),
+            Param::Talkover => controls.talkover.enabled.store(value >= 0.5, Ordering::Relaxed),
+            Param::TalkoverThreshold => controls.talkover.threshold_db.store((1.0 - value) * TALKOVER_MIN_THRESHOLD_DB),
+            Param::TalkoverDepth => controls.talkover.depth_db.store(value * TALKOVER_MAX_DEPTH_DB),
+            Param::CueListen(bus, slot) => controls.cue_buses[bus].listen[slot].store(value >= 0.5, Ordering::Relaxed),
+            Param::CueGain(bus, slot) => controls.cue_buses[bus].gain_db[slot].store(MIN_TRIM_DB + value * (MAX_TRIM_DB - MIN_TRIM_DB)),
+            Param::CuePads(bus) => controls.cue_buses[bus].listen_pads.store(value >= 0.5, Ordering::Relaxed),
+            // A trigger has no off state: a controller's note-off (0) does nothing
+            Param::PadTrigger(pad) => if value >= 0.5 { controls.pads.pads[pad].trigger() },
+            Param::PadGain(pad) => controls.pads.pads[pad].gain_db.store(MIN_TRIM_DB + value * (MAX_TRIM_DB - MIN_TRIM_DB)),
+            Param::PadsListen => controls.pads.listen.store(value >= 0.5, Ordering::Relaxed),
+            Param::CueClick(bus) => controls.cue_buses[bus].listen_click.store(value >= 0.5, Ordering::Relaxed),
+            Param::ClickListen => controls.click.listen.store(value >= 0.5, Ordering::Relaxed),
+            Param::ClickBpm => controls.click.bpm.store(MIN_BPM + value * (MAX_BPM - MIN_BPM)),
+            Param::ClickLevel => controls.click.level_db.store((1.0 - value) * MIN_CLICK_LEVEL_DB),
+        }
+    }
+
+    /// Flip a switch; EQ knobs jump between their neutral position and the bottom of the range,
+    /// the filter re-centres, cue and pad gains go back to 0 dB and pads fire.
+    pub fn toggle(&self, controls: &MixControls) {
+        match *self {
+            Param::EqGain(slot, band) => {
+                let gain = &controls.inputs[slot].eq_gain_db[band as usize];
+                gain.store(if gain.load() > eq::MIN_DB { eq::MIN_DB } else { 0.0 });
+            }
+            Param::EqKill(slot, band) => {
+                controls.inputs[slot].eq_kill[band as usize].fetch_xor(true, Ordering::Relaxed);
+            }
+            Param::Filter(slot) => controls.inputs[slot].filter.store(0.0),
+            Param::FilterResonance(slot) => controls.inputs[slot].filter_resonance.store(0.0),
+            Param::Gate(slot) => {
+                controls.inputs[slot].gate_enabled.fetch_xor(true, Ordering::Relaxed);
+            }
+            Param::Talkover => {
+                controls.talkover.enabled.fetch_xor(true, Ordering::Relaxed);
+            }
+            Param::CueListen(bus, slot) => controls.cue_buses[bus].toggle(slot),
+            Param::CueGain(bus, slot) => controls.cue_buses[bus].gain_db[slot].store(0.0),
+            Param::CuePads(bus) => {
+                controls.cue_buses[bus].listen_pads.fetch_xor(true, Ordering::Relaxed);
+            }
+            Param::PadTrigger(pad) => controls.pads.pads[pad].trigger(),
+            Param::PadGain(pad) => controls.pads.pads[pad].gain_db.store(0.0),
+            Param::PadsListen => {
+                controls.pads.listen.fetch_xor(true, Ordering::Relaxed);
+            }
+            Param::CueClick(bus) => {
+                controls.cue_buses[bus].listen_click.fetch_xor(true, Ordering::Relaxed);
+            }
+            Param::ClickListen => {
+                controls.click.listen.fetch_xor(true, Ordering::Relaxed);
+            }
+            Param::GateThreshold(_) | Param::Delay(_) | Param::TalkoverThreshold | Param::TalkoverDepth | Param::ClickBpm | Param::ClickLevel => {}
+        }
+    }
+
+    /// Value in its own unit, for display.
+    pub fn display(&self, controls: &MixControls) -> String {
+        match *self {
+            Param::EqGain(slot, band) => {
+                let db = controls.inputs[slot].eq_gain_db[band as usize].load();
+                if db <= eq::MIN_DB { "kill".to_string() } else { format!("{:+.1} dB", db) }
+            }
+            Param::EqKill(..) | Param::Gate(..) | Param::Talkover | Param::CueListen(..) | Param::CuePads(..) | Param::PadsListen | Param::CueClick(..) | Param::ClickListen => if self.get(controls) >= 0.5 { "on" } else { "off" }.to_string(),
+            Param::Filter(slot) => filter::describe(controls.inputs[slot].filter.load()),
+            Param::FilterResonance(slot) => format!("{:.0}%", controls.inputs[slot].filter_resonance.load() * 100.0),
+            Param::GateThreshold(slot) => format!("{:.1} dBFS", controls.inputs[slot].gate_threshold_db.load()),
+            Param::Delay(slot) => format!("{:.1} ms", controls.inputs[slot].delay_ms.load()),
+            Param::TalkoverThreshold => format!("{:.1} dBFS", controls.talkover.threshold_db.load()),
+            Param::TalkoverDepth => format!("{:.1} dB", controls.talkover.depth_db.load()),
+            Param::CueGain(bus, slot) => format!("{:+.1} dB", controls.cue_buses[bus].gain_db[slot].load()),
+            Param::PadTrigger(_) => if self.get(controls) >= 0.5 { "playing" } else { "idle" }.to_string(),
+            Param::PadGain(pad) => format!("{:+.1} dB", controls.pads.pads[pad].gain_db.load()),
+            Param::ClickBpm => format!("{:.1} BPM", controls.click.bpm.load()),
+            Param::ClickLevel => format!("{:.1} dBFS", controls.click.level_db.load()),
+        }
+    }
+}
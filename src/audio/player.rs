@@ -0,0 +1,311 @@
+// Audio file playback as an input source (a jingle, a reference track). The file is decoded
+// a packet at a time on the slot's source thread (see `source`), turned into stereo, and
+// resampled linearly when its rate differs from the output's. Transport state is shared
+// with the UI and the API through `FilePlayer`.
+
+use crate::audio::controls::AtomicF32;
+use crate::audio::source::SOURCE_CHANNELS;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::Time;
+
+// Decoded frames kept before the read position is compacted away
+const COMPACT_FRAMES: usize = 8192;
+
+/// Transport of one input slot's file player.
+pub struct FilePlayer {
+    file: Mutex<Option<PathBuf>>,
+    // Bumped on every load so the source thread reopens the file
+    generation: AtomicUsize,
+    pub playing: AtomicBool,
+    pub looping: AtomicBool,
+    // Seek request in seconds, taken by the source thread
+    seek: Mutex<Option<f64>>,
+    /// Position and length in seconds; the position is kept up to date by the source thread.
+    pub position: AtomicF32,
+    pub duration: AtomicF32,
+    error: Mutex<Option<String>>,
+}
+
+impl FilePlayer {
+    pub fn new() -> Self {
+        Self {
+            file: Mutex::new(None),
+            generation: AtomicUsize::new(0),
+            playing: AtomicBool::new(false),
+            looping: AtomicBool::new(false),
+            seek: Mutex::new(None),
+            position: AtomicF32::new(0.0),
+            duration: AtomicF32::new(0.0),
+            error: Mutex::new(None),
+        }
+    }
+
+    /// Open `path` to check it can be decoded and replace the current file, paused at the start.
+    pub fn load(&self, path: &Path) -> Result<(), String> {
+        let decoder = Decoder::open(path)?;
+        self.playing.store(false, Ordering::Relaxed);
+        *self.seek.lock().unwrap() = None;
+        self.position.store(0.0);
+        self.duration.store(decoder.duration().unwrap_or(0.0) as f32);
+        *self.error.lock().unwrap() = None;
+        *self.file.lock().unwrap() = Some(path.to_path_buf());
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn file(&self) -> Option<PathBuf> {
+        self.file.lock().unwrap().clone()
+    }
+
+    pub fn play(&self) {
+        self.playing.store(self.file().is_some(), Ordering::Relaxed);
+    }
+
+    pub fn pause(&self) {
+        self.playing.store(false, Ordering::Relaxed);
+    }
+
+    pub fn toggle(&self) {
+        if self.playing.load(Ordering::Relaxed) { self.pause() } else { self.play() }
+    }
+
+    pub fn seek(&self, seconds: f64) {
+        let seconds = seconds.clamp(0.0, self.duration.load().max(0.0) as f64);
+        *self.seek.lock().unwrap() = Some(seconds);
+        self.position.store(seconds as f32);
+    }
+
+    /// Last decoding error of the current file.
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+
+    fn fail(&self, msg: String) {
+        self.playing.store(false, Ordering::Relaxed);
+        *self.error.lock().unwrap() = Some(msg);
+    }
+}
+
+/// Source thread side of a `FilePlayer`: produces interleaved stereo at the output's rate.
+pub struct FileReader {
+    sample_rate: u32,
+    generation: Option<usize>,
+    decoder: Option<Decoder>,
+    // Decoded stereo frames at the file's rate; the first is file frame `base`
+    pending: Vec<f32>,
+    base: u64,
+    // Read position in frames into `pending`, fractional while resampling
+    pos: f64,
+}
+
+impl FileReader {
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate: sample_rate.max(1), generation: None, decoder: None, pending: Vec::new(), base: 0, pos: 0.0 }
+    }
+
+    pub fn fill(&mut self, block: &mut [f32], player: &FilePlayer) {
+        let generation = player.generation.load(Ordering::Relaxed);
+        if self.generation != Some(generation) {
+            self.generation = Some(generation);
+            self.decoder = None;
+            self.restart(0);
+            if let Some(path) = player.file() {
+                match Decoder::open(&path) {
+                    Ok(decoder) => self.decoder = Some(decoder),
+                    Err(e) => player.fail(e),
+                }
+            }
+        }
+        if let Some(seconds) = player.seek.lock().unwrap().take() {
+            self.seek(seconds, player);
+        }
+
+        block.fill(0.0);
+        let decoder_rate = match &self.decoder {
+            Some(decoder) if player.playing.load(Ordering::Relaxed) => decoder.sample_rate,
+            _ => return,
+        };
+        let step = decoder_rate as f64 / self.sample_rate as f64;
+        for frame in block.chunks_exact_mut(SOURCE_CHANNELS) {
+            let i = self.pos as usize;
+            // Both frames around the read position must be decoded
+            while (i + 2) * SOURCE_CHANNELS > self.pending.len() {
+                if !self.decode_more(player) {
+                    break;
+                }
+            }
+            if (i + 2) * SOURCE_CHANNELS > self.pending.len() {
+                // End of the file: rewind, so play starts over
+                player.pause();
+                self.seek(0.0, player);
+                break;
+            }
+            let t = (self.pos - i as f64) as f32;
+            for (ch, s) in frame.iter_mut().enumerate() {
+                let (a, b) = (self.pending[i * SOURCE_CHANNELS + ch], self.pending[(i + 1) * SOURCE_CHANNELS + ch]);
+                *s = a + (b - a) * t;
+            }
+            self.pos += step;
+        }
+
+        if self.pos as usize > COMPACT_FRAMES {
+            let drop = self.pos as usize;
+            self.pending.drain(..drop * SOURCE_CHANNELS);
+            self.base += drop as u64;
+            self.pos -= drop as f64;
+        }
+        let seconds = (self.base as f64 + self.pos) / decoder_rate as f64;
+        let duration = player.duration.load() as f64;
+        player.position.store(if duration > 0.0 { seconds % duration } else { seconds } as f32);
+    }
+
+    // Append the next packet's frames; at the end of the file, wrap around when looping
+    fn decode_more(&mut self, player: &FilePlayer) -> bool {
+        let decoder = match &mut self.decoder {
+            Some(decoder) => decoder,
+            None => return false,
+        };
+        match decoder.next(&mut self.pending) {
+            Ok(true) => true,
+            Ok(false) if player.looping.load(Ordering::Relaxed) => match decoder.seek(0.0) {
+                // The looped frames follow on in `pending`; the position wraps by the duration
+                Ok(_) => decoder.next(&mut self.pending).unwrap_or(false),
+                Err(e) => {
+                    player.fail(e);
+                    false
+                }
+            },
+            Ok(false) => false,
+            Err(e) => {
+                player.fail(e);
+                false
+            }
+        }
+    }
+
+    fn seek(&mut self, seconds: f64, player: &FilePlayer) {
+        let frame = match &mut self.decoder {
+            Some(decoder) => match decoder.seek(seconds) {
+                Ok(frame) => frame,
+                Err(e) => return player.fail(e),
+            },
+            None => 0,
+        };
+        self.restart(frame);
+        player.position.store(seconds as f32);
+    }
+
+    fn restart(&mut self, frame: u64) {
+        self.pending.clear();
+        self.base = frame;
+        self.pos = 0.0;
+    }
+}
+
+/// Decode a whole file into stereo frames at its own sample rate, for in-memory playback;
+/// fails if it is longer than `max_seconds`.
+pub fn decode_file(path: &Path, max_seconds: f64) -> Result<(Vec<f32>, u32), String> {
+    let mut decoder = Decoder::open(path)?;
+    let limit = (max_seconds * decoder.sample_rate as f64) as usize * SOURCE_CHANNELS;
+    let mut samples = Vec::new();
+    while decoder.next(&mut samples)? {
+        if samples.len() > limit {
+            return Err(format!("{} is longer than {} s", path.display(), max_seconds));
+        }
+    }
+    Ok((samples, decoder.sample_rate))
+}
+
+struct Decoder {
+    format: Box<dyn FormatReader>,
+    codec: Box<dyn symphonia::core::codecs::Decoder>,
+    track: u32,
+    sample_rate: u32,
+    frames: Option<u64>,
+    // Frames to drop after a seek that landed before the requested time
+    skip: u64,
+    buffer: Option<SampleBuffer<f32>>,
+}
+
+impl Decoder {
+    fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+        let probed = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions { enable_gapless: true, ..Default::default() }, &MetadataOptions::default())
+            .map_err(|e| format!("Unsupported file {}: {}", path.display(), e))?;
+        let format = probed.format;
+        let track = format.default_track().ok_or_else(|| format!("No audio track in {}", path.display()))?;
+        let params = &track.codec_params;
+        let sample_rate = params.sample_rate.ok_or_else(|| format!("Unknown sample rate in {}", path.display()))?;
+        let codec = symphonia::default::get_codecs()
+            .make(params, &DecoderOptions::default())
+            .map_err(|e| format!("Unsupported codec in {}: {}", path.display(), e))?;
+        Ok(Self { track: track.id, sample_rate, frames: params.n_frames, format, codec, skip: 0, buffer: None })
+    }
+
+    fn duration(&self) -> Option<f64> {
+        self.frames.map(|n| n as f64 / self.sample_rate as f64)
+    }
+
+    // Decode the next packet onto `out` as stereo frames; `Ok(false)` at the end of the file
+    fn next(&mut self, out: &mut Vec<f32>) -> Result<bool, String> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+                Err(DecodeError::ResetRequired) => return Ok(false),
+                Err(e) => return Err(format!("Failed to read file: {}", e)),
+            };
+            if packet.track_id() != self.track {
+                continue;
+            }
+            let decoded = match self.codec.decode(&packet) {
+                Ok(decoded) => decoded,
+                // A corrupt packet is skipped, like players do
+                Err(DecodeError::DecodeError(_)) => continue,
+                Err(e) => return Err(format!("Failed to decode file: {}", e)),
+            };
+            let spec = *decoded.spec();
+            let channels = spec.channels.count().max(1);
+            let buffer = match &mut self.buffer {
+                Some(buffer) if buffer.capacity() >= decoded.capacity() * channels => buffer,
+                slot => slot.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+            };
+            buffer.copy_interleaved_ref(decoded);
+            let skip = (self.skip as usize).min(buffer.samples().len() / channels);
+            self.skip -= skip as u64;
+            for frame in buffer.samples().chunks_exact(channels).skip(skip) {
+                // Mono plays on both sides; beyond two channels only the first pair is kept
+                let (left, right) = (frame[0], frame[if channels > 1 { 1 } else { 0 }]);
+                out.extend_from_slice(&[left, right]);
+            }
+            return Ok(true);
+        }
+    }
+
+    // Returns the file frame decoding continues from. Timestamps are taken as frames, which
+    // holds for the time bases of the supported formats
+    fn seek(&mut self, seconds: f64) -> Result<u64, String> {
+        let seeked = self.format
+            .seek(SeekMode::Accurate, SeekTo::Time { time: Time::from(seconds), track_id: Some(self.track) })
+            .map_err(|e| format!("Failed to seek: {}", e))?;
+        self.codec.reset();
+        self.skip = seeked.required_ts.saturating_sub(seeked.actual_ts);
+        Ok(seeked.required_ts)
+    }
+}
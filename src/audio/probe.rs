@@ -0,0 +1,51 @@
+// One-shot test signal played on the main mix, for measurements such as round-trip latency.
+//
+// The render path only `try_lock`s the samples; a block where that fails plays the mix and
+// the signal simply starts (or continues) one block later.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+pub struct Probe {
+    samples: Mutex<Vec<f32>>,
+    playing: AtomicBool,
+    // Next sample to play; only the render path advances it while playing
+    position: AtomicUsize,
+}
+
+impl Probe {
+    pub fn new() -> Self {
+        Self { samples: Mutex::new(Vec::new()), playing: AtomicBool::new(false), position: AtomicUsize::new(0) }
+    }
+
+    /// Play `samples` (mono) once on every channel of the mix, replacing the mix meanwhile.
+    pub fn play(&self, samples: Vec<f32>) {
+        *self.samples.lock().unwrap() = samples;
+        self.position.store(0, Ordering::Relaxed);
+        self.playing.store(true, Ordering::Release);
+    }
+
+    pub fn stop(&self) {
+        self.playing.store(false, Ordering::Relaxed);
+    }
+
+    /// Render side: overwrite an interleaved block with the next part of the signal.
+    pub fn render(&self, block: &mut [f32], channels: usize) {
+        if !self.playing.load(Ordering::Acquire) {
+            return;
+        }
+        let samples = match self.samples.try_lock() {
+            Ok(samples) => samples,
+            Err(_) => return,
+        };
+        let mut pos = self.position.load(Ordering::Relaxed);
+        for frame in block.chunks_exact_mut(channels.max(1)) {
+            frame.fill(samples.get(pos).copied().unwrap_or(0.0));
+            pos += 1;
+        }
+        self.position.store(pos, Ordering::Relaxed);
+        if pos >= samples.len() {
+            self.playing.store(false, Ordering::Relaxed);
+        }
+    }
+}
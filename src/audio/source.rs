@@ -0,0 +1,77 @@
+// Software input sources (test generators, file playback): a thread that feeds an input
+// slot's ring and tap through the same `CaptureSink` a capture device uses. Rather than
+// keeping time itself, it tops the ring up to a fixed fill level, so it runs at exactly the
+// rate the render path consumes (the output clock) and never drifts.
+
+use crate::audio::backend::InputSource;
+use crate::audio::controls::MixControls;
+use crate::audio::engine::CaptureSink;
+use crate::audio::generator::{SignalGenerator, GENERATOR_CHANNELS};
+use crate::audio::player::FileReader;
+use crate::audio::tap::{BlockTap, TapFormat};
+use ringbuf::HeapProducer;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Frames produced per block
+const BLOCK_FRAMES: usize = 256;
+// How much audio is kept queued for the render path
+const TARGET_MS: usize = 20;
+const POLL: Duration = Duration::from_millis(2);
+
+/// Stops its thread when dropped.
+pub struct SourceThread {
+    stop_flag: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Channels every software source produces.
+pub const SOURCE_CHANNELS: usize = GENERATOR_CHANNELS;
+
+impl SourceThread {
+    /// Feed input `slot` from its software source, or `None` if `source` is a device.
+    pub fn start(source: InputSource, slot: usize, producer: HeapProducer<f32>, tap: Arc<BlockTap>, controls: Arc<MixControls>, sample_rate: u32) -> Option<Self> {
+        match source {
+            InputSource::Device(_) => None,
+            InputSource::Generator => {
+                let mut generator = SignalGenerator::new(sample_rate);
+                Some(Self::spawn(producer, tap, SOURCE_CHANNELS, sample_rate, move |block| generator.fill(block, &controls.generators[slot])))
+            }
+            InputSource::File => {
+                let mut reader = FileReader::new(sample_rate);
+                Some(Self::spawn(producer, tap, SOURCE_CHANNELS, sample_rate, move |block| reader.fill(block, &controls.players[slot])))
+            }
+        }
+    }
+
+    /// Run `fill` on interleaved blocks of `channels` for as long as the source lives.
+    pub fn spawn(producer: HeapProducer<f32>, tap: Arc<BlockTap>, channels: usize, sample_rate: u32, mut fill: impl FnMut(&mut [f32]) + Send + 'static) -> Self {
+        tap.set_format(Some(TapFormat { sample_rate, channels: channels as u16 }));
+        let mut sink = CaptureSink::new(producer, tap, BLOCK_FRAMES * channels);
+        let target = sample_rate as usize * TARGET_MS / 1000 * channels;
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let stop = stop_flag.clone();
+        let thread = thread::spawn(move || {
+            let mut block = vec![0.0f32; BLOCK_FRAMES * channels];
+            while !stop.load(Ordering::Relaxed) {
+                while sink.queued() < target {
+                    fill(&mut block);
+                    sink.push(&block, |s| s);
+                }
+                thread::sleep(POLL);
+            }
+        });
+        Self { stop_flag, thread: Some(thread) }
+    }
+}
+
+impl Drop for SourceThread {
+    fn drop(&mut self) {
+        self.stop_flag.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use ringbuf::HeapProducer;
+use std::iter;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Sample rate and channel count of the blocks passing through a tap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TapFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Hand-off point where an audio thread offers its blocks to non-real-time consumers
+/// (recorders, analyzers). Consumers attach the producer half of their own ring buffer.
+///
+/// The audio side only ever uses `try_lock`, so attaching/detaching from the UI can make
+/// it skip a block but never block it; blocks that don't fit a consumer's ring, or that
+/// arrive while the sinks are locked, are dropped whole and counted instead of waiting.
+/// Each consumer gets the same number of frames of silence in their place once its ring
+/// has room again, so its timing and channel alignment survive the loss.
+pub struct BlockTap {
+    sinks: Mutex<Vec<Sink>>,
+    // First-block times by sink id. Only consumers lock this, so polling it never gets in
+    // the way of `push`
+    first_blocks: Mutex<Vec<(u64, Arc<FirstBlock>)>>,
+    format: Mutex<Option<TapFormat>>,
+    next_id: AtomicU64,
+    attached: AtomicUsize,
+    dropped: AtomicU64,
+    // Samples of every block skipped while the sinks were locked, owed to each sink as silence
+    skipped: AtomicU64,
+    channels: AtomicUsize,
+    // Samples in the last block, for latency estimates
+    block_len: AtomicUsize,
+    epoch: Instant,
+}
+
+struct Sink {
+    id: u64,
+    producer: HeapProducer<f32>,
+    first_block: Arc<FirstBlock>,
+    // Samples of silence still to push before the next block
+    owed: usize,
+    // `BlockTap::skipped` when this sink last caught up with it
+    skipped_seen: u64,
+}
+
+// Arrival time and sample count of a sink's first block, for aligning several recordings
+#[derive(Default)]
+struct FirstBlock {
+    // Nanoseconds after the tap's epoch plus one; 0 until the block has arrived
+    arrived: AtomicU64,
+    len: AtomicUsize,
+}
+
+impl BlockTap {
+    pub fn new() -> Self {
+        Self {
+            sinks: Mutex::new(Vec::new()),
+            first_blocks: Mutex::new(Vec::new()),
+            format: Mutex::new(None),
+            next_id: AtomicU64::new(1),
+            attached: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            channels: AtomicUsize::new(1),
+            block_len: AtomicUsize::new(0),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Called by the backend when a stream is (re)started or stopped.
+    pub fn set_format(&self, format: Option<TapFormat>) {
+        *self.format.lock().unwrap() = format;
+        self.channels.store(format.map_or(1, |f| f.channels.max(1) as usize), Ordering::Relaxed);
+        self.block_len.store(0, Ordering::Relaxed);
+    }
+
+    pub fn format(&self) -> Option<TapFormat> {
+        *self.format.lock().unwrap()
+    }
+
+    /// Start receiving blocks; returns an id for `detach`.
+    pub fn attach(&self, producer: HeapProducer<f32>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let first_block = Arc::new(FirstBlock::default());
+        self.first_blocks.lock().unwrap().push((id, first_block.clone()));
+        let mut sinks = self.sinks.lock().unwrap();
+        sinks.push(Sink { id, producer, first_block, owed: 0, skipped_seen: 0 });
+        self.attached.store(sinks.len(), Ordering::Relaxed);
+        id
+    }
+
+    pub fn detach(&self, id: u64) {
+        let mut sinks = self.sinks.lock().unwrap();
+        sinks.retain(|sink| sink.id != id);
+        self.attached.store(sinks.len(), Ordering::Relaxed);
+        drop(sinks);
+        self.first_blocks.lock().unwrap().retain(|(sink, _)| *sink != id);
+    }
+
+    /// When the first block reached sink `id`, and how many samples it had.
+    pub fn first_block(&self, id: u64) -> Option<(Instant, usize)> {
+        let first_blocks = self.first_blocks.lock().unwrap();
+        let first = &first_blocks.iter().find(|(sink, _)| *sink == id)?.1;
+        let arrived = first.arrived.load(Ordering::Acquire).checked_sub(1)?;
+        Some((self.epoch + Duration::from_nanos(arrived), first.len.load(Ordering::Relaxed)))
+    }
+
+    /// Duration of the last block in ms (the device's callback size), once the format is set
+    /// and a block has passed.
+    pub fn block_ms(&self) -> Option<f32> {
+        let format = self.format()?;
+        let len = self.block_len.load(Ordering::Relaxed);
+        if len == 0 || format.channels == 0 {
+            return None;
+        }
+        Some(len as f32 / format.channels as f32 / format.sample_rate as f32 * 1000.0)
+    }
+
+    /// Real-time side: offer an interleaved block to every attached consumer.
+    pub fn push(&self, samples: &[f32]) {
+        self.block_len.store(samples.len(), Ordering::Relaxed);
+        let mut sinks = match self.sinks.try_lock() {
+            Ok(sinks) => sinks,
+            Err(_) => {
+                // Attaching or detaching right now; every consumer misses this block
+                let missed = samples.len() * self.attached.load(Ordering::Relaxed);
+                self.dropped.fetch_add(missed as u64, Ordering::Relaxed);
+                self.skipped.fetch_add(samples.len() as u64, Ordering::Relaxed);
+                return;
+            }
+        };
+        let channels = self.channels.load(Ordering::Relaxed);
+        let skipped = self.skipped.load(Ordering::Relaxed);
+        for sink in sinks.iter_mut() {
+            let first = &sink.first_block;
+            if first.arrived.load(Ordering::Relaxed) == 0 {
+                let arrived = self.epoch.elapsed().as_nanos() as u64 + 1;
+                first.len.store(samples.len(), Ordering::Relaxed);
+                first.arrived.store(arrived, Ordering::Release);
+                // Blocks skipped before this one were never this sink's
+                sink.skipped_seen = skipped;
+            }
+            sink.owed += (skipped - sink.skipped_seen) as usize;
+            sink.skipped_seen = skipped;
+
+            // Fill earlier gaps first, in whole frames, so what follows lands where it belongs
+            if sink.owed > 0 {
+                let room = sink.producer.free_len() / channels * channels;
+                let n = sink.owed.min(room);
+                sink.producer.push_iter(&mut iter::repeat_n(0.0, n));
+                sink.owed -= n;
+            }
+            if sink.owed == 0 && sink.producer.free_len() >= samples.len() {
+                sink.producer.push_slice(samples);
+            } else {
+                sink.owed += samples.len();
+                self.dropped.fetch_add(samples.len() as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Samples dropped because a consumer could not keep up or a block was skipped.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
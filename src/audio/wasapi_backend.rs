@@ -1,4 +1,8 @@
-use crate::audio::backend::{AudioBackend, BackendError, DeviceEntry, Mode};
+use crate::audio::backend::{AudioBackend, BackendError, ChannelRange, DeviceDescription, DeviceEntry, InputSelection, Mode, OutputSelection, COMMON_SAMPLE_RATES};
+use crate::audio::controls::{MixControls, MAX_CUE_BUSES, MAX_OUTPUTS};
+use crate::audio::engine::{CaptureSink, CueBusMix, EngineInput, MixEngine, OutputFeed, OutputParams, OutputStage, RenderSource};
+use crate::audio::source::{SourceThread, SOURCE_CHANNELS};
+use crate::audio::tap::TapFormat;
 use ringbuf::HeapRb;
 use std::ffi::OsStr;
 use std::os::windows::ffi::OsStrExt;
@@ -39,6 +43,8 @@ const BUFFER_FRAMES: usize = 16384;
 pub struct WasapiBackend {
     stop_flag: Arc<AtomicBool>,
     threads: Vec<JoinHandle<()>>,
+    // Software sources feeding input slots
+    sources: Vec<SourceThread>,
     event_handles: Vec<HANDLE>,
 }
 
@@ -68,6 +74,7 @@ impl WasapiBackend {
         Ok(Self {
             stop_flag: Arc::new(AtomicBool::new(false)),
             threads: Vec::new(),
+            sources: Vec::new(),
             event_handles: Vec::new(),
         })
     }
@@ -106,6 +113,149 @@ impl WasapiBackend {
         OsStr::new(s).encode_wide().chain(Some(0)).collect()
     }
 
+    // Event-driven capture loop for one device; converted blocks go to every sink (one per
+    // input slot using the device)
+    fn spawn_capture_thread(&mut self, bundle: ClientBundle, mut sinks: Vec<CaptureSink>) -> JoinHandle<()> {
+        let stop_flag = self.stop_flag.clone();
+        let event = bundle.event;
+        self.event_handles.push(event);
+
+        let audio_client = bundle.audio_client as usize;
+        let format = bundle.format;
+        let task_name = WasapiBackend::to_wide("Pro Audio");
+        let event = event as usize;
+
+        thread::spawn(move || {
+            unsafe { CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED); }
+            let mut task_index: u32 = 0;
+            let mmcss = unsafe { AvSetMmThreadCharacteristicsW(task_name.as_ptr(), &mut task_index) };
+
+            let audio_client = audio_client as *mut IAudioClient;
+            let event = event as HANDLE;
+            let mut capture_client: *mut IAudioCaptureClient = ptr::null_mut();
+            let hr = unsafe { (*audio_client).GetService(&IAudioCaptureClient::uuidof(), &mut capture_client as *mut _ as *mut _) };
+            if FAILED(hr) {
+                unsafe { (*audio_client).Release(); }
+                if !mmcss.is_null() { unsafe { AvRevertMmThreadCharacteristics(mmcss); } }
+                unsafe { CoUninitialize(); }
+                return;
+            }
+
+            unsafe { (*audio_client).Start(); }
+
+            while !stop_flag.load(Ordering::Relaxed) {
+                let wait = unsafe { WaitForSingleObject(event, 2000) };
+                if wait != WAIT_OBJECT_0 { continue; }
+
+                let mut packet: u32 = 0;
+                unsafe { (*capture_client).GetNextPacketSize(&mut packet); }
+                while packet > 0 {
+                    let mut data: *mut u8 = ptr::null_mut();
+                    let mut frames: u32 = 0;
+                    let mut flags: u32 = 0;
+                    let hr = unsafe { (*capture_client).GetBuffer(&mut data, &mut frames, &mut flags, ptr::null_mut(), ptr::null_mut()) };
+                    if FAILED(hr) { break; }
+
+                    let total = frames as usize * format.channels as usize;
+                    for sink in sinks.iter_mut() {
+                        if flags & AUDCLNT_BUFFERFLAGS_SILENT != 0 {
+                            sink.push_silence(total);
+                        } else if format.is_float && format.bits_per_sample == 32 {
+                            let samples = unsafe { std::slice::from_raw_parts(data as *const f32, total) };
+                            sink.push(samples, |s| s);
+                        } else if !format.is_float && format.bits_per_sample == 16 {
+                            let samples = unsafe { std::slice::from_raw_parts(data as *const i16, total) };
+                            sink.push(samples, |s| s as f32 / 32768.0);
+                        } else if !format.is_float && format.bits_per_sample == 32 {
+                            let samples = unsafe { std::slice::from_raw_parts(data as *const i32, total) };
+                            sink.push(samples, |s| s as f32 / 2147483648.0);
+                        }
+                    }
+
+                    unsafe { (*capture_client).ReleaseBuffer(frames); }
+                    unsafe { (*capture_client).GetNextPacketSize(&mut packet); }
+                }
+            }
+
+            unsafe { (*audio_client).Stop(); }
+            unsafe { (*capture_client).Release(); }
+            unsafe { (*audio_client).Release(); }
+            if !mmcss.is_null() { unsafe { AvRevertMmThreadCharacteristics(mmcss); } }
+            unsafe { CoUninitialize(); }
+        })
+    }
+
+    // Event-driven render loop for one output device, pulling its blocks from `source`
+    fn spawn_render_thread<R: RenderSource + 'static>(&mut self, bundle: ClientBundle, mut source: R) -> JoinHandle<()> {
+        let stop_flag = self.stop_flag.clone();
+        let event = bundle.event;
+        self.event_handles.push(event);
+
+        let audio_client = bundle.audio_client as usize;
+        let format = bundle.format;
+        let buffer_frames = bundle.buffer_frames;
+        let task_name = WasapiBackend::to_wide("Pro Audio");
+        let event = event as usize;
+
+        thread::spawn(move || {
+            unsafe { CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED); }
+            let mut task_index: u32 = 0;
+            let mmcss = unsafe { AvSetMmThreadCharacteristicsW(task_name.as_ptr(), &mut task_index) };
+
+            let audio_client = audio_client as *mut IAudioClient;
+            let event = event as HANDLE;
+            let mut render_client: *mut IAudioRenderClient = ptr::null_mut();
+            let hr = unsafe { (*audio_client).GetService(&IAudioRenderClient::uuidof(), &mut render_client as *mut _ as *mut _) };
+            if FAILED(hr) {
+                unsafe { (*audio_client).Release(); }
+                if !mmcss.is_null() { unsafe { AvRevertMmThreadCharacteristics(mmcss); } }
+                unsafe { CoUninitialize(); }
+                return;
+            }
+
+            unsafe { (*audio_client).Start(); }
+
+            while !stop_flag.load(Ordering::Relaxed) {
+                let wait = unsafe { WaitForSingleObject(event, 2000) };
+                if wait != WAIT_OBJECT_0 { continue; }
+
+                let mut padding: u32 = 0;
+                let hr = unsafe { (*audio_client).GetCurrentPadding(&mut padding) };
+                if FAILED(hr) { continue; }
+
+                let frames_avail = buffer_frames.saturating_sub(padding);
+                if frames_avail == 0 { continue; }
+
+                let mut data: *mut u8 = ptr::null_mut();
+                let hr = unsafe { (*render_client).GetBuffer(frames_avail, &mut data) };
+                if FAILED(hr) { continue; }
+
+                let channels = format.channels as usize;
+                let total = frames_avail as usize * channels;
+                let mixed = source.render(frames_avail as usize);
+
+                if format.is_float && format.bits_per_sample == 32 {
+                    let samples = unsafe { std::slice::from_raw_parts_mut(data as *mut f32, total) };
+                    samples.copy_from_slice(mixed);
+                } else if !format.is_float && format.bits_per_sample == 16 {
+                    let samples = unsafe { std::slice::from_raw_parts_mut(data as *mut i16, total) };
+                    for (dst, &s) in samples.iter_mut().zip(mixed) { *dst = (s.clamp(-1.0, 1.0) * 32767.0) as i16; }
+                } else if !format.is_float && format.bits_per_sample == 32 {
+                    let samples = unsafe { std::slice::from_raw_parts_mut(data as *mut i32, total) };
+                    for (dst, &s) in samples.iter_mut().zip(mixed) { *dst = (s.clamp(-1.0, 1.0) * 2147483647.0) as i32; }
+                }
+
+                unsafe { (*render_client).ReleaseBuffer(frames_avail, 0); }
+            }
+
+            unsafe { (*audio_client).Stop(); }
+            unsafe { (*render_client).Release(); }
+            unsafe { (*audio_client).Release(); }
+            if !mmcss.is_null() { unsafe { AvRevertMmThreadCharacteristics(mmcss); } }
+            unsafe { CoUninitialize(); }
+        })
+    }
+
     unsafe fn create_enumerator() -> Result<*mut IMMDeviceEnumerator, BackendError> {
         let mut enumerator: *mut IMMDeviceEnumerator = ptr::null_mut();
         let hr = CoCreateInstance(
@@ -224,6 +374,100 @@ impl WasapiBackend {
         })
     }
 
+    fn extensible_format(channels: u16, rate: u32, bits: u16, is_float: bool) -> WAVEFORMATEXTENSIBLE {
+        let mut wfxe: WAVEFORMATEXTENSIBLE = unsafe { std::mem::zeroed() };
+        let block_align = (bits / 8) * channels;
+        wfxe.Format.wFormatTag = WAVE_FORMAT_EXTENSIBLE;
+        wfxe.Format.nChannels = channels;
+        wfxe.Format.nSamplesPerSec = rate;
+        wfxe.Format.wBitsPerSample = bits;
+        wfxe.Format.nBlockAlign = block_align;
+        wfxe.Format.nAvgBytesPerSec = rate * block_align as u32;
+        wfxe.Format.cbSize = (std::mem::size_of::<WAVEFORMATEXTENSIBLE>() - std::mem::size_of::<WAVEFORMATEX>()) as u16;
+        wfxe.Samples = bits;
+        wfxe.dwChannelMask = if channels == 1 {
+            SPEAKER_FRONT_CENTER
+        } else if channels == 2 {
+            SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT
+        } else {
+            0
+        };
+        wfxe.SubFormat = if is_float { KSDATAFORMAT_SUBTYPE_IEEE_FLOAT } else { KSDATAFORMAT_SUBTYPE_PCM };
+        wfxe
+    }
+
+    fn format_name(bits: u16, is_float: bool) -> String {
+        if is_float { format!("f{}", bits) } else { format!("i{}", bits) }
+    }
+
+    // Returns (sample rates, channel counts, format names) the device accepts in `mode`.
+    // Shared mode only ever runs at the mix format; exclusive mode is probed with IsFormatSupported.
+    unsafe fn probe_formats(enumerator: *mut IMMDeviceEnumerator, device_id: &str, mode: Mode) -> Result<(Vec<u32>, Vec<u16>, Vec<String>), BackendError> {
+        let wide = WasapiBackend::to_wide(device_id);
+        let mut device: *mut IMMDevice = ptr::null_mut();
+        let hr = (*enumerator).GetDevice(wide.as_ptr(), &mut device);
+        if FAILED(hr) {
+            return Err(BackendError::InitError(format!("GetDevice failed: 0x{:08X}", hr as u32)));
+        }
+
+        let mut audio_client: *mut IAudioClient = ptr::null_mut();
+        let hr = (*device).Activate(&IAudioClient::uuidof(), CLSCTX_ALL, ptr::null_mut(), &mut audio_client as *mut _ as *mut _);
+        (*device).Release();
+        if FAILED(hr) {
+            return Err(BackendError::InitError(format!("Activate(IAudioClient) failed: 0x{:08X}", hr as u32)));
+        }
+
+        let mut pwfx: *mut WAVEFORMATEX = ptr::null_mut();
+        let hr = (*audio_client).GetMixFormat(&mut pwfx);
+        if FAILED(hr) {
+            (*audio_client).Release();
+            return Err(BackendError::InitError(format!("GetMixFormat failed: 0x{:08X}", hr as u32)));
+        }
+        let mix = WasapiBackend::parse_format(pwfx as *const _).ok();
+        CoTaskMemFree(pwfx as *mut _);
+
+        let mut rates = Vec::new();
+        let mut channel_counts = Vec::new();
+        let mut formats = Vec::new();
+
+        match mode {
+            Mode::Shared => {
+                if let Some(m) = mix {
+                    rates.push(m.sample_rate);
+                    channel_counts.push(m.channels);
+                    formats.push(WasapiBackend::format_name(m.bits_per_sample, m.is_float));
+                }
+            }
+            Mode::Exclusive => {
+                let base_channels = mix.map(|m| m.channels).unwrap_or(2);
+                let base_rate = mix.map(|m| m.sample_rate).unwrap_or(48000);
+                let candidates: [(u16, bool); 4] = [(32, true), (32, false), (24, false), (16, false)];
+                let supported = |channels: u16, rate: u32, bits: u16, is_float: bool| -> bool {
+                    let wfxe = WasapiBackend::extensible_format(channels, rate, bits, is_float);
+                    // Exclusive mode never suggests a closest match, so no out pointer
+                    (*audio_client).IsFormatSupported(AUDCLNT_SHAREMODE_EXCLUSIVE, &wfxe.Format as *const _, ptr::null_mut()) == S_OK
+                };
+
+                for &rate in COMMON_SAMPLE_RATES.iter() {
+                    if candidates.iter().any(|&(bits, f)| supported(base_channels, rate, bits, f)) { rates.push(rate); }
+                }
+                for &channels in [1u16, 2, 4, 6, 8].iter() {
+                    if candidates.iter().any(|&(bits, f)| supported(channels, base_rate, bits, f)) { channel_counts.push(channels); }
+                }
+                if !channel_counts.contains(&base_channels) && candidates.iter().any(|&(bits, f)| supported(base_channels, base_rate, bits, f)) {
+                    channel_counts.push(base_channels);
+                    channel_counts.sort();
+                }
+                for &(bits, f) in candidates.iter() {
+                    if supported(base_channels, base_rate, bits, f) { formats.push(WasapiBackend::format_name(bits, f)); }
+                }
+            }
+        }
+
+        (*audio_client).Release();
+        Ok((rates, channel_counts, formats))
+    }
+
     unsafe fn open_device_exclusive(enumerator: *mut IMMDeviceEnumerator, device_id: &str) -> Result<ClientBundle, BackendError> {
         let wide = WasapiBackend::to_wide(device_id);
         let mut device: *mut IMMDevice = ptr::null_mut();
@@ -317,26 +561,10 @@ impl WasapiBackend {
 
         for &rate in base_rates.iter() {
             for &(bits, is_float) in [(32, true), (24, false), (16, false)].iter() {
-                let mut wfxe: WAVEFORMATEXTENSIBLE = std::mem::zeroed();
                 let channels = base_channels;
                 let block_align = (bits / 8) * channels;
                 if block_align == 0 { continue; }
-                wfxe.Format.wFormatTag = WAVE_FORMAT_EXTENSIBLE;
-                wfxe.Format.nChannels = channels;
-                wfxe.Format.nSamplesPerSec = rate;
-                wfxe.Format.wBitsPerSample = bits;
-                wfxe.Format.nBlockAlign = block_align;
-                wfxe.Format.nAvgBytesPerSec = rate * block_align as u32;
-                wfxe.Format.cbSize = (std::mem::size_of::<WAVEFORMATEXTENSIBLE>() - std::mem::size_of::<WAVEFORMATEX>()) as u16;
-                wfxe.Samples = bits;
-                wfxe.dwChannelMask = if channels == 1 {
-                    SPEAKER_FRONT_CENTER
-                } else if channels == 2 {
-                    SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT
-                } else {
-                    0
-                };
-                wfxe.SubFormat = if is_float { KSDATAFORMAT_SUBTYPE_IEEE_FLOAT } else { KSDATAFORMAT_SUBTYPE_PCM };
+                let wfxe = WasapiBackend::extensible_format(channels, rate, bits, is_float);
 
                 let mut closest: *mut WAVEFORMATEX = ptr::null_mut();
                 let hr = (*audio_client).IsFormatSupported(AUDCLNT_SHAREMODE_EXCLUSIVE, &wfxe.Format as *const _, &mut closest);
@@ -479,7 +707,31 @@ impl AudioBackend for WasapiBackend {
         Ok(out)
     }
 
-    fn start(&mut self, input_a: Option<usize>, input_b: Option<usize>, output: Option<usize>, listen_a: Arc<AtomicBool>, listen_b: Arc<AtomicBool>) -> Result<(), BackendError> {
+    fn describe_devices(&self) -> Result<Vec<DeviceDescription>, BackendError> {
+        let entries = self.enumerate_devices()?;
+        let should_uninit = WasapiBackend::com_init()?;
+        let mut out = Vec::with_capacity(entries.len());
+        unsafe {
+            let enumerator = WasapiBackend::create_enumerator()?;
+            for (i, entry) in entries.iter().enumerate() {
+                let mut desc = DeviceDescription::from_entry(i, entry);
+                if let Some(id) = entry.device_id.as_ref() {
+                    // A device that fails to probe (e.g. in use exclusively) is still listed, just without formats
+                    if let Ok((rates, channels, formats)) = WasapiBackend::probe_formats(enumerator, id, entry.mode) {
+                        desc.sample_rates = rates;
+                        desc.channel_counts = channels;
+                        desc.formats = formats;
+                    }
+                }
+                out.push(desc);
+            }
+            (*enumerator).Release();
+        }
+        WasapiBackend::com_uninit(should_uninit);
+        Ok(out)
+    }
+
+    fn start(&mut self, input_a: Option<InputSelection>, input_b: Option<InputSelection>, outputs: &[Option<OutputSelection>], cue_buses: &[Option<OutputSelection>], controls: Arc<MixControls>) -> Result<(), BackendError> {
         // Stop any existing threads
         let _ = self.stop();
         self.stop_flag.store(false, Ordering::Relaxed);
@@ -495,9 +747,18 @@ impl AudioBackend for WasapiBackend {
             }
         };
 
-        let in_a = get_entry(input_a)?;
-        let in_b = get_entry(input_b)?;
-        let out = get_entry(output)?;
+        let in_a = get_entry(input_a.and_then(|s| s.device()))?;
+        let in_b = get_entry(input_b.and_then(|s| s.device()))?;
+        let output = outputs.first().copied().flatten();
+        let out = get_entry(output.map(|s| s.device))?;
+        let out_range = output.and_then(|s| s.channels);
+        // Both slots on one entry: open the device once and split its channels
+        let shared_input = matches!((input_a.and_then(|s| s.device()), input_b.and_then(|s| s.device())), (Some(a), Some(b)) if a == b);
+        // Software sources (generator, file) feed their slot's ring instead of a device
+        let soft_a = input_a.filter(|s| s.device().is_none()).map(|s| s.source);
+        let soft_b = input_b.filter(|s| s.device().is_none()).map(|s| s.source);
+        let range_a = input_a.and_then(|s| s.channels);
+        let range_b = input_b.and_then(|s| s.channels);
 
         let out = match out {
             Some(d) => d,
@@ -509,11 +770,24 @@ impl AudioBackend for WasapiBackend {
         let in_b_mode = in_b.as_ref().map(|d| d.mode);
 
         let out_id = out.device_id.clone().ok_or_else(|| BackendError::StartError("Output device has no ID".into()))?;
+
+        // Extra outputs and cue bus outputs: (settings, device ID, mode, channels)
+        let mut extra_outputs = Vec::new();
+        let extras = outputs.iter().take(MAX_OUTPUTS).enumerate().skip(1).map(|(slot, s)| (OutputParams::Output(slot), s));
+        let buses = cue_buses.iter().take(MAX_CUE_BUSES).enumerate().map(|(bus, s)| (OutputParams::CueBus(bus), s));
+        for (slot, selection) in extras.chain(buses) {
+            if let Some(selection) = selection {
+                let entry = entries.get(selection.device).ok_or_else(|| BackendError::StartError("Device index out of range".into()))?;
+                let id = entry.device_id.clone().ok_or_else(|| BackendError::StartError(format!("{} has no ID", slot.label())))?;
+                extra_outputs.push((slot, id, entry.mode, selection.channels));
+            }
+        }
         let in_a_id = in_a.as_ref().and_then(|d| d.device_id.clone());
         let in_b_id = in_b.as_ref().and_then(|d| d.device_id.clone());
 
         let should_uninit = WasapiBackend::com_init()?;
         let mut threads = Vec::new();
+        let mut sources = Vec::new();
 
         unsafe {
             let enumerator = WasapiBackend::create_enumerator()?;
@@ -524,29 +798,78 @@ impl AudioBackend for WasapiBackend {
                 Mode::Shared => WasapiBackend::open_device_shared(enumerator, &out_id)?,
             };
             let out_format = out_bundle.format.clone();
+            if let Some(range) = out_range {
+                if let Err(e) = range.validate(out_format.channels as usize) {
+                    (*out_bundle.audio_client).Release();
+                    return Err(e);
+                }
+            }
+
+            // Open extra outputs and cue buses; they must run at the main output's rate
+            let mut extra_bundles: Vec<(OutputParams, ClientBundle, Option<ChannelRange>)> = Vec::new();
+            for (slot, id, mode, range) in extra_outputs {
+                let opened = match mode {
+                    Mode::Exclusive => WasapiBackend::open_device_exclusive(enumerator, &id),
+                    Mode::Shared => WasapiBackend::open_device_shared(enumerator, &id),
+                };
+                let checked = opened.and_then(|b| {
+                    if b.format.sample_rate != out_format.sample_rate {
+                        let msg = format!("{} sample rate mismatch ({} Hz vs main output {} Hz)", slot.label(), b.format.sample_rate, out_format.sample_rate);
+                        (*b.audio_client).Release();
+                        return Err(BackendError::StartError(msg));
+                    }
+                    if let Some(Err(e)) = range.map(|r| r.validate(b.format.channels as usize)) {
+                        (*b.audio_client).Release();
+                        return Err(e);
+                    }
+                    Ok(b)
+                });
+                match checked {
+                    Ok(b) => extra_bundles.push((slot, b, range)),
+                    Err(e) => {
+                        (*out_bundle.audio_client).Release();
+                        for (_, b, _) in &extra_bundles { (*b.audio_client).Release(); }
+                        return Err(e);
+                    }
+                }
+            }
 
             // Open inputs if provided
-            let in_a_bundle = if let Some(id) = in_a_id.as_ref() {
-                let mode = in_a_mode.unwrap_or(Mode::Exclusive);
-                Some(match mode {
-                    Mode::Exclusive => WasapiBackend::open_device_exclusive(enumerator, id)?,
-                    Mode::Shared => WasapiBackend::open_device_shared(enumerator, id)?,
-                })
-            } else { None };
-            let in_b_bundle = if let Some(id) = in_b_id.as_ref() {
-                let mode = in_b_mode.unwrap_or(Mode::Exclusive);
-                Some(match mode {
-                    Mode::Exclusive => WasapiBackend::open_device_exclusive(enumerator, id)?,
-                    Mode::Shared => WasapiBackend::open_device_shared(enumerator, id)?,
-                })
-            } else { None };
+            let open_input = |id: Option<&String>, mode: Option<Mode>| match id {
+                Some(id) => match mode.unwrap_or(Mode::Exclusive) {
+                    Mode::Exclusive => WasapiBackend::open_device_exclusive(enumerator, id).map(Some),
+                    Mode::Shared => WasapiBackend::open_device_shared(enumerator, id).map(Some),
+                },
+                None => Ok(None),
+            };
+            let in_a_opened = open_input(in_a_id.as_ref(), in_a_mode);
+            let in_b_opened = if shared_input { Ok(None) } else { open_input(in_b_id.as_ref(), in_b_mode) };
 
             (*enumerator).Release();
 
+            let (in_a_bundle, in_b_bundle) = match (in_a_opened, in_b_opened) {
+                (Ok(a), Ok(b)) => (a, b),
+                (a, b) => {
+                    (*out_bundle.audio_client).Release();
+                    for (_, b, _) in &extra_bundles { (*b.audio_client).Release(); }
+                    for bundle in [&a, &b].into_iter().flat_map(|r| r.as_ref().ok()).flatten() {
+                        (*bundle.audio_client).Release();
+                    }
+                    return Err(a.and(b).err().unwrap());
+                }
+            };
+            let release_inputs = || {
+                for bundle in [&in_a_bundle, &in_b_bundle].into_iter().flatten() {
+                    (*bundle.audio_client).Release();
+                }
+            };
+
             // Validate format compatibility
             if let Some(ref b) = in_a_bundle {
                 if b.format.sample_rate != out_format.sample_rate || b.format.channels == 0 {
                     (*out_bundle.audio_client).Release();
+                    for (_, b, _) in &extra_bundles { (*b.audio_client).Release(); }
+                    release_inputs();
                     return Err(BackendError::StartError(format!(
                         "Input A sample rate mismatch ({} Hz vs output {} Hz)",
                         b.format.sample_rate,
@@ -557,6 +880,8 @@ impl AudioBackend for WasapiBackend {
             if let Some(ref b) = in_b_bundle {
                 if b.format.sample_rate != out_format.sample_rate || b.format.channels == 0 {
                     (*out_bundle.audio_client).Release();
+                    for (_, b, _) in &extra_bundles { (*b.audio_client).Release(); }
+                    release_inputs();
                     return Err(BackendError::StartError(format!(
                         "Input B sample rate mismatch ({} Hz vs output {} Hz)",
                         b.format.sample_rate,
@@ -565,273 +890,97 @@ impl AudioBackend for WasapiBackend {
                 }
             }
 
+            // Device channel count behind each slot, and the slot's own count
+            let dev_a = in_a_bundle.as_ref().map(|b| b.format.channels as usize);
+            let dev_b = if shared_input { dev_a } else { in_b_bundle.as_ref().map(|b| b.format.channels as usize) };
+            for (dev, range) in [(dev_a, range_a), (dev_b, range_b)] {
+                if let (Some(dev), Some(range)) = (dev, range) {
+                    if let Err(e) = range.validate(dev) {
+                        (*out_bundle.audio_client).Release();
+                        for (_, b, _) in &extra_bundles { (*b.audio_client).Release(); }
+                        release_inputs();
+                        return Err(e);
+                    }
+                }
+            }
+            let slot_channels = |dev: Option<usize>, range: Option<ChannelRange>| dev.map(|d| range.map(|r| r.count as usize).unwrap_or(d));
+
             // Create ringbuffers
-            let in_a_channels = in_a_bundle.as_ref().map(|b| b.format.channels as usize).unwrap_or(0).max(1);
-            let in_b_channels = in_b_bundle.as_ref().map(|b| b.format.channels as usize).unwrap_or(0).max(1);
+            let in_a_channels = if soft_a.is_some() { SOURCE_CHANNELS } else { slot_channels(dev_a, range_a).unwrap_or(0).max(1) };
+            let in_b_channels = if soft_b.is_some() { SOURCE_CHANNELS } else { slot_channels(dev_b, range_b).unwrap_or(0).max(1) };
+
+            let has_a = dev_a.is_some() || soft_a.is_some();
+            let has_b = dev_b.is_some() || soft_b.is_some();
 
             let rb_a = HeapRb::<f32>::new(BUFFER_FRAMES * in_a_channels);
-            let (mut prod_a, mut cons_a) = rb_a.split();
+            let (prod_a, cons_a) = rb_a.split();
             let rb_b = HeapRb::<f32>::new(BUFFER_FRAMES * in_b_channels);
-            let (mut prod_b, mut cons_b) = rb_b.split();
+            let (prod_b, cons_b) = rb_b.split();
 
             // Spawn capture threads
+            let sample_rate = out_format.sample_rate;
+            let tap_format = |channels: usize| TapFormat { sample_rate, channels: channels as u16 };
+            controls.input_taps[0].set_format(if has_a { Some(tap_format(in_a_channels)) } else { None });
+            controls.input_taps[1].set_format(if has_b { Some(tap_format(in_b_channels)) } else { None });
+            let buffer_frames = |b: &Option<ClientBundle>| b.as_ref().map(|b| b.buffer_frames as usize).unwrap_or(0);
+            let sink_a = if let Some(source) = soft_a {
+                sources.extend(SourceThread::start(source, 0, prod_a, controls.input_taps[0].clone(), controls.clone(), sample_rate));
+                None
+            } else {
+                Some(CaptureSink::new(prod_a, controls.input_taps[0].clone(), buffer_frames(&in_a_bundle) * in_a_channels)
+                    .select(dev_a.unwrap_or(1), range_a))
+            };
+            let frames_b = if shared_input { buffer_frames(&in_a_bundle) } else { buffer_frames(&in_b_bundle) };
+            let mut sink_b = if let Some(source) = soft_b {
+                sources.extend(SourceThread::start(source, 1, prod_b, controls.input_taps[1].clone(), controls.clone(), sample_rate));
+                None
+            } else {
+                Some(CaptureSink::new(prod_b, controls.input_taps[1].clone(), frames_b * in_b_channels)
+                    .select(dev_b.unwrap_or(1), range_b))
+            };
+            let mut sinks_a: Vec<CaptureSink> = sink_a.into_iter().collect();
+            if shared_input { sinks_a.extend(sink_b.take()); }
             if let Some(bundle) = in_a_bundle {
-                let stop_flag = self.stop_flag.clone();
-                let event = bundle.event;
-                self.event_handles.push(event);
-
-                let audio_client = bundle.audio_client as usize;
-                let format = bundle.format;
-                let task_name = WasapiBackend::to_wide("Pro Audio");
-                let event = event as usize;
-
-                let handle = thread::spawn(move || {
-                    unsafe { CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED); }
-                    let mut task_index: u32 = 0;
-                    let mmcss = unsafe { AvSetMmThreadCharacteristicsW(task_name.as_ptr(), &mut task_index) };
-
-                    let audio_client = audio_client as *mut IAudioClient;
-                    let event = event as HANDLE;
-                    let mut capture_client: *mut IAudioCaptureClient = ptr::null_mut();
-                    let hr = unsafe { (*audio_client).GetService(&IAudioCaptureClient::uuidof(), &mut capture_client as *mut _ as *mut _) };
-                    if FAILED(hr) {
-                        unsafe { (*audio_client).Release(); }
-                        if !mmcss.is_null() { unsafe { AvRevertMmThreadCharacteristics(mmcss); } }
-                        unsafe { CoUninitialize(); }
-                        return;
-                    }
-
-                    unsafe { (*audio_client).Start(); }
-
-                    while !stop_flag.load(Ordering::Relaxed) {
-                        let wait = unsafe { WaitForSingleObject(event, 2000) };
-                        if wait != WAIT_OBJECT_0 { continue; }
-
-                        let mut packet: u32 = 0;
-                        unsafe { (*capture_client).GetNextPacketSize(&mut packet); }
-                        while packet > 0 {
-                            let mut data: *mut u8 = ptr::null_mut();
-                            let mut frames: u32 = 0;
-                            let mut flags: u32 = 0;
-                            let hr = unsafe { (*capture_client).GetBuffer(&mut data, &mut frames, &mut flags, ptr::null_mut(), ptr::null_mut()) };
-                            if FAILED(hr) { break; }
-
-                            let channels = format.channels as usize;
-                            if flags & AUDCLNT_BUFFERFLAGS_SILENT != 0 {
-                                for _ in 0..(frames as usize * channels) { let _ = prod_a.push(0.0); }
-                            } else {
-                                let total = frames as usize * channels;
-                                if format.is_float && format.bits_per_sample == 32 {
-                                    let samples = unsafe { std::slice::from_raw_parts(data as *const f32, total) };
-                                    for s in samples.iter().take(total) { let _ = prod_a.push(*s); }
-                                } else if !format.is_float && format.bits_per_sample == 16 {
-                                    let samples = unsafe { std::slice::from_raw_parts(data as *const i16, total) };
-                                    for s in samples.iter().take(total) { let _ = prod_a.push(*s as f32 / 32768.0); }
-                                } else if !format.is_float && format.bits_per_sample == 32 {
-                                    let samples = unsafe { std::slice::from_raw_parts(data as *const i32, total) };
-                                    for s in samples.iter().take(total) { let _ = prod_a.push(*s as f32 / 2147483648.0); }
-                                }
-                            }
-
-                            unsafe { (*capture_client).ReleaseBuffer(frames); }
-                            unsafe { (*capture_client).GetNextPacketSize(&mut packet); }
-                        }
-                    }
-
-                    unsafe { (*audio_client).Stop(); }
-                    unsafe { (*capture_client).Release(); }
-                    unsafe { (*audio_client).Release(); }
-                    if !mmcss.is_null() { unsafe { AvRevertMmThreadCharacteristics(mmcss); } }
-                    unsafe { CoUninitialize(); }
-                });
-
-                threads.push(handle);
+                threads.push(self.spawn_capture_thread(bundle, sinks_a));
             }
-
             if let Some(bundle) = in_b_bundle {
-                let stop_flag = self.stop_flag.clone();
-                let event = bundle.event;
-                self.event_handles.push(event);
-
-                let audio_client = bundle.audio_client as usize;
-                let format = bundle.format;
-                let task_name = WasapiBackend::to_wide("Pro Audio");
-                let event = event as usize;
-
-                let handle = thread::spawn(move || {
-                    unsafe { CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED); }
-                    let mut task_index: u32 = 0;
-                    let mmcss = unsafe { AvSetMmThreadCharacteristicsW(task_name.as_ptr(), &mut task_index) };
-
-                    let audio_client = audio_client as *mut IAudioClient;
-                    let event = event as HANDLE;
-                    let mut capture_client: *mut IAudioCaptureClient = ptr::null_mut();
-                    let hr = unsafe { (*audio_client).GetService(&IAudioCaptureClient::uuidof(), &mut capture_client as *mut _ as *mut _) };
-                    if FAILED(hr) {
-                        unsafe { (*audio_client).Release(); }
-                        if !mmcss.is_null() { unsafe { AvRevertMmThreadCharacteristics(mmcss); } }
-                        unsafe { CoUninitialize(); }
-                        return;
-                    }
-
-                    unsafe { (*audio_client).Start(); }
-
-                    while !stop_flag.load(Ordering::Relaxed) {
-                        let wait = unsafe { WaitForSingleObject(event, 2000) };
-                        if wait != WAIT_OBJECT_0 { continue; }
-
-                        let mut packet: u32 = 0;
-                        unsafe { (*capture_client).GetNextPacketSize(&mut packet); }
-                        while packet > 0 {
-                            let mut data: *mut u8 = ptr::null_mut();
-                            let mut frames: u32 = 0;
-                            let mut flags: u32 = 0;
-                            let hr = unsafe { (*capture_client).GetBuffer(&mut data, &mut frames, &mut flags, ptr::null_mut(), ptr::null_mut()) };
-                            if FAILED(hr) { break; }
-
-                            let channels = format.channels as usize;
-                            if flags & AUDCLNT_BUFFERFLAGS_SILENT != 0 {
-                                for _ in 0..(frames as usize * channels) { let _ = prod_b.push(0.0); }
-                            } else {
-                                let total = frames as usize * channels;
-                                if format.is_float && format.bits_per_sample == 32 {
-                                    let samples = unsafe { std::slice::from_raw_parts(data as *const f32, total) };
-                                    for s in samples.iter().take(total) { let _ = prod_b.push(*s); }
-                                } else if !format.is_float && format.bits_per_sample == 16 {
-                                    let samples = unsafe { std::slice::from_raw_parts(data as *const i16, total) };
-                                    for s in samples.iter().take(total) { let _ = prod_b.push(*s as f32 / 32768.0); }
-                                } else if !format.is_float && format.bits_per_sample == 32 {
-                                    let samples = unsafe { std::slice::from_raw_parts(data as *const i32, total) };
-                                    for s in samples.iter().take(total) { let _ = prod_b.push(*s as f32 / 2147483648.0); }
-                                }
-                            }
-
-                            unsafe { (*capture_client).ReleaseBuffer(frames); }
-                            unsafe { (*capture_client).GetNextPacketSize(&mut packet); }
-                        }
-                    }
-
-                    unsafe { (*audio_client).Stop(); }
-                    unsafe { (*capture_client).Release(); }
-                    unsafe { (*audio_client).Release(); }
-                    if !mmcss.is_null() { unsafe { AvRevertMmThreadCharacteristics(mmcss); } }
-                    unsafe { CoUninitialize(); }
-                });
-
-                threads.push(handle);
+                threads.push(self.spawn_capture_thread(bundle, sink_b.into_iter().collect()));
             }
 
-            // Output thread
-            {
-                let stop_flag = self.stop_flag.clone();
-                let event = out_bundle.event;
-                self.event_handles.push(event);
-
-                let audio_client = out_bundle.audio_client as usize;
-                let format = out_bundle.format;
-                let buffer_frames = out_bundle.buffer_frames;
-                let task_name = WasapiBackend::to_wide("Pro Audio");
-                let event = event as usize;
-                let in_a_channels = in_a_channels;
-                let in_b_channels = in_b_channels;
-
-                let handle = thread::spawn(move || {
-                    unsafe { CoInitializeEx(ptr::null_mut(), COINIT_MULTITHREADED); }
-                    let mut task_index: u32 = 0;
-                    let mmcss = unsafe { AvSetMmThreadCharacteristicsW(task_name.as_ptr(), &mut task_index) };
-
-                    let audio_client = audio_client as *mut IAudioClient;
-                    let event = event as HANDLE;
-                    let mut render_client: *mut IAudioRenderClient = ptr::null_mut();
-                    let hr = unsafe { (*audio_client).GetService(&IAudioRenderClient::uuidof(), &mut render_client as *mut _ as *mut _) };
-                    if FAILED(hr) {
-                        unsafe { (*audio_client).Release(); }
-                        if !mmcss.is_null() { unsafe { AvRevertMmThreadCharacteristics(mmcss); } }
-                        unsafe { CoUninitialize(); }
-                        return;
-                    }
-
-                    unsafe { (*audio_client).Start(); }
-
-                    while !stop_flag.load(Ordering::Relaxed) {
-                        let wait = unsafe { WaitForSingleObject(event, 2000) };
-                        if wait != WAIT_OBJECT_0 { continue; }
-
-                        let mut padding: u32 = 0;
-                        let hr = unsafe { (*audio_client).GetCurrentPadding(&mut padding) };
-                        if FAILED(hr) { continue; }
-
-                        let frames_avail = buffer_frames.saturating_sub(padding);
-                        if frames_avail == 0 { continue; }
-
-                        let mut data: *mut u8 = ptr::null_mut();
-                        let hr = unsafe { (*render_client).GetBuffer(frames_avail, &mut data) };
-                        if FAILED(hr) { continue; }
-
-                        let channels = format.channels as usize;
-                        let total = frames_avail as usize * channels;
-
-                        let use_a = listen_a.load(Ordering::Relaxed);
-                        let use_b = listen_b.load(Ordering::Relaxed);
-
-                        let mut frame_a = vec![0.0f32; in_a_channels];
-                        let mut frame_b = vec![0.0f32; in_b_channels];
-
-                        if format.is_float && format.bits_per_sample == 32 {
-                            let samples = unsafe { std::slice::from_raw_parts_mut(data as *mut f32, total) };
-                            for f in 0..frames_avail as usize {
-                                for i in 0..in_a_channels { frame_a[i] = if use_a { cons_a.pop().unwrap_or(0.0) } else { let _ = cons_a.pop(); 0.0 }; }
-                                for i in 0..in_b_channels { frame_b[i] = if use_b { cons_b.pop().unwrap_or(0.0) } else { let _ = cons_b.pop(); 0.0 }; }
-                                for ch in 0..channels {
-                                    let a = if in_a_channels == 0 { 0.0 } else if ch < in_a_channels { frame_a[ch] } else { frame_a[0] };
-                                    let b = if in_b_channels == 0 { 0.0 } else if ch < in_b_channels { frame_b[ch] } else { frame_b[0] };
-                                    samples[f * channels + ch] = a + b;
-                                }
-                            }
-                        } else if !format.is_float && format.bits_per_sample == 16 {
-                            let samples = unsafe { std::slice::from_raw_parts_mut(data as *mut i16, total) };
-                            for f in 0..frames_avail as usize {
-                                for i in 0..in_a_channels { frame_a[i] = if use_a { cons_a.pop().unwrap_or(0.0) } else { let _ = cons_a.pop(); 0.0 }; }
-                                for i in 0..in_b_channels { frame_b[i] = if use_b { cons_b.pop().unwrap_or(0.0) } else { let _ = cons_b.pop(); 0.0 }; }
-                                for ch in 0..channels {
-                                    let a = if in_a_channels == 0 { 0.0 } else if ch < in_a_channels { frame_a[ch] } else { frame_a[0] };
-                                    let b = if in_b_channels == 0 { 0.0 } else if ch < in_b_channels { frame_b[ch] } else { frame_b[0] };
-                                    let mixed = (a + b).clamp(-1.0, 1.0);
-                                    samples[f * channels + ch] = (mixed * 32767.0) as i16;
-                                }
-                            }
-                        } else if !format.is_float && format.bits_per_sample == 32 {
-                            let samples = unsafe { std::slice::from_raw_parts_mut(data as *mut i32, total) };
-                            for f in 0..frames_avail as usize {
-                                for i in 0..in_a_channels { frame_a[i] = if use_a { cons_a.pop().unwrap_or(0.0) } else { let _ = cons_a.pop(); 0.0 }; }
-                                for i in 0..in_b_channels { frame_b[i] = if use_b { cons_b.pop().unwrap_or(0.0) } else { let _ = cons_b.pop(); 0.0 }; }
-                                for ch in 0..channels {
-                                    let a = if in_a_channels == 0 { 0.0 } else if ch < in_a_channels { frame_a[ch] } else { frame_a[0] };
-                                    let b = if in_b_channels == 0 { 0.0 } else if ch < in_b_channels { frame_b[ch] } else { frame_b[0] };
-                                    let mixed = (a + b).clamp(-1.0, 1.0);
-                                    samples[f * channels + ch] = (mixed * 2147483647.0) as i32;
-                                }
-                            }
-                        }
-
-                        unsafe { (*render_client).ReleaseBuffer(frames_avail, 0); }
-                    }
-
-                    unsafe { (*audio_client).Stop(); }
-                    unsafe { (*render_client).Release(); }
-                    unsafe { (*audio_client).Release(); }
-                    if !mmcss.is_null() { unsafe { AvRevertMmThreadCharacteristics(mmcss); } }
-                    unsafe { CoUninitialize(); }
-                });
-
-                threads.push(handle);
+            // Output threads: extra outputs play a copy of the mix, cue buses their own mix,
+            // each through a ring
+            let mix_channels = out_range.map(|r| r.count).unwrap_or(out_format.channels);
+            controls.output_tap.set_format(Some(TapFormat { sample_rate, channels: mix_channels }));
+            let mix_channels = mix_channels as usize;
+            let mut feeds = Vec::new();
+            let mut bus_mixes = Vec::new();
+            for (slot, bundle, range) in extra_bundles {
+                let channels = match slot {
+                    OutputParams::Output(_) => mix_channels,
+                    OutputParams::CueBus(_) => range.map(|r| r.count).unwrap_or(bundle.format.channels) as usize,
+                };
+                let (producer, consumer) = HeapRb::<f32>::new(BUFFER_FRAMES * channels).split();
+                match slot {
+                    OutputParams::Output(_) => feeds.push(producer),
+                    OutputParams::CueBus(bus) => bus_mixes.push(CueBusMix::new(bus, channels, producer, sample_rate)),
+                }
+                let stage = OutputStage::new(slot, channels, bundle.format.channels as usize, range, sample_rate);
+                threads.push(self.spawn_render_thread(bundle, OutputFeed::new(controls.clone(), consumer, stage)));
             }
 
+            let engine = MixEngine::new(controls.clone(), vec![
+                if has_a { Some(EngineInput::new(cons_a, in_a_channels, sample_rate)) } else { None },
+                if has_b { Some(EngineInput::new(cons_b, in_b_channels, sample_rate)) } else { None },
+            ], OutputStage::new(OutputParams::Output(0), mix_channels, out_format.channels as usize, out_range, sample_rate), sample_rate, out_bundle.buffer_frames as usize)
+                .with_feeds(feeds)
+                .with_cue_buses(bus_mixes);
+            threads.push(self.spawn_render_thread(out_bundle, engine));
         }
 
         WasapiBackend::com_uninit(should_uninit);
 
         self.threads = threads;
+        self.sources = sources;
         Ok(())
     }
 
@@ -845,6 +994,7 @@ impl AudioBackend for WasapiBackend {
         for t in self.threads.drain(..) {
             let _ = t.join();
         }
+        self.sources.clear();
 
         Ok(())
     }
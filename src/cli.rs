@@ -0,0 +1,161 @@
+use crate::audio::backend::{DeviceEntry, Mode};
+use crate::record::RecordFormat;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage:
+  external_cue                       start the GUI
+  external_cue list-devices [--json] print the device list and exit
+                                     (--json adds supported rates/channels/formats)
+  external_cue run [options]         route audio without a window (Ctrl+C to stop)
+
+Run options:
+  --config <path>      load routing from a JSON config file (flags below override it)
+  --input-a <id>       input A device, generator for the test signal generator, or
+                       file:<path> to play a WAV/FLAC/MP3/OGG file
+  --input-b <id>       input B device (or generator, file:<path>)
+  --output <id>        output device (required)
+  --channels-a <n-m>   use only channels n to m of input A's device (e.g. 3-4); give
+  --channels-b <n-m>   both inputs the same device to split it into two inputs
+  --output-channels <n-m>
+                       send the mix to channels n to m of the output (others silent)
+  --listen <a|b|ab|none>
+                       which inputs are audible at start (default: every selected input)
+  --api-port <port>    also start the remote control API on this port
+  --api-bind <addr>    address the API listens on (default 127.0.0.1, localhost only;
+                       0.0.0.0 for the LAN)
+  --api-token <token>  require this token in the X-Api-Token header of API requests
+  --meter-interval <ms>
+                       how often a meter line is printed (default 1000, 0 disables)
+  --record <wav|flac>  record the output mix to recordings/ while running
+  --multitrack         with --record: also record each input to its own file
+  --history <seconds>  keep the last N seconds in memory for /api/history/save
+                       (default 120, 0 disables)
+
+A device <id> is the index printed by list-devices, the exact device name, or the
+device ID optionally followed by :shared or :exclusive (shared if omitted).";
+
+#[derive(Debug)]
+pub enum CliCommand {
+    Gui,
+    ListDevices { json: bool },
+    Run(Box<RunArgs>),
+}
+
+#[derive(Debug, Default)]
+pub struct RunArgs {
+    pub config: Option<PathBuf>,
+    pub input_a: Option<String>,
+    pub input_b: Option<String>,
+    pub output: Option<String>,
+    pub channels_a: Option<String>,
+    pub channels_b: Option<String>,
+    pub output_channels: Option<String>,
+    pub listen: Option<String>,
+    pub api_port: Option<u16>,
+    pub api_bind: Option<String>,
+    pub api_token: Option<String>,
+    pub meter_interval_ms: Option<u64>,
+    pub record: Option<RecordFormat>,
+    pub multitrack: bool,
+    pub history_seconds: Option<u32>,
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<CliCommand, String> {
+    let sub = match args.next() {
+        None => return Ok(CliCommand::Gui),
+        Some(s) => s,
+    };
+
+    match sub.as_str() {
+        "list-devices" => {
+            let mut json = false;
+            for arg in args {
+                match arg.as_str() {
+                    "--json" => json = true,
+                    _ => return Err(format!("Unexpected argument for list-devices: {}", arg)),
+                }
+            }
+            Ok(CliCommand::ListDevices { json })
+        }
+        "run" => {
+            let mut run = RunArgs::default();
+            while let Some(flag) = args.next() {
+                let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", flag));
+                match flag.as_str() {
+                    "--config" => run.config = Some(PathBuf::from(value()?)),
+                    "--input-a" => run.input_a = Some(value()?),
+                    "--input-b" => run.input_b = Some(value()?),
+                    "--output" => run.output = Some(value()?),
+                    "--channels-a" => run.channels_a = Some(value()?),
+                    "--channels-b" => run.channels_b = Some(value()?),
+                    "--output-channels" => run.output_channels = Some(value()?),
+                    "--listen" => run.listen = Some(value()?),
+                    "--api-port" => {
+                        let v = value()?;
+                        run.api_port = Some(v.parse().map_err(|_| format!("Invalid port: {}", v))?);
+                    }
+                    "--api-bind" => run.api_bind = Some(value()?),
+                    "--api-token" => run.api_token = Some(value()?),
+                    "--meter-interval" => {
+                        let v = value()?;
+                        run.meter_interval_ms = Some(v.parse().map_err(|_| format!("Invalid interval: {}", v))?);
+                    }
+                    "--multitrack" => run.multitrack = true,
+                    "--history" => {
+                        let v = value()?;
+                        run.history_seconds = Some(v.parse().map_err(|_| format!("Invalid history length: {}", v))?);
+                    }
+                    "--record" => {
+                        let v = value()?;
+                        run.record = Some(RecordFormat::parse(&v).ok_or_else(|| format!("Invalid record format: {}", v))?);
+                    }
+                    _ => return Err(format!("Unknown option for run: {}", flag)),
+                }
+            }
+            Ok(CliCommand::Run(Box::new(run)))
+        }
+        "-h" | "--help" | "help" => Err(String::new()),
+        other => Err(format!("Unknown command: {}", other)),
+    }
+}
+
+/// Parse a `--listen` value into (listen_a, listen_b).
+pub fn parse_listen(value: &str) -> Result<(bool, bool), String> {
+    match value.to_ascii_lowercase().as_str() {
+        "a" => Ok((true, false)),
+        "b" => Ok((false, true)),
+        "ab" | "both" => Ok((true, true)),
+        "none" | "" => Ok((false, false)),
+        other => Err(format!("Invalid --listen value: {}", other)),
+    }
+}
+
+/// Find the entry a device spec refers to. `accepts` filters by direction so an input
+/// spec never resolves to a render-only endpoint with the same name.
+pub fn resolve_device<F>(entries: &[DeviceEntry], spec: &str, accepts: F) -> Result<usize, String>
+    where F: Fn(&DeviceEntry) -> bool
+{
+    if let Ok(idx) = spec.parse::<usize>() {
+        return match entries.get(idx) {
+            Some(d) if accepts(d) => Ok(idx),
+            Some(d) => Err(format!("Device {} ('{}') cannot be used here", idx, d.name)),
+            None => Err(format!("Device index {} out of range (0..{})", idx, entries.len())),
+        };
+    }
+
+    if let Some(idx) = entries.iter().position(|d| accepts(d) && d.name.eq_ignore_ascii_case(spec)) {
+        return Ok(idx);
+    }
+
+    let (id, mode) = if let Some(id) = spec.strip_suffix(":exclusive") {
+        (id, Mode::Exclusive)
+    } else if let Some(id) = spec.strip_suffix(":shared") {
+        (id, Mode::Shared)
+    } else {
+        (spec, Mode::Shared)
+    };
+    entries.iter()
+        .position(|d| accepts(d) && d.mode == mode && d.device_id.as_deref() == Some(id))
+        .ok_or_else(|| format!("No matching device for '{}' (see list-devices)", spec))
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Router setup loaded from a JSON file (`--config <path>`).
+///
+/// Devices are given as a device spec (see `resolve_device`): a list index, the exact
+/// entry name, or a platform device ID optionally suffixed with `:shared` / `:exclusive`.
+/// An input given as `"generator"` plays that slot's test signal generator instead, and
+/// `"file:<path>"` plays an audio file (WAV, FLAC, MP3 or OGG) from the start.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub input_a: Option<String>,
+    pub input_b: Option<String>,
+    pub output: Option<String>,
+    /// Channel subset of an input device, "3-4" or "3" (1-based).
+    pub channels_a: Option<String>,
+    pub channels_b: Option<String>,
+    pub output_channels: Option<String>,
+    /// Volume trim and latency compensation of the main output.
+    pub output_volume_db: Option<f32>,
+    pub output_delay_ms: Option<f32>,
+    /// Further outputs playing a copy of the mix (at most two).
+    pub extra_outputs: Vec<ExtraOutput>,
+    /// Cue buses with their own output and listen state (at most two), e.g. a second DJ's
+    /// headphones.
+    pub cue_buses: Vec<CueBus>,
+    pub listen_a: Option<bool>,
+    pub listen_b: Option<bool>,
+    /// Channel routing of input A: one row per input channel with a gain per output channel
+    /// (as returned by `GET /api/matrix`). The default mapping applies when omitted.
+    pub matrix_a: Option<Vec<Vec<f32>>>,
+    pub matrix_b: Option<Vec<Vec<f32>>>,
+    /// Test signal of each input slot, used when the input is `"generator"`.
+    pub generator_a: Option<Generator>,
+    pub generator_b: Option<Generator>,
+    /// Loop an input's file instead of stopping at its end.
+    pub loop_a: Option<bool>,
+    pub loop_b: Option<bool>,
+    /// Alignment delay of each input, in ms.
+    pub delay_a_ms: Option<f32>,
+    pub delay_b_ms: Option<f32>,
+    /// Sample pads, in pad order (at most eight).
+    pub pads: Vec<Pad>,
+    /// Pads are heard on the main mix (default on).
+    pub pads_listen: Option<bool>,
+    /// Metronome click (cue buses hear it with `listen_click`).
+    pub click: Option<Click>,
+    /// Start the remote API on this port.
+    pub api_port: Option<u16>,
+    /// Address the API listens on (localhost when omitted; "0.0.0.0" for the LAN).
+    pub api_bind: Option<String>,
+    /// Token API requests must carry in the `X-Api-Token` header.
+    pub api_token: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("Invalid config {}: {}", path.display(), e))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExtraOutput {
+    pub device: String,
+    pub channels: Option<String>,
+    pub volume_db: Option<f32>,
+    pub delay_ms: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CueBus {
+    pub device: String,
+    pub channels: Option<String>,
+    pub listen_a: Option<bool>,
+    pub listen_b: Option<bool>,
+    pub gain_a_db: Option<f32>,
+    pub gain_b_db: Option<f32>,
+    pub listen_pads: Option<bool>,
+    pub listen_click: Option<bool>,
+    pub volume_db: Option<f32>,
+    pub delay_ms: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Generator {
+    /// "sine", "white", "pink", "sweep" or "channel_id".
+    pub kind: Option<String>,
+    pub frequency: Option<f32>,
+    pub level_db: Option<f32>,
+    pub sweep_start: Option<f32>,
+    pub sweep_end: Option<f32>,
+    pub sweep_seconds: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Pad {
+    /// Audio file to load; an empty pad when omitted.
+    pub path: Option<String>,
+    pub gain_db: Option<f32>,
+    /// Choke group 1-4, 0 for none.
+    pub choke: Option<usize>,
+    /// Voices of the pad that may play at once.
+    pub polyphony: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Click {
+    /// Heard on the main mix.
+    pub listen: Option<bool>,
+    pub bpm: Option<f32>,
+    /// Beats per bar.
+    pub beats: Option<usize>,
+    /// Accented beats, counted from 1.
+    pub accents: Option<Vec<usize>>,
+    /// "beep", "wood" or "tick".
+    pub sound: Option<String>,
+    pub level_db: Option<f32>,
+}
@@ -0,0 +1,55 @@
+// Second-order sections (RBJ cookbook coefficients, transposed direct form II).
+
+use std::f32::consts::PI;
+
+/// Normalised coefficients (a0 = 1).
+#[derive(Debug, Clone, Copy)]
+pub struct Coeffs {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coeffs {
+    pub fn lowpass(sample_rate: u32, freq: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, freq, q);
+        Self::normalise((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    pub fn highpass(sample_rate: u32, freq: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, freq, q);
+        Self::normalise((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    pub fn allpass(sample_rate: u32, freq: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, freq, q);
+        Self::normalise(1.0 - alpha, -2.0 * cos, 1.0 + alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    fn prewarp(sample_rate: u32, freq: f32, q: f32) -> (f32, f32) {
+        let w0 = 2.0 * PI * (freq / sample_rate as f32).min(0.49);
+        (w0.cos(), w0.sin() / (2.0 * q))
+    }
+
+    fn normalise(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Self { b0: b0 / a0, b1: b1 / a0, b2: b2 / a0, a1: a1 / a0, a2: a2 / a0 }
+    }
+}
+
+/// State of one section for one channel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Biquad {
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    pub fn process(&mut self, c: &Coeffs, x: f32) -> f32 {
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
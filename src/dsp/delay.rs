@@ -0,0 +1,35 @@
+// Fixed-capacity delay line for interleaved blocks, used to time-align inputs that reach the
+// mix through paths with different latency.
+
+pub struct DelayLine {
+    channels: usize,
+    // Ring of past frames, `capacity` frames long
+    buffer: Vec<f32>,
+    capacity: usize,
+    pos: usize,
+}
+
+impl DelayLine {
+    /// Holds up to `max_frames` frames of delay.
+    pub fn new(max_frames: usize, channels: usize) -> Self {
+        let channels = channels.max(1);
+        let capacity = max_frames + 1;
+        Self { channels, buffer: vec![0.0; capacity * channels], capacity, pos: 0 }
+    }
+
+    /// Delay an interleaved block in place by `frames` frames (clamped to the capacity).
+    /// Changing the delay jumps straight to the new position.
+    pub fn process(&mut self, block: &mut [f32], frames: usize) {
+        let delay = frames.min(self.capacity - 1);
+        let channels = self.channels;
+        for frame in block.chunks_exact_mut(channels) {
+            let write = self.pos * channels;
+            self.buffer[write..write + channels].copy_from_slice(frame);
+            if delay > 0 {
+                let read = (self.pos + self.capacity - delay) % self.capacity * channels;
+                frame.copy_from_slice(&self.buffer[read..read + channels]);
+            }
+            self.pos = (self.pos + 1) % self.capacity;
+        }
+    }
+}
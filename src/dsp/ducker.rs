@@ -0,0 +1,58 @@
+// Talkover: a microphone input pushes the rest of the mix down while someone speaks.
+//
+// A peak follower on the key (microphone) decides whether the key is "open"; the gain for
+// the other inputs then moves towards the ducking depth with the attack time, and back to
+// unity with the release time.
+
+use crate::audio::controls::TalkoverControls;
+use std::sync::atomic::Ordering;
+
+// Decay of the key detector, so gaps between words don't release the duck
+const DETECTOR_RELEASE_SECONDS: f32 = 0.15;
+
+pub struct Ducker {
+    sample_rate: f32,
+    envelope: f32,
+    gain: f32,
+    detector_release: f32,
+}
+
+impl Ducker {
+    pub fn new(sample_rate: u32) -> Self {
+        let sample_rate = sample_rate as f32;
+        Self {
+            sample_rate,
+            envelope: 0.0,
+            gain: 1.0,
+            detector_release: (-1.0 / (DETECTOR_RELEASE_SECONDS * sample_rate)).exp(),
+        }
+    }
+
+    fn coeff(&self, ms: f32) -> f32 {
+        1.0 - (-1.0 / (ms.max(0.1) * 0.001 * self.sample_rate)).exp()
+    }
+
+    /// Fill `gains` with the per-frame gain for the ducked inputs, given the key's interleaved
+    /// block (`None` when the key input isn't running). Publishes the current reduction.
+    pub fn process(&mut self, key: Option<(&[f32], usize)>, gains: &mut [f32], controls: &TalkoverControls) {
+        let enabled = controls.enabled.load(Ordering::Relaxed);
+        let threshold = 10f32.powf(controls.threshold_db.load() / 20.0);
+        let depth = 10f32.powf(controls.depth_db.load().min(0.0) / 20.0);
+        let attack = self.coeff(controls.attack_ms.load());
+        let release = self.coeff(controls.release_ms.load());
+
+        for (f, gain) in gains.iter_mut().enumerate() {
+            let peak = match key {
+                Some((block, channels)) if enabled => {
+                    block[f * channels..(f + 1) * channels].iter().fold(0.0f32, |m, s| m.max(s.abs()))
+                }
+                _ => 0.0,
+            };
+            self.envelope = peak.max(self.envelope * self.detector_release);
+            let (target, coeff) = if self.envelope > threshold { (depth, attack) } else { (1.0, release) };
+            self.gain += (target - self.gain) * coeff;
+            *gain = self.gain;
+        }
+        controls.reduction_db.store(20.0 * self.gain.max(1e-6).log10());
+    }
+}
@@ -0,0 +1,114 @@
+// DJ-style three-band isolator EQ.
+//
+// The signal is split with 4th-order Linkwitz-Riley crossovers at 250 Hz and 2.5 kHz; the
+// low band also runs through the 2.5 kHz allpass so the three bands sum back flat at unity.
+// Each band then gets its own gain, which can go all the way to zero (kill).
+
+use super::biquad::{Biquad, Coeffs};
+use super::Smoother;
+use crate::audio::controls::InputControls;
+
+pub const LOW_MID_HZ: f32 = 250.0;
+pub const MID_HIGH_HZ: f32 = 2500.0;
+/// Knob range in dB; the bottom of the range is a full kill.
+pub const MIN_DB: f32 = -26.0;
+pub const MAX_DB: f32 = 6.0;
+// Butterworth Q; two in series make a Linkwitz-Riley section
+const Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+const SMOOTHING_SECONDS: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Band {
+    Low = 0,
+    Mid = 1,
+    High = 2,
+}
+
+impl Band {
+    pub const ALL: [Band; 3] = [Band::Low, Band::Mid, Band::High];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Band::Low => "Low",
+            Band::Mid => "Mid",
+            Band::High => "High",
+        }
+    }
+
+    /// Short name used in parameter ids ("a.eq.low").
+    pub fn key(self) -> &'static str {
+        match self {
+            Band::Low => "low",
+            Band::Mid => "mid",
+            Band::High => "high",
+        }
+    }
+}
+
+/// Linear band gain for a knob position in dB.
+pub fn gain_from_db(db: f32) -> f32 {
+    if db <= MIN_DB { 0.0 } else { 10f32.powf(db.min(MAX_DB) / 20.0) }
+}
+
+struct Coefficients {
+    low_lp: Coeffs,
+    low_hp: Coeffs,
+    high_lp: Coeffs,
+    high_hp: Coeffs,
+    high_ap: Coeffs,
+}
+
+// Per-channel filter state, named after the coefficients they run
+#[derive(Default, Clone, Copy)]
+struct ChannelState {
+    low_lp: [Biquad; 2],
+    low_hp: [Biquad; 2],
+    high_lp: [Biquad; 2],
+    high_hp: [Biquad; 2],
+    high_ap: Biquad,
+}
+
+pub struct ThreeBandEq {
+    coeffs: Coefficients,
+    channels: Vec<ChannelState>,
+    gains: [Smoother; 3],
+}
+
+impl ThreeBandEq {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            coeffs: Coefficients {
+                low_lp: Coeffs::lowpass(sample_rate, LOW_MID_HZ, Q),
+                low_hp: Coeffs::highpass(sample_rate, LOW_MID_HZ, Q),
+                high_lp: Coeffs::lowpass(sample_rate, MID_HIGH_HZ, Q),
+                high_hp: Coeffs::highpass(sample_rate, MID_HIGH_HZ, Q),
+                high_ap: Coeffs::allpass(sample_rate, MID_HIGH_HZ, Q),
+            },
+            channels: vec![ChannelState::default(); channels.max(1)],
+            gains: [Smoother::new(1.0, SMOOTHING_SECONDS, sample_rate); 3],
+        }
+    }
+
+    /// Filter an interleaved block in place.
+    pub fn process(&mut self, block: &mut [f32], controls: &InputControls) {
+        let targets = Band::ALL.map(|band| controls.eq_target(band));
+        let c = &self.coeffs;
+        let channels = self.channels.len();
+        for frame in block.chunks_exact_mut(channels) {
+            let [g_low, g_mid, g_high] = [0, 1, 2].map(|i| self.gains[i].next(targets[i]));
+            for (x, st) in frame.iter_mut().zip(self.channels.iter_mut()) {
+                let low = st.high_ap.process(&c.high_ap, cascade(&mut st.low_lp, &c.low_lp, *x));
+                let rest = cascade(&mut st.low_hp, &c.low_hp, *x);
+                let mid = cascade(&mut st.high_lp, &c.high_lp, rest);
+                let high = cascade(&mut st.high_hp, &c.high_hp, rest);
+                *x = g_low * low + g_mid * mid + g_high * high;
+            }
+        }
+    }
+}
+
+// Two identical Butterworth sections: one Linkwitz-Riley 4th-order filter
+fn cascade(sections: &mut [Biquad; 2], c: &Coeffs, x: f32) -> f32 {
+    let y = sections[0].process(c, x);
+    sections[1].process(c, y)
+}
@@ -0,0 +1,129 @@
+// Bipolar DJ filter: one knob, low-pass to the left, high-pass to the right, bypass in the
+// middle.
+//
+// A trapezoidal state-variable filter (Simper's SVF) stays stable while its cutoff moves
+// every sample, so the knob position and resonance are smoothed per sample instead of
+// stepping once per block.
+
+use super::Smoother;
+use crate::audio::controls::InputControls;
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+// Cutoff at the ends of each half of the knob
+const LP_MIN_HZ: f32 = 40.0;
+const LP_MAX_HZ: f32 = 20_000.0;
+const HP_MIN_HZ: f32 = 20.0;
+const HP_MAX_HZ: f32 = 10_000.0;
+// Q at full resonance
+const MAX_Q: f32 = 10.0;
+// Around the centre the filter fades in, so crossing it neither clicks nor resonates
+const CENTRE_FADE: f32 = 0.05;
+const SMOOTHING_SECONDS: f32 = 0.02;
+// Coefficients are only recomputed while the smoothed values still move
+const SETTLED: f32 = 1e-5;
+
+/// Cutoff in Hz for a knob position (-1..1), or `None` at the centre.
+pub fn cutoff_hz(position: f32) -> Option<f32> {
+    if position <= -CENTRE_FADE / 2.0 {
+        Some(LP_MAX_HZ * (LP_MIN_HZ / LP_MAX_HZ).powf(-position))
+    } else if position >= CENTRE_FADE / 2.0 {
+        Some(HP_MIN_HZ * (HP_MAX_HZ / HP_MIN_HZ).powf(position))
+    } else {
+        None
+    }
+}
+
+/// "LP 1.2k" / "HP 300" / "off", for knob readouts.
+pub fn describe(position: f32) -> String {
+    match cutoff_hz(position) {
+        Some(hz) => {
+            let kind = if position < 0.0 { "LP" } else { "HP" };
+            if hz >= 1000.0 { format!("{} {:.1}k", kind, hz / 1000.0) } else { format!("{} {:.0}", kind, hz) }
+        }
+        None => "off".to_string(),
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct SvfState {
+    ic1: f32,
+    ic2: f32,
+}
+
+pub struct SweepFilter {
+    sample_rate: f32,
+    position: Smoother,
+    resonance: Smoother,
+    channels: Vec<SvfState>,
+    // Cached coefficients for the last smoothed values
+    last: (f32, f32),
+    g: f32,
+    k: f32,
+}
+
+impl SweepFilter {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let mut filter = Self {
+            sample_rate: sample_rate as f32,
+            position: Smoother::new(0.0, SMOOTHING_SECONDS, sample_rate),
+            resonance: Smoother::new(0.0, SMOOTHING_SECONDS, sample_rate),
+            channels: vec![SvfState::default(); channels.max(1)],
+            last: (f32::NAN, f32::NAN),
+            g: 0.0,
+            k: 0.0,
+        };
+        filter.update(0.0, 0.0);
+        filter
+    }
+
+    fn update(&mut self, position: f32, resonance: f32) {
+        if (position - self.last.0).abs() < SETTLED && (resonance - self.last.1).abs() < SETTLED {
+            return;
+        }
+        self.last = (position, resonance);
+        let hz = if position < 0.0 {
+            LP_MAX_HZ * (LP_MIN_HZ / LP_MAX_HZ).powf(-position)
+        } else {
+            HP_MIN_HZ * (HP_MAX_HZ / HP_MIN_HZ).powf(position)
+        };
+        self.g = (PI * hz.min(self.sample_rate * 0.45) / self.sample_rate).tan();
+        // Resonance only comes in away from the centre
+        let amount = resonance * (position.abs() / CENTRE_FADE).min(1.0);
+        let q = FRAC_1_SQRT_2 * (MAX_Q / FRAC_1_SQRT_2).powf(amount);
+        self.k = 1.0 / q;
+    }
+
+    /// Filter an interleaved block in place.
+    pub fn process(&mut self, block: &mut [f32], controls: &InputControls) {
+        let target_position = controls.filter.load().clamp(-1.0, 1.0);
+        let target_resonance = controls.filter_resonance.load().clamp(0.0, 1.0);
+        // Fully bypassed and settled: nothing to do
+        if target_position == 0.0 && self.position.next(0.0).abs() < SETTLED {
+            return;
+        }
+
+        let channels = self.channels.len();
+        for frame in block.chunks_exact_mut(channels) {
+            let position = self.position.next(target_position);
+            let resonance = self.resonance.next(target_resonance);
+            self.update(position, resonance);
+            let wet = (position.abs() / CENTRE_FADE).min(1.0);
+            let high_pass = position > 0.0;
+
+            let (g, k) = (self.g, self.k);
+            let a1 = 1.0 / (1.0 + g * (g + k));
+            let a2 = g * a1;
+            let a3 = g * a2;
+            for (x, st) in frame.iter_mut().zip(self.channels.iter_mut()) {
+                let v0 = *x;
+                let v3 = v0 - st.ic2;
+                let v1 = a1 * st.ic1 + a2 * v3;
+                let v2 = st.ic2 + a2 * st.ic1 + a3 * v3;
+                st.ic1 = 2.0 * v1 - st.ic1;
+                st.ic2 = 2.0 * v2 - st.ic2;
+                let filtered = if high_pass { v0 - k * v1 - v2 } else { v2 };
+                *x = v0 + (filtered - v0) * wet;
+            }
+        }
+    }
+}
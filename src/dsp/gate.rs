@@ -0,0 +1,83 @@
+// Noise gate: mutes an input while it stays below threshold, e.g. turntable rumble or
+// interface hiss between tracks.
+//
+// The gate opens when the peak envelope rises above the threshold and only closes once it
+// has fallen below threshold minus hysteresis and the hold time has run out, so it doesn't
+// chatter on signals hovering around the threshold.
+
+use crate::audio::controls::InputControls;
+use std::sync::atomic::Ordering;
+
+// Decay of the level detector
+const DETECTOR_RELEASE_SECONDS: f32 = 0.01;
+
+pub struct NoiseGate {
+    sample_rate: f32,
+    envelope: f32,
+    open: bool,
+    hold_left: usize,
+    gain: f32,
+    detector_release: f32,
+    channels: usize,
+}
+
+impl NoiseGate {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let sample_rate = sample_rate as f32;
+        Self {
+            sample_rate,
+            envelope: 0.0,
+            open: true,
+            hold_left: 0,
+            gain: 1.0,
+            detector_release: (-1.0 / (DETECTOR_RELEASE_SECONDS * sample_rate)).exp(),
+            channels: channels.max(1),
+        }
+    }
+
+    fn coeff(&self, ms: f32) -> f32 {
+        1.0 - (-1.0 / (ms.max(0.05) * 0.001 * self.sample_rate)).exp()
+    }
+
+    /// Gate an interleaved block in place and publish whether the gate is open.
+    pub fn process(&mut self, block: &mut [f32], controls: &InputControls) {
+        if !controls.gate_enabled.load(Ordering::Relaxed) {
+            // Fade back in rather than jumping if the gate was closed when switched off
+            if self.gain < 1.0 {
+                let release = self.coeff(5.0);
+                for frame in block.chunks_exact_mut(self.channels) {
+                    self.gain += (1.0 - self.gain) * release;
+                    frame.iter_mut().for_each(|s| *s *= self.gain);
+                }
+            }
+            self.open = true;
+            controls.gate_open.store(true, Ordering::Relaxed);
+            return;
+        }
+
+        let open_level = 10f32.powf(controls.gate_threshold_db.load() / 20.0);
+        let close_level = 10f32.powf((controls.gate_threshold_db.load() - controls.gate_hysteresis_db.load().max(0.0)) / 20.0);
+        let hold = (controls.gate_hold_ms.load().max(0.0) * 0.001 * self.sample_rate) as usize;
+        let attack = self.coeff(controls.gate_attack_ms.load());
+        let release = self.coeff(controls.gate_release_ms.load());
+
+        for frame in block.chunks_exact_mut(self.channels) {
+            let peak = frame.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+            self.envelope = peak.max(self.envelope * self.detector_release);
+            if self.envelope > open_level {
+                self.open = true;
+                self.hold_left = hold;
+            } else if self.open && self.envelope < close_level {
+                if self.hold_left == 0 {
+                    self.open = false;
+                } else {
+                    self.hold_left -= 1;
+                }
+            }
+            let (target, coeff) = if self.open { (1.0, attack) } else { (0.0, release) };
+            self.gain += (target - self.gain) * coeff;
+            frame.iter_mut().for_each(|s| *s *= self.gain);
+        }
+        controls.gate_open.store(self.open, Ordering::Relaxed);
+    }
+}
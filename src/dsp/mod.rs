@@ -0,0 +1,29 @@
+// Per-input processing used by the mixing engine. Everything here runs on the render
+// thread: no allocation after construction, no locks.
+
+pub mod biquad;
+pub mod delay;
+pub mod ducker;
+pub mod eq;
+pub mod filter;
+pub mod gate;
+
+/// One-pole parameter smoother, so knob moves don't click or zipper.
+#[derive(Debug, Clone, Copy)]
+pub struct Smoother {
+    value: f32,
+    coeff: f32,
+}
+
+impl Smoother {
+    /// Settles to ~63% of a step in `time_seconds`.
+    pub fn new(initial: f32, time_seconds: f32, sample_rate: u32) -> Self {
+        let coeff = 1.0 - (-1.0 / (time_seconds * sample_rate as f32).max(1.0)).exp();
+        Self { value: initial, coeff }
+    }
+
+    pub fn next(&mut self, target: f32) -> f32 {
+        self.value += (target - self.value) * self.coeff;
+        self.value
+    }
+}
@@ -0,0 +1,419 @@
+// Headless mode: drives the audio backend from the command line, without eframe.
+
+use crate::analysis::loudness::format_lufs;
+use crate::api::{ApiOptions, ApiServer};
+use crate::audio::ActiveBackend;
+use crate::audio::backend::{AudioBackend, BackendError, ChannelRange, DeviceEntry, Mode, OutputSelection};
+use crate::audio::click::{ClickSound, MAX_BEATS_PER_BAR, MAX_BPM, MIN_BPM, MIN_CLICK_LEVEL_DB};
+use crate::audio::controls::{ClickControls, GeneratorControls, MAX_CUE_BUSES, MAX_OUTPUTS, MAX_TRIM_DB, MIN_TRIM_DB};
+use crate::audio::engine::{MAX_INPUT_DELAY_MS, MAX_OUTPUT_DELAY_MS};
+use crate::audio::generator::{GeneratorKind, MAX_GENERATOR_HZ, MAX_SWEEP_SECONDS, MIN_GENERATOR_HZ, MIN_GENERATOR_LEVEL_DB};
+use crate::audio::meters::LevelMeter;
+use crate::audio::pads::{PadBank, CHOKE_GROUPS, MAX_PADS, MAX_POLYPHONY};
+use crate::cli::{self, CliCommand, RunArgs};
+use crate::config::{self, Config};
+use crate::record;
+use crate::state::{AppState, Command, InputKind, Routing};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const DEFAULT_METER_INTERVAL_MS: u64 = 1000;
+// Input spec prefix of a file to play
+const FILE_PREFIX: &str = "file:";
+
+/// Run a non-GUI command and return the process exit code.
+pub fn run(cmd: CliCommand) -> i32 {
+    let result = match cmd {
+        CliCommand::Gui => Ok(()),
+        CliCommand::ListDevices { json } => list_devices(json),
+        CliCommand::Run(args) => run_router(*args),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(msg) => {
+            eprintln!("Error: {}", msg);
+            1
+        }
+    }
+}
+
+fn apply_generator(generator: &config::Generator, params: &GeneratorControls) -> Result<(), String> {
+    if let Some(key) = &generator.kind {
+        let kind = GeneratorKind::from_key(key).ok_or_else(|| format!("Invalid config: unknown generator kind '{}'", key))?;
+        params.kind.store(kind.index(), Ordering::Relaxed);
+    }
+    let hz = [(generator.frequency, &params.frequency), (generator.sweep_start, &params.sweep_start), (generator.sweep_end, &params.sweep_end)];
+    for (value, param) in hz {
+        if let Some(v) = value {
+            param.store(v.clamp(MIN_GENERATOR_HZ, MAX_GENERATOR_HZ));
+        }
+    }
+    if let Some(db) = generator.level_db {
+        params.level_db.store(db.clamp(MIN_GENERATOR_LEVEL_DB, 0.0));
+    }
+    if let Some(seconds) = generator.sweep_seconds {
+        params.sweep_seconds.store(seconds.clamp(0.1, MAX_SWEEP_SECONDS));
+    }
+    Ok(())
+}
+
+fn apply_pads(pads: &[config::Pad], bank: &PadBank) -> Result<(), String> {
+    if pads.len() > MAX_PADS {
+        return Err(format!("Invalid config: at most {} pads", MAX_PADS));
+    }
+    for (idx, (config, pad)) in pads.iter().zip(&bank.pads).enumerate() {
+        if let Some(path) = &config.path {
+            bank.load(idx, Some(Path::new(path)))?;
+        }
+        if let Some(db) = config.gain_db {
+            pad.gain_db.store(db.clamp(MIN_TRIM_DB, MAX_TRIM_DB));
+        }
+        if let Some(group) = config.choke {
+            if group > CHOKE_GROUPS {
+                return Err(format!("Invalid config: choke group of pad {} must be 0-{}", idx + 1, CHOKE_GROUPS));
+            }
+            pad.choke.store(group, Ordering::Relaxed);
+        }
+        if let Some(voices) = config.polyphony {
+            pad.polyphony.store(voices.clamp(1, MAX_POLYPHONY), Ordering::Relaxed);
+        }
+    }
+    Ok(())
+}
+
+fn apply_click(click: &config::Click, params: &ClickControls) -> Result<(), String> {
+    if let Some(key) = &click.sound {
+        let sound = ClickSound::from_key(key).ok_or_else(|| format!("Invalid config: unknown click sound '{}'", key))?;
+        params.sound.store(sound.index(), Ordering::Relaxed);
+    }
+    if let Some(beats) = click.beats {
+        params.beats.store(beats.clamp(1, MAX_BEATS_PER_BAR), Ordering::Relaxed);
+    }
+    if let Some(accents) = &click.accents {
+        let mut mask = 0;
+        for &beat in accents {
+            if !(1..=MAX_BEATS_PER_BAR).contains(&beat) {
+                return Err(format!("Invalid config: accented beats must be 1-{}", MAX_BEATS_PER_BAR));
+            }
+            mask |= 1 << (beat - 1);
+        }
+        params.accents.store(mask, Ordering::Relaxed);
+    }
+    if let Some(bpm) = click.bpm {
+        params.bpm.store(bpm.clamp(MIN_BPM, MAX_BPM));
+    }
+    if let Some(db) = click.level_db {
+        params.level_db.store(db.clamp(MIN_CLICK_LEVEL_DB, 0.0));
+    }
+    if let Some(on) = click.listen {
+        params.listen.store(on, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+fn backend_error(e: BackendError) -> String {
+    match e {
+        BackendError::InitError(msg) => msg,
+        BackendError::StartError(msg) => msg,
+    }
+}
+
+fn list_devices(json: bool) -> Result<(), String> {
+    let backend = ActiveBackend::new().map_err(backend_error)?;
+    if json {
+        let descriptions = backend.describe_devices().map_err(backend_error)?;
+        let text = serde_json::to_string_pretty(&descriptions).map_err(|e| format!("Failed to serialize devices: {}", e))?;
+        println!("{}", text);
+        return Ok(());
+    }
+
+    let entries = backend.enumerate_devices().map_err(backend_error)?;
+    for (i, d) in entries.iter().enumerate() {
+        let dir = match (d.is_input, d.is_output) {
+            (true, true) => "in/out",
+            (true, false) => "in",
+            (false, true) => "out",
+            (false, false) => "-",
+        };
+        let mode = match d.mode {
+            Mode::Shared => "shared",
+            Mode::Exclusive => "exclusive",
+        };
+        println!("{:>3}  {:<6} {:<9}  {}", i, dir, mode, d.name);
+        if let Some(id) = &d.device_id {
+            println!("     id: {}", id);
+        }
+    }
+    Ok(())
+}
+
+fn run_router(args: RunArgs) -> Result<(), String> {
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    let mut backend = ActiveBackend::new().map_err(backend_error)?;
+    let entries = backend.enumerate_devices().map_err(backend_error)?;
+
+    // Command-line flags take precedence over the config file
+    let input_a = args.input_a.or(config.input_a);
+    let input_b = args.input_b.or(config.input_b);
+    let output = args.output.or(config.output).ok_or("No output device given (--output or config)")?;
+
+    // "generator" instead of a device plays the slot's test signal, "file:<path>" a file
+    let resolve_input = |spec: Option<String>| -> Result<(InputKind, Option<usize>, Option<PathBuf>), String> {
+        match spec {
+            Some(s) if s == InputKind::Generator.key() => Ok((InputKind::Generator, None, None)),
+            Some(s) if s.starts_with(FILE_PREFIX) => Ok((InputKind::File, None, Some(PathBuf::from(&s[FILE_PREFIX.len()..])))),
+            spec => Ok((InputKind::Device, spec.map(|s| cli::resolve_device(&entries, &s, |d: &DeviceEntry| d.is_input)).transpose()?, None)),
+        }
+    };
+    let (source_a, input_a, file_a) = resolve_input(input_a)?;
+    let (source_b, input_b, file_b) = resolve_input(input_b)?;
+    let channels = |spec: Option<String>| spec.map(|s| ChannelRange::parse(&s)).transpose();
+    if config.extra_outputs.len() > MAX_OUTPUTS - 1 {
+        return Err(format!("Invalid config: at most {} extra outputs", MAX_OUTPUTS - 1));
+    }
+    let mut extra_outputs = [None; MAX_OUTPUTS - 1];
+    for (slot, extra) in extra_outputs.iter_mut().zip(&config.extra_outputs) {
+        *slot = Some(OutputSelection {
+            device: cli::resolve_device(&entries, &extra.device, |d| d.is_output)?,
+            channels: channels(extra.channels.clone())?,
+        });
+    }
+    if config.cue_buses.len() > MAX_CUE_BUSES {
+        return Err(format!("Invalid config: at most {} cue buses", MAX_CUE_BUSES));
+    }
+    let mut cue_outputs = [None; MAX_CUE_BUSES];
+    for (slot, bus) in cue_outputs.iter_mut().zip(&config.cue_buses) {
+        *slot = Some(OutputSelection {
+            device: cli::resolve_device(&entries, &bus.device, |d| d.is_output)?,
+            channels: channels(bus.channels.clone())?,
+        });
+    }
+    let routing = Routing {
+        input_a,
+        input_b,
+        sources: [source_a, source_b],
+        output: Some(cli::resolve_device(&entries, &output, |d| d.is_output)?),
+        channels_a: channels(args.channels_a.or(config.channels_a))?,
+        channels_b: channels(args.channels_b.or(config.channels_b))?,
+        output_channels: channels(args.output_channels.or(config.output_channels))?,
+        extra_outputs,
+        cue_outputs,
+    };
+
+    let (listen_a, listen_b) = match args.listen {
+        Some(v) => cli::parse_listen(&v)?,
+        None => (
+            config.listen_a.unwrap_or(routing.input(0).is_some()),
+            config.listen_b.unwrap_or(routing.input(1).is_some()),
+        ),
+    };
+
+    let name = |idx: Option<usize>| idx.and_then(|i| entries.get(i)).map(|d| d.name.clone()).unwrap_or_else(|| "-".into());
+    let channels = |range: Option<ChannelRange>| range.map(|r| format!(" (channels {})", r.label())).unwrap_or_default();
+    for (slot, (label, input, range)) in [("A", routing.input_a, routing.channels_a), ("B", routing.input_b, routing.channels_b)].into_iter().enumerate() {
+        match routing.sources[slot] {
+            InputKind::Device => println!("Input {}: {}{}", label, name(input), channels(range)),
+            InputKind::Generator => println!("Input {}: generator", label),
+            InputKind::File => println!("Input {}: file {}", label, [&file_a, &file_b][slot].as_ref().map(|p| p.display().to_string()).unwrap_or_default()),
+        }
+    }
+    println!("Output:  {}{}", name(routing.output), channels(routing.output_channels));
+    for (slot, extra) in routing.extra_outputs.iter().enumerate() {
+        if let Some(o) = extra {
+            println!("Output {}: {}{}", slot + 2, name(Some(o.device)), channels(o.channels));
+        }
+    }
+    for (bus, cue) in routing.cue_outputs.iter().enumerate() {
+        if let Some(o) = cue {
+            println!("Cue bus {}: {}{}", bus + 1, name(Some(o.device)), channels(o.channels));
+        }
+    }
+
+    let state = Arc::new(AppState::new(entries.clone()));
+    *state.routing.lock().unwrap() = routing;
+    state.controls.listen_a.store(listen_a, Ordering::Relaxed);
+    state.controls.listen_b.store(listen_b, Ordering::Relaxed);
+    let trims = std::iter::once((config.output_volume_db, config.output_delay_ms))
+        .chain(config.extra_outputs.iter().map(|o| (o.volume_db, o.delay_ms)));
+    for ((volume_db, delay_ms), params) in trims.zip(&state.controls.outputs) {
+        if let Some(db) = volume_db {
+            params.volume_db.store(db.clamp(MIN_TRIM_DB, MAX_TRIM_DB));
+        }
+        if let Some(ms) = delay_ms {
+            params.delay_ms.store(ms.clamp(0.0, MAX_OUTPUT_DELAY_MS));
+        }
+    }
+    for (bus, params) in config.cue_buses.iter().zip(&state.controls.cue_buses) {
+        params.listen[0].store(bus.listen_a.unwrap_or(routing.input(0).is_some()), Ordering::Relaxed);
+        params.listen[1].store(bus.listen_b.unwrap_or(routing.input(1).is_some()), Ordering::Relaxed);
+        for (gain, db) in params.gain_db.iter().zip([bus.gain_a_db, bus.gain_b_db]) {
+            if let Some(db) = db {
+                gain.store(db.clamp(MIN_TRIM_DB, MAX_TRIM_DB));
+            }
+        }
+        if let Some(on) = bus.listen_pads {
+            params.listen_pads.store(on, Ordering::Relaxed);
+        }
+        if let Some(on) = bus.listen_click {
+            params.listen_click.store(on, Ordering::Relaxed);
+        }
+        if let Some(db) = bus.volume_db {
+            params.output.volume_db.store(db.clamp(MIN_TRIM_DB, MAX_TRIM_DB));
+        }
+        if let Some(ms) = bus.delay_ms {
+            params.output.delay_ms.store(ms.clamp(0.0, MAX_OUTPUT_DELAY_MS));
+        }
+    }
+    for (params, ms) in state.controls.inputs.iter().zip([config.delay_a_ms, config.delay_b_ms]) {
+        if let Some(ms) = ms {
+            params.delay_ms.store(ms.clamp(0.0, MAX_INPUT_DELAY_MS));
+        }
+    }
+    for ((file, looping), player) in [(file_a, config.loop_a), (file_b, config.loop_b)].into_iter().zip(&state.controls.players) {
+        if let Some(path) = file {
+            player.load(&path)?;
+            player.looping.store(looping.unwrap_or(false), Ordering::Relaxed);
+            player.play();
+        }
+    }
+    for (generator, params) in [&config.generator_a, &config.generator_b].into_iter().zip(&state.controls.generators) {
+        if let Some(generator) = generator {
+            apply_generator(generator, params)?;
+        }
+    }
+    apply_pads(&config.pads, &state.controls.pads)?;
+    if let Some(on) = config.pads_listen {
+        state.controls.pads.listen.store(on, Ordering::Relaxed);
+    }
+    if let Some(click) = &config.click {
+        apply_click(click, &state.controls.click)?;
+    }
+    for (slot, rows) in [&config.matrix_a, &config.matrix_b].into_iter().enumerate() {
+        if let Some(rows) = rows {
+            state.controls.inputs[slot].route.load_rows(rows).map_err(|e| format!("Invalid config: {}", e))?;
+        }
+    }
+    if let Some(seconds) = args.history_seconds {
+        state.history_seconds.store(seconds, Ordering::Relaxed);
+    }
+
+    let _api = match args.api_port.or(config.api_port) {
+        Some(port) => {
+            let mut options = ApiOptions::new(port);
+            if let Some(bind) = args.api_bind.or(config.api_bind) {
+                options.bind = bind;
+            }
+            options.token = args.api_token.or(config.api_token);
+            Some(ApiServer::start(options, state.clone())?)
+        }
+        None => None,
+    };
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    {
+        let shutdown = shutdown.clone();
+        ctrlc::set_handler(move || shutdown.store(true, Ordering::Relaxed))
+            .map_err(|e| format!("Failed to install signal handler: {}", e))?;
+    }
+
+    start(&mut backend, &state)?;
+    if let Some(format) = args.record {
+        let path = state.start_recording(Path::new(record::DEFAULT_DIR), format, args.multitrack)?;
+        println!("Recording to {}", path.display());
+    }
+
+    let meter_interval = Duration::from_millis(args.meter_interval_ms.unwrap_or(DEFAULT_METER_INTERVAL_MS));
+    let started_at = Instant::now();
+    let mut last_meter = Instant::now();
+
+    while !shutdown.load(Ordering::Relaxed) {
+        // Start/stop requests from the remote API
+        for cmd in state.take_commands() {
+            match cmd {
+                Command::Start if !state.is_running() => {
+                    if let Err(msg) = start(&mut backend, &state) { eprintln!("Failed to start audio backend: {}", msg); }
+                }
+                Command::Stop if state.is_running() => stop(&mut backend, &state),
+                _ => {}
+            }
+        }
+
+        if !meter_interval.is_zero() && last_meter.elapsed() >= meter_interval {
+            last_meter = Instant::now();
+            println!("{}", meter_line(&state, started_at.elapsed()));
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    println!("Shutting down");
+    if state.is_running() {
+        stop(&mut backend, &state);
+    }
+    Ok(())
+}
+
+fn start(backend: &mut ActiveBackend, state: &AppState) -> Result<(), String> {
+    let routing = state.routing();
+    match backend.start(routing.input(0), routing.input(1), &routing.outputs(), &routing.cue_outputs, state.controls.clone()) {
+        Ok(()) => {
+            state.audio_started.store(true, Ordering::Relaxed);
+            *state.last_error.lock().unwrap() = None;
+            state.start_history();
+            state.start_analysis();
+            println!("Audio started");
+            Ok(())
+        }
+        Err(e) => {
+            let msg = backend_error(e);
+            *state.last_error.lock().unwrap() = Some(msg.clone());
+            Err(msg)
+        }
+    }
+}
+
+fn stop(backend: &mut ActiveBackend, state: &AppState) {
+    match state.stop_recording() {
+        Some(Ok(path)) => println!("Recording saved to {}", path.display()),
+        Some(Err(msg)) => eprintln!("{}", msg),
+        None => {}
+    }
+    state.pause_history();
+    state.pause_analysis();
+    if let Err(e) = backend.stop() {
+        eprintln!("Failed to stop audio backend: {:?}", e);
+    }
+    state.audio_started.store(false, Ordering::Relaxed);
+    state.controls.meters.reset();
+    println!("Audio stopped");
+}
+
+fn meter_line(state: &AppState, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    let db = |m: &LevelMeter| format!("{:>6.1} dB", m.peak_db());
+    let on = |flag: &AtomicBool| if flag.load(Ordering::Relaxed) { "on " } else { "off" };
+    let meters = &state.controls.meters;
+    let rec = match state.recorder.lock().unwrap().as_ref() {
+        Some(r) => format!(" | rec {} {}", record::format_duration(r.elapsed()), record::format_size(r.file_size())),
+        None => String::new(),
+    };
+    let lufs = match state.loudness.lock().unwrap().as_ref() {
+        Some(m) => format!(" | S {} I {} LUFS TP {} dBTP", format_lufs(m.readings().short_term()), format_lufs(m.readings().integrated()), format_lufs(m.readings().true_peak())),
+        None => String::new(),
+    };
+    format!(
+        "[{:02}:{:02}:{:02}] {} | A {} ({}) | B {} ({}) | out {}{}{}",
+        secs / 3600, (secs / 60) % 60, secs % 60,
+        if state.is_running() { "running" } else { "stopped" },
+        db(&meters.input_a), on(&state.controls.listen_a),
+        db(&meters.input_b), on(&state.controls.listen_b),
+        db(&meters.output), lufs, rec,
+    )
+}
@@ -1,17 +1,85 @@
+mod analysis;
+mod api;
 mod audio;
+mod cli;
+mod config;
+mod dsp;
+mod headless;
+mod record;
+mod state;
+mod ui;
 use eframe::egui;
 use egui::{Color32, RichText};
-use crate::audio::WasapiBackend;
-use crate::audio::backend::{AudioBackend, BackendError};
+use crate::analysis::latency::{MeasureStatus, MAX_RUNS};
+use crate::analysis::loudness::format_lufs;
+use crate::analysis::spectrum::SpectrumSource;
+use crate::api::{ApiOptions, ApiServer};
+use crate::audio::ActiveBackend;
+use crate::audio::params::Param;
+use crate::audio::click::{ClickSound, MAX_BEATS_PER_BAR, MAX_BPM, MIN_BPM, MIN_CLICK_LEVEL_DB};
+use crate::audio::backend::{AudioBackend, BackendError, ChannelRange, OutputSelection};
+use crate::audio::controls::{AtomicF32, GeneratorControls, MAX_CUE_BUSES, MAX_OUTPUTS, MAX_TRIM_DB, MIN_TRIM_DB};
+use crate::audio::engine::MAX_OUTPUT_DELAY_MS;
+use crate::audio::generator::{GeneratorKind, MAX_GENERATOR_HZ, MAX_SWEEP_SECONDS, MIN_GENERATOR_HZ, MIN_GENERATOR_LEVEL_DB};
+use crate::audio::pads::{CHOKE_GROUPS, MAX_PADS, MAX_POLYPHONY};
+use crate::audio::player::FilePlayer;
+use crate::record::RecordFormat;
+use crate::record::history;
+use crate::state::{AppState, Command, InputKind};
+use crate::ui::meters::{Ballistics, MeterDisplay};
 use rdev::Key;
-use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::sync::{Arc, Mutex, atomic::Ordering};
+use std::hash::Hash;
+use std::path::Path;
+use std::time::Duration;
 
 // --- CONFIGURATION (fixed hotkeys as variables) ---
 // Later we can make these configurable via UI
 const KEY_TOGGLE_A: Key = Key::F9;
 const KEY_TOGGLE_B: Key = Key::F10;
+const KEY_RECORD: egui::Key = egui::Key::F11;
+const KEY_SAVE_HISTORY: egui::Key = egui::Key::F12;
+// Listen toggles (A, B) of each cue bus
+const CUE_HOTKEYS: [[egui::Key; 2]; MAX_CUE_BUSES] = [[egui::Key::F5, egui::Key::F6], [egui::Key::F7, egui::Key::F8]];
+// Parameter hotkeys (ids from `audio::params`) and their group in the status strip;
+// ignored while a text field has focus
+const PARAM_HOTKEYS: &[(egui::Key, &str, &str)] = &[
+    (egui::Key::Num1, "a.eq.low.kill", "Kills A"),
+    (egui::Key::Num2, "a.eq.mid.kill", "Kills A"),
+    (egui::Key::Num3, "a.eq.high.kill", "Kills A"),
+    (egui::Key::Num8, "b.eq.low.kill", "Kills B"),
+    (egui::Key::Num9, "b.eq.mid.kill", "Kills B"),
+    (egui::Key::Num0, "b.eq.high.kill", "Kills B"),
+    (egui::Key::T, "talkover", "Talkover"),
+    (egui::Key::Z, "pad1", "Pads"),
+    (egui::Key::X, "pad2", "Pads"),
+    (egui::Key::C, "pad3", "Pads"),
+    (egui::Key::V, "pad4", "Pads"),
+    (egui::Key::A, "pad5", "Pads"),
+    (egui::Key::S, "pad6", "Pads"),
+    (egui::Key::D, "pad7", "Pads"),
+    (egui::Key::F, "pad8", "Pads"),
+    (egui::Key::K, "cue1.listen.click", "Click cue 1/2"),
+    (egui::Key::L, "cue2.listen.click", "Click cue 1/2"),
+];
+
+// Width of the level meters next to the device pickers
+const METER_WIDTH: f32 = 170.0;
+const SPECTRUM_HEIGHT: f32 = 160.0;
+const GONIOMETER_SIZE: f32 = 140.0;
 
 fn main() -> eframe::Result<()> {
+    // Subcommands run headless; no arguments starts the GUI
+    match cli::parse_args(std::env::args().skip(1)) {
+        Ok(cli::CliCommand::Gui) => {}
+        Ok(cmd) => std::process::exit(headless::run(cmd)),
+        Err(msg) => {
+            if !msg.is_empty() { eprintln!("{}\n", msg); }
+            eprintln!("{}", cli::USAGE);
+            std::process::exit(if msg.is_empty() { 0 } else { 2 });
+        }
+    }
+
     let mut native_options = eframe::NativeOptions::default();
     let (w, h) = AudioApp::WINDOW_SIZE;
     native_options.initial_window_size = Some(egui::vec2(w, h));
@@ -24,73 +92,138 @@ fn main() -> eframe::Result<()> {
 }
 
 struct AudioApp {
-    backend: WasapiBackend,
+    backend: ActiveBackend,
     device_entries: Vec<crate::audio::backend::DeviceEntry>,     // entries provided by backend (SHARED/EXCLUSIVE)
 
-    // Selection Indices
-    input_a_idx: Option<usize>,
-    input_b_idx: Option<usize>,
-    output_idx: Option<usize>,
+    // Routing, listen toggles, meters and run state; shared with the remote API
+    state: Arc<AppState>,
+
+    // Optional remote control server
+    api_server: Option<ApiServer>,
+    api_port: u16,
+    // Listen on the LAN rather than localhost only, and the token clients must send (if any)
+    api_lan: bool,
+    api_token: String,
+    api_error: Option<String>,
+
+    // Output recording: format for the next take and the last saved file / error
+    record_format: RecordFormat,
+    record_multitrack: bool,
+    record_message: Option<String>,
+
+    // "Save last N seconds": span to save and the last save error
+    history_save_seconds: u32,
+    history_error: Option<String>,
+
+    // Level meter display state (input A, input B, output) and the selected ballistics
+    meter_displays: [MeterDisplay; 3],
+    ballistics: Ballistics,
+
+    // Channel count per device entry (0 until probed), shown in the pickers
+    device_channels: Arc<Mutex<Vec<u16>>>,
+
+    // Round-trip latency test: input the loopback cable comes back on, runs, last start error
+    latency_input: usize,
+    latency_runs: usize,
+    latency_error: Option<String>,
+
+    // File player inputs: path being typed and the last load error, by slot
+    file_paths: [String; 2],
+    file_errors: [Option<String>; 2],
 
-    // Toggles controlled by global hotkeys
-    listen_a: Arc<AtomicBool>,
-    listen_b: Arc<AtomicBool>,
-    audio_started: bool,
-    last_error: Option<String>,
+    // Sample pads: path being typed per pad, last load error
+    pad_paths: [String; MAX_PADS],
+    pad_error: Option<String>,
 }
 
 impl AudioApp {
     // Window size constant (width, height) — change here to resize the app window
-    pub const WINDOW_SIZE: (f32, f32) = (700.0, 240.0);
+    pub const WINDOW_SIZE: (f32, f32) = (800.0, 400.0);
 
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
         // Initialize backend and get device entries
-        let backend = WasapiBackend::new().unwrap_or_else(|_| panic!("Failed to initialize WASAPI backend"));
+        let backend = ActiveBackend::new().unwrap_or_else(|_| panic!("Failed to initialize WASAPI backend"));
         let entries = match backend.enumerate_devices() {
             Ok(vec) => vec,
             Err(_) => Vec::new(),
         };
 
-        let listen_a = Arc::new(AtomicBool::new(false));
-        let listen_b = Arc::new(AtomicBool::new(false));
+        let state = Arc::new(AppState::new(entries.clone()));
+        // Remote API commands need the UI thread to wake up even when the window is idle
+        let ctx = cc.egui_ctx.clone();
+        state.set_waker(Box::new(move || ctx.request_repaint()));
+
+        // Probing formats can take a moment per device, so channel counts fill in later
+        let device_channels = Arc::new(Mutex::new(Vec::new()));
+        {
+            let device_channels = device_channels.clone();
+            let ctx = cc.egui_ctx.clone();
+            std::thread::spawn(move || {
+                if let Ok(described) = ActiveBackend::new().and_then(|b| b.describe_devices()) {
+                    *device_channels.lock().unwrap() = described.iter().map(|d| d.channel_counts.iter().copied().max().unwrap_or(0)).collect();
+                    ctx.request_repaint();
+                }
+            });
+        }
 
         Self {
             backend,
             device_entries: entries,
-            input_a_idx: None,
-            input_b_idx: None,
-            output_idx: None,
-            listen_a,
-            listen_b,
-            audio_started: false,
-            last_error: None,
+            state,
+            api_server: None,
+            api_port: api::DEFAULT_PORT,
+            api_lan: false,
+            api_token: String::new(),
+            api_error: None,
+            record_format: RecordFormat::default(),
+            record_multitrack: false,
+            record_message: None,
+            history_save_seconds: 60,
+            history_error: None,
+            meter_displays: [MeterDisplay::new(), MeterDisplay::new(), MeterDisplay::new()],
+            ballistics: Ballistics::default(),
+            device_channels,
+            latency_input: 0,
+            latency_runs: 5,
+            latency_error: None,
+            file_paths: Default::default(),
+            file_errors: Default::default(),
+            pad_paths: Default::default(),
+            pad_error: None,
         }
     }
 
     fn start_audio(&mut self) {
-        match self.backend.start(self.input_a_idx, self.input_b_idx, self.output_idx, self.listen_a.clone(), self.listen_b.clone()) {
+        let routing = self.state.routing();
+        match self.backend.start(routing.input(0), routing.input(1), &routing.outputs(), &routing.cue_outputs, self.state.controls.clone()) {
             Ok(()) => {
-                self.audio_started = true;
-                self.last_error = None;
+                self.state.audio_started.store(true, Ordering::Relaxed);
+                *self.state.last_error.lock().unwrap() = None;
+                self.state.start_history();
+                self.state.start_analysis();
                 println!("Audio started");
             }
             Err(e) => {
-                self.audio_started = false;
+                self.state.audio_started.store(false, Ordering::Relaxed);
                 let msg = match e {
                     BackendError::InitError(msg) => msg,
                     BackendError::StartError(msg) => msg,
                 };
-                self.last_error = Some(msg.clone());
+                *self.state.last_error.lock().unwrap() = Some(msg.clone());
                 eprintln!("Failed to start audio backend: {}", msg);
             }
         }
     }
 
     fn stop_audio(&mut self) {
+        self.stop_recording();
+        self.state.pause_history();
+        self.state.pause_analysis();
         match self.backend.stop() {
             Ok(()) => {
-                self.audio_started = false;
-                self.last_error = None;
+                self.state.audio_started.store(false, Ordering::Relaxed);
+                *self.state.last_error.lock().unwrap() = None;
+                self.state.controls.meters.reset();
                 println!("Audio stopped");
             }
             Err(e) => {
@@ -98,91 +231,680 @@ impl AudioApp {
             }
         }
     }
+
+    fn toggle_recording(&mut self) {
+        if self.state.is_recording() {
+            self.stop_recording();
+            return;
+        }
+        self.record_message = match self.state.start_recording(Path::new(record::DEFAULT_DIR), self.record_format, self.record_multitrack) {
+            Ok(path) => { println!("Recording to {}", path.display()); None }
+            Err(msg) => { eprintln!("Failed to start recording: {}", msg); Some(msg) }
+        };
+    }
+
+    fn stop_recording(&mut self) {
+        match self.state.stop_recording() {
+            Some(Ok(path)) => {
+                println!("Recording saved to {}", path.display());
+                self.record_message = Some(format!("Saved {}", path.display()));
+            }
+            Some(Err(msg)) => {
+                eprintln!("{}", msg);
+                self.record_message = Some(msg);
+            }
+            None => {}
+        }
+    }
+
+    fn save_history(&mut self) {
+        self.history_error = self.state.save_history(Path::new(record::DEFAULT_DIR), self.history_save_seconds, self.record_format).err();
+    }
+
+    fn set_api_enabled(&mut self, enabled: bool) {
+        if enabled {
+            let options = ApiOptions {
+                bind: if self.api_lan { api::LAN_BIND } else { api::DEFAULT_BIND }.to_string(),
+                port: self.api_port,
+                token: Some(self.api_token.trim().to_string()).filter(|t| !t.is_empty()),
+            };
+            match ApiServer::start(options, self.state.clone()) {
+                Ok(server) => {
+                    self.api_server = Some(server);
+                    self.api_error = None;
+                }
+                Err(msg) => {
+                    eprintln!("{}", msg);
+                    self.api_error = Some(msg);
+                }
+            }
+        } else {
+            // Dropping the server unbinds the port and ends its threads
+            self.api_server = None;
+        }
+    }
+
+    fn process_commands(&mut self) {
+        for cmd in self.state.take_commands() {
+            match cmd {
+                Command::Start => if !self.state.is_running() { self.start_audio(); },
+                Command::Stop => if self.state.is_running() { self.stop_audio(); },
+            }
+        }
+    }
 }
 
 impl eframe::App for AudioApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.process_commands();
+
         // Local (in-app) hotkeys only
         if ctx.input(|i| i.key_pressed(egui::Key::F9)) {
-            let val = self.listen_a.load(Ordering::Relaxed);
-            self.listen_a.store(!val, Ordering::Relaxed);
+            self.state.controls.toggle_a();
         }
         if ctx.input(|i| i.key_pressed(egui::Key::F10)) {
-            let val = self.listen_b.load(Ordering::Relaxed);
-            self.listen_b.store(!val, Ordering::Relaxed);
+            self.state.controls.toggle_b();
+        }
+        for (bus, keys) in CUE_HOTKEYS.iter().enumerate() {
+            for (slot, key) in keys.iter().enumerate() {
+                if ctx.input(|i| i.key_pressed(*key)) {
+                    self.state.controls.cue_buses[bus].toggle(slot);
+                }
+            }
+        }
+        if ctx.input(|i| i.key_pressed(KEY_RECORD)) {
+            self.toggle_recording();
+        }
+        if ctx.input(|i| i.key_pressed(KEY_SAVE_HISTORY)) {
+            self.save_history();
+        }
+        if !ctx.wants_keyboard_input() {
+            for (key, id, _) in PARAM_HOTKEYS {
+                if ctx.input(|i| i.key_pressed(*key)) {
+                    if let Some(param) = Param::parse(id) {
+                        param.toggle(&self.state.controls);
+                    }
+                }
+            }
+        }
+
+        if matches!(*self.state.latency_test.lock().unwrap(), MeasureStatus::Running { .. }) {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+        // Meters need a steady frame rate while audio runs
+        if self.state.is_running() {
+            ctx.request_repaint_after(Duration::from_millis(30));
+        }
+        // Listen toggles may also change remotely; keep the window in sync while the API is up
+        if self.api_server.is_some() || self.state.is_recording() {
+            ctx.request_repaint_after(Duration::from_millis(100));
         }
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading(RichText::new("ExternalCue").heading());
-            ui.label(RichText::new("Low-Latency Audio Router").strong());
-            ui.add_space(6.0);
-
-            egui::Frame::group(ui.style()).show(ui, |ui| {
-                egui::Grid::new("device_grid").spacing([16.0, 8.0]).show(ui, |ui| {
-                        ui.label(RichText::new("Input Channel A:").strong());
-                        render_device_picker_filtered(ui, &self.device_entries, &mut self.input_a_idx, 480.0, |d| d.is_input);
-                        ui.end_row();
-
-                        ui.label(RichText::new("Input Channel B:").strong());
-                        render_device_picker_filtered(ui, &self.device_entries, &mut self.input_b_idx, 480.0, |d| d.is_input);
-                        ui.end_row();
-
-                        ui.label(RichText::new("Output Device:").strong());
-                        render_device_picker_filtered(ui, &self.device_entries, &mut self.output_idx, 480.0, |d| d.is_output);
-                        ui.end_row();
+            // Panels stack up past any fixed window height; scroll rather than grow the window
+            egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+                ui.heading(RichText::new("ExternalCue").heading());
+                ui.label(RichText::new("Low-Latency Audio Router").strong());
+                ui.add_space(6.0);
+
+                let mut routing = self.state.routing();
+                let before = routing;
+                egui::Frame::group(ui.style()).show(ui, |ui| {
+                    egui::Grid::new("device_grid").spacing([16.0, 8.0]).show(ui, |ui| {
+                            let meters = &self.state.controls.meters;
+                            let [meter_a, meter_b, meter_out] = &mut self.meter_displays;
+
+                            let device_channels = self.device_channels.lock().unwrap();
+
+                            ui.label(RichText::new("Input Channel A:").strong());
+                            ui.horizontal(|ui| {
+                                render_source_picker(ui, "source_a", &mut routing.sources[0]);
+                                match routing.sources[0] {
+                                    InputKind::Device => {
+                                        render_device_picker_filtered(ui, "input_a_device", &self.device_entries, &device_channels, &mut routing.input_a, 250.0, |d| d.is_input);
+                                        render_channel_picker(ui, "channels_a", &mut routing.channels_a);
+                                    }
+                                    InputKind::Generator => render_generator_controls(ui, "generator_a", &self.state.controls.generators[0]),
+                                    InputKind::File => render_file_controls(ui, &mut self.file_paths[0], &mut self.file_errors[0], &self.state.controls.players[0]),
+                                }
+                            });
+                            meter_a.show(ui, &meters.input_a, self.ballistics, METER_WIDTH);
+                            ui.end_row();
+
+                            ui.label(RichText::new("Input Channel B:").strong());
+                            ui.horizontal(|ui| {
+                                render_source_picker(ui, "source_b", &mut routing.sources[1]);
+                                match routing.sources[1] {
+                                    InputKind::Device => {
+                                        render_device_picker_filtered(ui, "input_b_device", &self.device_entries, &device_channels, &mut routing.input_b, 250.0, |d| d.is_input);
+                                        render_channel_picker(ui, "channels_b", &mut routing.channels_b);
+                                    }
+                                    InputKind::Generator => render_generator_controls(ui, "generator_b", &self.state.controls.generators[1]),
+                                    InputKind::File => render_file_controls(ui, &mut self.file_paths[1], &mut self.file_errors[1], &self.state.controls.players[1]),
+                                }
+                            });
+                            meter_b.show(ui, &meters.input_b, self.ballistics, METER_WIDTH);
+                            ui.end_row();
+
+                            ui.label(RichText::new("Output Device:").strong());
+                            ui.horizontal(|ui| {
+                                render_device_picker_filtered(ui, "output_device", &self.device_entries, &device_channels, &mut routing.output, 330.0, |d| d.is_output);
+                                render_channel_picker(ui, "output_channels", &mut routing.output_channels);
+                            });
+                            meter_out.show(ui, &meters.output, self.ballistics, METER_WIDTH);
+                            ui.end_row();
+                        });
+                });
+                // Extra outputs (copies of the mix) and per-output volume / latency compensation
+                egui::CollapsingHeader::new("Outputs").id_source("outputs_panel").show(ui, |ui| {
+                    let device_channels = self.device_channels.lock().unwrap();
+                    egui::Grid::new("outputs_grid").spacing([12.0, 6.0]).show(ui, |ui| {
+                        for slot in 0..MAX_OUTPUTS {
+                            ui.label(RichText::new(if slot == 0 { "Main output:".to_string() } else { format!("Output {}:", slot + 1) }).strong());
+                            if slot == 0 {
+                                ui.label(routing.output.and_then(|i| self.device_entries.get(i)).map(|d| d.name.as_str()).unwrap_or("-"));
+                            } else {
+                                let extra = &mut routing.extra_outputs[slot - 1];
+                                let mut device = extra.map(|o| o.device);
+                                let mut channels = extra.and_then(|o| o.channels);
+                                ui.horizontal(|ui| {
+                                    render_device_picker_filtered(ui, ("output_device", slot), &self.device_entries, &device_channels, &mut device, 330.0, |d| d.is_output);
+                                    render_channel_picker(ui, &format!("output_channels_{}", slot), &mut channels);
+                                    if device.is_some() && ui.small_button("x").on_hover_text("Remove this output").clicked() {
+                                        device = None;
+                                    }
+                                });
+                                *extra = device.map(|device| OutputSelection { device, channels });
+                            }
+                            let params = &self.state.controls.outputs[slot];
+                            let mut volume = params.volume_db.load();
+                            if ui.add(egui::DragValue::new(&mut volume).clamp_range(MIN_TRIM_DB..=MAX_TRIM_DB).speed(0.2).prefix("vol ").suffix(" dB")).changed() {
+                                params.volume_db.store(volume);
+                            }
+                            let mut delay = params.delay_ms.load();
+                            if ui.add(egui::DragValue::new(&mut delay).clamp_range(0.0..=MAX_OUTPUT_DELAY_MS).speed(0.1).prefix("delay ").suffix(" ms"))
+                                .on_hover_text("Latency compensation: delay this output to line up with a slower one")
+                                .changed()
+                            {
+                                params.delay_ms.store(delay);
+                            }
+                            ui.end_row();
+                        }
                     });
-            });
+                    ui.label(RichText::new("Extra outputs take effect on the next start and must run at the main output's sample rate.").small().weak());
+                });
 
-            ui.add_space(8.0);
+                // Cue buses: independent mixes of the same inputs, e.g. for a second DJ
+                egui::CollapsingHeader::new("Cue buses").id_source("cue_panel").show(ui, |ui| {
+                    let device_channels = self.device_channels.lock().unwrap();
+                    egui::Grid::new("cue_grid").spacing([12.0, 6.0]).show(ui, |ui| {
+                        for (bus, keys) in CUE_HOTKEYS.iter().enumerate() {
+                            let params = &self.state.controls.cue_buses[bus];
+                            ui.label(RichText::new(format!("Cue bus {}:", bus + 1)).strong());
+                            let cue = &mut routing.cue_outputs[bus];
+                            let mut device = cue.map(|o| o.device);
+                            let mut channels = cue.and_then(|o| o.channels);
+                            ui.horizontal(|ui| {
+                                render_device_picker_filtered(ui, ("cue_device", bus), &self.device_entries, &device_channels, &mut device, 330.0, |d| d.is_output);
+                                render_channel_picker(ui, &format!("cue_channels_{}", bus), &mut channels);
+                                if device.is_some() && ui.small_button("x").on_hover_text("Turn this cue bus off").clicked() {
+                                    device = None;
+                                }
+                            });
+                            *cue = device.map(|device| OutputSelection { device, channels });
+                            ui.end_row();
 
-            ui.horizontal(|ui| {
-                if !self.audio_started {
-                    if ui.add_sized([180.0, 30.0], egui::Button::new("Start Audio")).clicked() {
-                        self.start_audio();
+                            ui.label("");
+                            ui.horizontal(|ui| {
+                                for (slot, name) in ["A", "B"].into_iter().enumerate() {
+                                    let on = params.listen[slot].load(Ordering::Relaxed);
+                                    let text = RichText::new(format!("LISTEN {} ({:?})", name, keys[slot]))
+                                        .color(if on { Color32::from_rgb(120, 220, 120) } else { Color32::LIGHT_GRAY });
+                                    if ui.selectable_label(on, text).clicked() {
+                                        params.toggle(slot);
+                                    }
+                                    let mut gain = params.gain_db[slot].load();
+                                    if ui.add(egui::DragValue::new(&mut gain).clamp_range(MIN_TRIM_DB..=MAX_TRIM_DB).speed(0.2).suffix(" dB"))
+                                        .on_hover_text(format!("Input {} gain on this bus", name))
+                                        .changed()
+                                    {
+                                        params.gain_db[slot].store(gain);
+                                    }
+                                    ui.add_space(8.0);
+                                }
+                                let mut volume = params.output.volume_db.load();
+                                if ui.add(egui::DragValue::new(&mut volume).clamp_range(MIN_TRIM_DB..=MAX_TRIM_DB).speed(0.2).prefix("vol ").suffix(" dB")).changed() {
+                                    params.output.volume_db.store(volume);
+                                }
+                                let mut delay = params.output.delay_ms.load();
+                                if ui.add(egui::DragValue::new(&mut delay).clamp_range(0.0..=MAX_OUTPUT_DELAY_MS).speed(0.1).prefix("delay ").suffix(" ms")).changed() {
+                                    params.output.delay_ms.store(delay);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                    ui.label(RichText::new("Cue bus outputs take effect on the next start and must run at the main output's sample rate.").small().weak());
+                });
+
+                // Sample pads: one-shots triggered into the main mix and/or the cue buses
+                egui::CollapsingHeader::new("Sample pads").id_source("pads_panel").show(ui, |ui| {
+                    let bank = &self.state.controls.pads;
+                    ui.horizontal(|ui| {
+                        ui.label("Heard on:");
+                        let mut main = bank.listen.load(Ordering::Relaxed);
+                        if ui.checkbox(&mut main, "Main mix").changed() {
+                            bank.listen.store(main, Ordering::Relaxed);
+                        }
+                        for (bus, params) in self.state.controls.cue_buses.iter().enumerate() {
+                            let mut on = params.listen_pads.load(Ordering::Relaxed);
+                            if ui.checkbox(&mut on, format!("Cue bus {}", bus + 1)).changed() {
+                                params.listen_pads.store(on, Ordering::Relaxed);
+                            }
+                        }
+                    });
+                    egui::Grid::new("pads_grid").spacing([8.0, 4.0]).show(ui, |ui| {
+                        for (idx, pad) in bank.pads.iter().enumerate() {
+                            let path = &mut self.pad_paths[idx];
+                            if path.is_empty() {
+                                if let Some(file) = pad.file() {
+                                    *path = file.display().to_string();
+                                }
+                            }
+                            let id = format!("pad{}", idx + 1);
+                            let key = PARAM_HOTKEYS.iter().find(|(_, p, _)| *p == id).map(|(key, _, _)| format!(" ({:?})", key));
+                            let active = pad.active.load(Ordering::Relaxed) > 0;
+                            let text = RichText::new(format!("PAD {}{}", idx + 1, key.unwrap_or_default()))
+                                .color(if active { Color32::from_rgb(120, 220, 120) } else { Color32::LIGHT_GRAY });
+                            if ui.add_enabled(pad.seconds().is_some(), egui::SelectableLabel::new(active, text)).clicked() {
+                                pad.trigger();
+                            }
+                            ui.add(egui::TextEdit::singleline(path).desired_width(200.0).hint_text("WAV/FLAC/MP3/OGG path"));
+                            ui.horizontal(|ui| {
+                                if ui.button("Load").clicked() {
+                                    self.pad_error = bank.load(idx, Some(Path::new(path.trim()))).err();
+                                }
+                                if ui.add_enabled(pad.file().is_some(), egui::Button::new("Clear")).clicked() {
+                                    self.pad_error = bank.load(idx, None).err();
+                                    path.clear();
+                                }
+                            });
+                            let mut gain = pad.gain_db.load();
+                            if ui.add(egui::DragValue::new(&mut gain).clamp_range(MIN_TRIM_DB..=MAX_TRIM_DB).speed(0.2).suffix(" dB")).changed() {
+                                pad.gain_db.store(gain);
+                            }
+                            let choke = pad.choke.load(Ordering::Relaxed);
+                            let choke_label = |group: usize| if group == 0 { "No choke".to_string() } else { format!("Choke {}", group) };
+                            egui::ComboBox::from_id_source(("pad_choke", idx))
+                                .width(90.0)
+                                .selected_text(choke_label(choke))
+                                .show_ui(ui, |ui| {
+                                    for group in 0..=CHOKE_GROUPS {
+                                        if ui.selectable_label(choke == group, choke_label(group)).clicked() {
+                                            pad.choke.store(group, Ordering::Relaxed);
+                                        }
+                                    }
+                                });
+                            let mut polyphony = pad.polyphony.load(Ordering::Relaxed);
+                            if ui.add(egui::DragValue::new(&mut polyphony).clamp_range(1..=MAX_POLYPHONY).prefix("voices ")).changed() {
+                                pad.polyphony.store(polyphony, Ordering::Relaxed);
+                            }
+                            ui.label(pad.seconds().map(|s| format!("{:.1} s", s)).unwrap_or_default());
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(msg) = &self.pad_error {
+                        ui.colored_label(Color32::LIGHT_RED, msg);
                     }
-                } else {
-                    if ui.add_sized([120.0, 30.0], egui::Button::new("Stop Audio")).clicked() {
-                        self.stop_audio();
+                });
+
+                // Metronome for a drummer, usually on a cue bus only
+                egui::CollapsingHeader::new("Click").id_source("click_panel").show(ui, |ui| {
+                    let click = &self.state.controls.click;
+                    ui.horizontal(|ui| {
+                        ui.label("Heard on:");
+                        let mut main = click.listen.load(Ordering::Relaxed);
+                        if ui.checkbox(&mut main, "Main mix").changed() {
+                            click.listen.store(main, Ordering::Relaxed);
+                        }
+                        for (bus, params) in self.state.controls.cue_buses.iter().enumerate() {
+                            let id = format!("cue{}.listen.click", bus + 1);
+                            let key = PARAM_HOTKEYS.iter().find(|(_, p, _)| *p == id).map(|(key, _, _)| format!(" ({:?})", key));
+                            let mut on = params.listen_click.load(Ordering::Relaxed);
+                            if ui.checkbox(&mut on, format!("Cue bus {}{}", bus + 1, key.unwrap_or_default())).changed() {
+                                params.listen_click.store(on, Ordering::Relaxed);
+                            }
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let mut bpm = click.bpm.load();
+                        if ui.add(egui::DragValue::new(&mut bpm).clamp_range(MIN_BPM..=MAX_BPM).speed(0.1).fixed_decimals(1).suffix(" BPM")).changed() {
+                            click.bpm.store(bpm);
+                        }
+                        let mut beats = click.beats.load(Ordering::Relaxed);
+                        if ui.add(egui::DragValue::new(&mut beats).clamp_range(1..=MAX_BEATS_PER_BAR).suffix(" beats")).changed() {
+                            click.beats.store(beats, Ordering::Relaxed);
+                        }
+                        let sound = ClickSound::from_index(click.sound.load(Ordering::Relaxed));
+                        egui::ComboBox::from_id_source("click_sound")
+                            .width(100.0)
+                            .selected_text(sound.label())
+                            .show_ui(ui, |ui| {
+                                for option in ClickSound::ALL {
+                                    if ui.selectable_label(option == sound, option.label()).clicked() {
+                                        click.sound.store(option.index(), Ordering::Relaxed);
+                                    }
+                                }
+                            });
+                        let mut level = click.level_db.load();
+                        if ui.add(egui::DragValue::new(&mut level).clamp_range(MIN_CLICK_LEVEL_DB..=0.0).speed(0.2).suffix(" dBFS")).changed() {
+                            click.level_db.store(level);
+                        }
+                    });
+                    // One button per beat: click to accent it; the beat playing is lit while anyone listens
+                    ui.horizontal(|ui| {
+                        let heard = click.heard(&self.state.controls.cue_buses);
+                        let current = click.beat.load(Ordering::Relaxed);
+                        let accents = click.accents.load(Ordering::Relaxed);
+                        for beat in 0..click.beats.load(Ordering::Relaxed).clamp(1, MAX_BEATS_PER_BAR) {
+                            let accented = accents & (1 << beat) != 0;
+                            let text = RichText::new(if accented { format!("{}>", beat + 1) } else { format!("{}", beat + 1) })
+                                .color(if heard && beat == current { Color32::from_rgb(120, 220, 120) } else { Color32::LIGHT_GRAY });
+                            if ui.selectable_label(accented, text).on_hover_text("Accent this beat").clicked() {
+                                click.accents.fetch_xor(1 << beat, Ordering::Relaxed);
+                            }
+                        }
+                    });
+                    ui.label(RichText::new("The bar starts on beat one when the first listener turns the click on.").small().weak());
+                });
+
+                // Round-trip latency through a loopback cable from the main output to an input
+                egui::CollapsingHeader::new("Latency test").id_source("latency_panel").show(ui, |ui| {
+                    let status = self.state.latency_test.lock().unwrap().clone();
+                    let running = matches!(status, MeasureStatus::Running { .. });
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_source("latency_input")
+                            .selected_text(if self.latency_input == 0 { "Back on Input A" } else { "Back on Input B" })
+                            .width(130.0)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.latency_input, 0, "Back on Input A");
+                                ui.selectable_value(&mut self.latency_input, 1, "Back on Input B");
+                            });
+                        ui.add(egui::DragValue::new(&mut self.latency_runs).clamp_range(1..=MAX_RUNS).suffix(" runs"));
+                        let button = egui::Button::new(if running { "Measuring..." } else { "Measure" });
+                        if ui.add_enabled(!running && self.state.is_running(), button)
+                            .on_hover_text("Plays a test sequence on the main output instead of the mix; keep headphones off")
+                            .clicked()
+                        {
+                            self.latency_error = self.state.start_latency_test(self.latency_input, self.latency_runs).err();
+                        }
+                    });
+                    match status {
+                        MeasureStatus::Idle => {
+                            ui.label(RichText::new("Connect the main output to an input with a cable, then start audio and measure.").small().weak());
+                        }
+                        MeasureStatus::Running { done, runs } => { ui.label(format!("Run {} of {}...", done + 1, runs)); }
+                        MeasureStatus::Done(stats) => {
+                            ui.label(RichText::new(format!(
+                                "Round trip {:.2} ms  (min {:.2}, max {:.2}, jitter {:.2} ms)", stats.mean_ms, stats.min_ms, stats.max_ms, stats.jitter_ms
+                            )).monospace().strong());
+                            let runs: Vec<String> = stats.runs_ms.iter().map(|ms| format!("{:.2}", ms)).collect();
+                            ui.label(RichText::new(format!("Runs: {} ms", runs.join(", "))).small().weak());
+                        }
+                        MeasureStatus::Failed(msg) => { ui.colored_label(Color32::from_rgb(230, 60, 50), msg); }
                     }
+                    if let Some(err) = &self.latency_error {
+                        ui.colored_label(Color32::from_rgb(230, 60, 50), err);
+                    }
+                });
+
+                // Only this frame's own edits; a change made through the API meanwhile stays
+                if routing != before {
+                    *self.state.routing.lock().unwrap() = routing;
                 }
 
-                ui.add_space(12.0);
+                ui.add_space(8.0);
 
-                // Listen toggles with colored labels
-                let a_state = self.listen_a.load(Ordering::Relaxed);
-                let b_state = self.listen_b.load(Ordering::Relaxed);
+                ui.horizontal(|ui| {
+                    if !self.state.is_running() {
+                        if ui.add_sized([180.0, 30.0], egui::Button::new("Start Audio")).clicked() {
+                            self.start_audio();
+                        }
+                    } else {
+                        if ui.add_sized([120.0, 30.0], egui::Button::new("Stop Audio")).clicked() {
+                            self.stop_audio();
+                        }
+                    }
 
-                if ui.selectable_label(a_state, RichText::new(format!("LISTEN A ({:?})", KEY_TOGGLE_A)).color(if a_state { Color32::from_rgb(120, 220, 120) } else { Color32::LIGHT_GRAY })).clicked() {
-                    let new = !a_state;
-                    self.listen_a.store(new, Ordering::Relaxed);
-                }
+                    ui.add_space(12.0);
+
+                    // Listen toggles with colored labels
+                    let a_state = self.state.controls.listen_a.load(Ordering::Relaxed);
+                    let b_state = self.state.controls.listen_b.load(Ordering::Relaxed);
+
+                    if ui.selectable_label(a_state, RichText::new(format!("LISTEN A ({:?})", KEY_TOGGLE_A)).color(if a_state { Color32::from_rgb(120, 220, 120) } else { Color32::LIGHT_GRAY })).clicked() {
+                        self.state.controls.toggle_a();
+                    }
+
+                    ui.add_space(8.0);
+
+                    if ui.selectable_label(b_state, RichText::new(format!("LISTEN B ({:?})", KEY_TOGGLE_B)).color(if b_state { Color32::from_rgb(220, 120, 120) } else { Color32::LIGHT_GRAY })).clicked() {
+                        self.state.controls.toggle_b();
+                    }
+                });
+
+                // Output loudness (EBU R128)
+                ui.horizontal(|ui| {
+                    let loudness = self.state.loudness.lock().unwrap();
+                    let (m, s, i, tp) = match loudness.as_ref() {
+                        Some(l) => { let r = l.readings(); (r.momentary(), r.short_term(), r.integrated(), r.true_peak()) }
+                        None => (f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+                    };
+                    ui.label(RichText::new("Loudness:").strong());
+                    ui.label(RichText::new(format!("M {:>5}  S {:>5}  I {:>5} LUFS", format_lufs(m), format_lufs(s), format_lufs(i))).monospace());
+                    // EBU R128 ceiling is -1 dBTP
+                    let tp_color = if tp > -1.0 { Color32::from_rgb(230, 60, 50) } else { ui.visuals().text_color() };
+                    ui.label(RichText::new(format!("TP {:>5} dBTP", format_lufs(tp))).monospace().color(tp_color));
+                    let has_meter = loudness.is_some();
+                    drop(loudness);
+                    if ui.add_enabled(has_meter, egui::Button::new("Reset")).on_hover_text("Restart integrated loudness and true peak").clicked() {
+                        self.state.reset_loudness();
+                    }
+                });
 
                 ui.add_space(8.0);
 
-                if ui.selectable_label(b_state, RichText::new(format!("LISTEN B ({:?})", KEY_TOGGLE_B)).color(if b_state { Color32::from_rgb(220, 120, 120) } else { Color32::LIGHT_GRAY })).clicked() {
-                    let new = !b_state;
-                    self.listen_b.store(new, Ordering::Relaxed);
-                }
-            });
+                // Output recording
+                ui.horizontal(|ui| {
+                    let recording = self.state.is_recording();
+                    let label = if recording { format!("STOP REC ({:?})", KEY_RECORD) } else { format!("RECORD ({:?})", KEY_RECORD) };
+                    let button = egui::Button::new(RichText::new(label).color(if recording { Color32::WHITE } else { Color32::LIGHT_GRAY }))
+                        .fill(if recording { Color32::from_rgb(180, 40, 40) } else { ui.visuals().widgets.inactive.bg_fill });
+                    if ui.add_enabled(recording || self.state.is_running(), button).clicked() {
+                        self.toggle_recording();
+                    }
+
+                    ui.add_enabled_ui(!recording, |ui| {
+                        egui::ComboBox::from_id_source("record_format")
+                            .selected_text(self.record_format.extension().to_uppercase())
+                            .width(70.0)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.record_format, RecordFormat::Wav, "WAV");
+                                ui.selectable_value(&mut self.record_format, RecordFormat::Flac, "FLAC");
+                            });
+                        ui.checkbox(&mut self.record_multitrack, "Multitrack").on_hover_text("Also record each input to its own file (session folder)");
+                    });
+
+                    if let Some(rec) = self.state.recorder.lock().unwrap().as_ref() {
+                        ui.label(RichText::new(format!("{}  {}", record::format_duration(rec.elapsed()), record::format_size(rec.file_size()))).color(Color32::from_rgb(220, 120, 120)).monospace());
+                        ui.label(RichText::new(rec.path().display().to_string()).weak());
+                    } else if let Some(msg) = &self.record_message {
+                        ui.label(RichText::new(msg).weak());
+                    }
+                });
 
-            ui.add_space(10.0);
-            // Status strip
-            egui::Frame::none().show(ui, |ui| {
-                let status_text = if self.audio_started { RichText::new("Audio: Running").color(Color32::from_rgb(120,220,120)).strong() } else { RichText::new("Audio: Stopped").color(Color32::LIGHT_RED) };
+                // Retroactive capture from the history buffer
                 ui.horizontal(|ui| {
-                    ui.label(status_text);
-                    ui.add_space(12.0);
-                    ui.label(format!("Hotkeys: A={}  B={}", format!("{:?}", KEY_TOGGLE_A), format!("{:?}", KEY_TOGGLE_B)));
+                    let history = self.state.history.lock().unwrap();
+                    let button = egui::Button::new(format!("SAVE LAST ({:?})", KEY_SAVE_HISTORY));
+                    let clicked = ui.add_enabled(history.is_some(), button).clicked();
+                    ui.add(egui::DragValue::new(&mut self.history_save_seconds).suffix(" s").clamp_range(1..=history::MAX_SECONDS));
+
+                    ui.add_space(8.0);
+                    let mut seconds = self.state.history_seconds.load(Ordering::Relaxed);
+                    let length_text = |s: u32| if s == 0 { "History off".to_string() } else { format!("History {} min", s / 60) };
+                    let before = seconds;
+                    egui::ComboBox::from_id_source("history_length")
+                        .selected_text(length_text(seconds))
+                        .width(110.0)
+                        .show_ui(ui, |ui| {
+                            for s in [0, 120, 300, 600] {
+                                ui.selectable_value(&mut seconds, s, length_text(s));
+                            }
+                        });
+
+                    match history.as_ref() {
+                        Some(h) if h.is_saving() => { ui.label(RichText::new("Saving…").weak()); }
+                        Some(h) => {
+                            let buffered = record::format_duration(Duration::from_secs_f32(h.buffered_seconds()));
+                            ui.label(RichText::new(format!("{} buffered", buffered)).weak().monospace());
+                            match (&self.history_error, &h.last_save()) {
+                                (Some(msg), _) | (None, Some(Err(msg))) => { ui.label(RichText::new(msg).color(Color32::YELLOW)); }
+                                (None, Some(Ok(path))) => { ui.label(RichText::new(format!("Saved {}", path.display())).weak()); }
+                                (None, None) => {}
+                            }
+                        }
+                        None => {
+                            if let Some(msg) = &self.history_error { ui.label(RichText::new(msg).color(Color32::YELLOW)); }
+                        }
+                    }
+                    drop(history);
+
+                    if clicked {
+                        self.save_history();
+                    }
+                    if seconds != before {
+                        self.state.history_seconds.store(seconds, Ordering::Relaxed);
+                        // Takes effect right away; the current buffer is discarded
+                        self.state.start_history();
+                    }
+                });
+
+                ui.add_space(8.0);
+
+                // Per-input EQ and filter, talkover
+                egui::CollapsingHeader::new("Channel strips").id_source("strips_panel").show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui::strip::show(ui, "Input A", 0, &self.state.controls, PARAM_HOTKEYS);
+                        ui::strip::show(ui, "Input B", 1, &self.state.controls, PARAM_HOTKEYS);
+                    });
+                    ui::strip::talkover(ui, &self.state.controls);
+                });
+
+                ui.add_space(8.0);
+
+                // Spectrum analyzer of one source
+                egui::CollapsingHeader::new("Spectrum").id_source("spectrum_panel").show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        let mut source = self.state.spectrum_source();
+                        egui::ComboBox::from_id_source("spectrum_source")
+                            .selected_text(source.label())
+                            .width(90.0)
+                            .show_ui(ui, |ui| {
+                                for s in SpectrumSource::ALL {
+                                    ui.selectable_value(&mut source, s, s.label());
+                                }
+                            });
+                        if source != self.state.spectrum_source() {
+                            self.state.set_spectrum_source(source);
+                        }
+                        let mut smoothing = self.state.spectrum_smoothing();
+                        if ui.add(egui::Slider::new(&mut smoothing, 0.0..=0.95).text("Smoothing")).changed() {
+                            self.state.set_spectrum_smoothing(smoothing);
+                        }
+                    });
+                    let spectrum = self.state.spectrum.lock().unwrap().as_ref().and_then(|a| a.spectrum());
+                    ui::spectrum::show(ui, spectrum.as_ref(), SPECTRUM_HEIGHT);
+                });
+
+                // Phase correlation and goniometer of every stereo source
+                egui::CollapsingHeader::new("Stereo").id_source("stereo_panel").show(ui, |ui| {
+                    let views = self.state.stereo.lock().unwrap().each_ref().map(|a| a.as_ref().map(|a| a.view()));
+                    ui.horizontal(|ui| {
+                        for (source, view) in SpectrumSource::ALL.iter().zip(&views) {
+                            ui::stereo::show(ui, source.label(), view.as_ref(), GONIOMETER_SIZE);
+                            ui.add_space(12.0);
+                        }
+                    });
+                });
+
+                ui.add_space(8.0);
+
+                // Remote control API (HTTP + WebSocket)
+                ui.horizontal(|ui| {
+                    let mut enabled = self.api_server.is_some();
+                    if ui.checkbox(&mut enabled, "Remote API").changed() {
+                        self.set_api_enabled(enabled);
+                    }
+                    let stopped = self.api_server.is_none();
+                    ui.add_enabled(stopped, egui::DragValue::new(&mut self.api_port).prefix("port ").clamp_range(1..=65535));
+                    ui.add_enabled(stopped, egui::Checkbox::new(&mut self.api_lan, "LAN"))
+                        .on_hover_text("Reachable from other devices on the network, not just this PC");
+                    ui.add_enabled(stopped, egui::TextEdit::singleline(&mut self.api_token).password(true).desired_width(110.0).hint_text("token (optional)"));
+                    if let Some(server) = &self.api_server {
+                        let host = if self.api_lan { "<this-pc>" } else { "127.0.0.1" };
+                        ui.label(RichText::new(format!("http://{}:{}/api/status", host, server.port())).weak());
+                    } else if let Some(msg) = &self.api_error {
+                        ui.label(RichText::new(msg).color(Color32::YELLOW));
+                    }
+                });
+
+                ui.add_space(10.0);
+                // Status strip
+                egui::Frame::none().show(ui, |ui| {
+                    let status_text = if self.state.is_running() { RichText::new("Audio: Running").color(Color32::from_rgb(120,220,120)).strong() } else { RichText::new("Audio: Stopped").color(Color32::LIGHT_RED) };
+                    ui.horizontal(|ui| {
+                        ui.label(status_text);
+                        ui.add_space(12.0);
+                        ui.label(hotkey_help());
+                        ui.add_space(12.0);
+                        egui::ComboBox::from_id_source("meter_ballistics")
+                            .selected_text(format!("Meters: {}", self.ballistics.label()))
+                            .width(150.0)
+                            .show_ui(ui, |ui| {
+                                for b in Ballistics::ALL {
+                                    ui.selectable_value(&mut self.ballistics, b, b.label());
+                                }
+                            });
+                    });
+                    if let Some(msg) = &self.state.last_error() {
+                        ui.add_space(6.0);
+                        ui.label(RichText::new(format!("Warning: {}", msg)).color(Color32::YELLOW));
+                    }
                 });
-                if let Some(msg) = &self.last_error {
-                    ui.add_space(6.0);
-                    ui.label(RichText::new(format!("Warning: {}", msg)).color(Color32::YELLOW));
-                }
             });
         });
     }
 }
 
+// Status-strip summary of the hotkey tables; consecutive parameter keys in one group share an entry
+fn hotkey_help() -> String {
+    let mut parts = vec![
+        format!("A={:?}", KEY_TOGGLE_A),
+        format!("B={:?}", KEY_TOGGLE_B),
+        format!("Rec={}", KEY_RECORD.name()),
+        format!("Save last={}", KEY_SAVE_HISTORY.name()),
+    ];
+    for (bus, keys) in CUE_HOTKEYS.iter().enumerate() {
+        parts.push(format!("Cue {} A/B={}", bus + 1, keys.map(|k| k.name()).join("/")));
+    }
+    for group in PARAM_HOTKEYS.chunk_by(|a, b| a.2 == b.2) {
+        let keys: Vec<&str> = group.iter().map(|(key, _, _)| key.name()).collect();
+        parts.push(format!("{}={}", group[0].2, keys.join("/")));
+    }
+    format!("Hotkeys: {}", parts.join("  "))
+}
+
 fn render_device_picker(ui: &mut egui::Ui, entries: &[String], selected: &mut Option<usize>, width: f32) {
     let id = format!("device_picker_{:p}", selected);
     let selected_text = selected
@@ -200,23 +922,123 @@ fn render_device_picker(ui: &mut egui::Ui, entries: &[String], selected: &mut Op
         });
 }
 
-fn render_device_picker_filtered<F>(ui: &mut egui::Ui, entries: &[crate::audio::backend::DeviceEntry], selected: &mut Option<usize>, width: f32, mut filter: F)
+// `channels` holds each entry's channel count (0 or missing while unknown). `id_source` must be
+// stable across frames: pickers bind to per-frame copies, so their addresses are no id
+fn render_device_picker_filtered<F>(ui: &mut egui::Ui, id_source: impl Hash, entries: &[crate::audio::backend::DeviceEntry], channels: &[u16], selected: &mut Option<usize>, width: f32, mut filter: F)
     where F: FnMut(&crate::audio::backend::DeviceEntry) -> bool
 {
-    let id = format!("device_picker_{:p}", selected);
+    let label = |i: usize, d: &crate::audio::backend::DeviceEntry| match channels.get(i) {
+        Some(&n) if n > 0 => format!("{} ({} ch)", d.name, n),
+        _ => d.name.clone(),
+    };
     let selected_text = selected
-        .and_then(|i| entries.get(i))
-        .map(|d| d.name.clone())
+        .and_then(|i| entries.get(i).map(|d| label(i, d)))
         .unwrap_or_else(|| "Select...".to_string());
 
-    egui::ComboBox::from_id_source(id)
+    egui::ComboBox::from_id_source(id_source)
         .selected_text(selected_text)
         .width(width)
         .show_ui(ui, |ui| {
             for (i, entry) in entries.iter().enumerate() {
                 if filter(entry) {
-                    ui.selectable_value(selected, Some(i), entry.name.clone());
+                    ui.selectable_value(selected, Some(i), label(i, entry));
                 }
             }
         });
 }
+
+// Channel subset of a device. Picking the same input device for both inputs with different
+// channels splits it into two inputs (the backend opens it once); on the output the other
+// channels stay silent
+fn render_channel_picker(ui: &mut egui::Ui, id: &str, selected: &mut Option<ChannelRange>) {
+    let pairs = (0..4).map(|p| ChannelRange { first: p * 2, count: 2 });
+    let singles = (0..8).map(|c| ChannelRange { first: c, count: 1 });
+    egui::ComboBox::from_id_source(id)
+        .selected_text(selected.map(|r| format!("ch {}", r.label())).unwrap_or_else(|| "All ch".to_string()))
+        .width(80.0)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(selected, None, "All channels");
+            for range in pairs.chain(singles) {
+                ui.selectable_value(selected, Some(range), format!("ch {}", range.label()));
+            }
+        });
+}
+
+fn render_source_picker(ui: &mut egui::Ui, id: &str, selected: &mut InputKind) {
+    egui::ComboBox::from_id_source(id)
+        .selected_text(selected.label())
+        .width(80.0)
+        .show_ui(ui, |ui| {
+            for kind in InputKind::ALL {
+                ui.selectable_value(selected, kind, kind.label());
+            }
+        });
+}
+
+// Generator settings apply live; only switching between device and generator needs a restart
+fn render_generator_controls(ui: &mut egui::Ui, id: &str, params: &GeneratorControls) {
+    let mut kind = GeneratorKind::from_index(params.kind.load(Ordering::Relaxed));
+    egui::ComboBox::from_id_source(id)
+        .selected_text(kind.label())
+        .width(130.0)
+        .show_ui(ui, |ui| {
+            for k in GeneratorKind::ALL {
+                ui.selectable_value(&mut kind, k, k.label());
+            }
+        });
+    params.kind.store(kind.index(), Ordering::Relaxed);
+
+    let hz = |ui: &mut egui::Ui, value: &AtomicF32, prefix: &str| {
+        let mut v = value.load();
+        if ui.add(egui::DragValue::new(&mut v).clamp_range(MIN_GENERATOR_HZ..=MAX_GENERATOR_HZ).speed(5.0).prefix(prefix).suffix(" Hz")).changed() {
+            value.store(v);
+        }
+    };
+    match kind {
+        GeneratorKind::Sine | GeneratorKind::ChannelId => hz(ui, &params.frequency, ""),
+        GeneratorKind::Sweep => {
+            hz(ui, &params.sweep_start, "from ");
+            hz(ui, &params.sweep_end, "to ");
+            let mut seconds = params.sweep_seconds.load();
+            if ui.add(egui::DragValue::new(&mut seconds).clamp_range(0.1..=MAX_SWEEP_SECONDS).speed(0.1).suffix(" s")).changed() {
+                params.sweep_seconds.store(seconds);
+            }
+        }
+        GeneratorKind::WhiteNoise | GeneratorKind::PinkNoise => {}
+    }
+    let mut level = params.level_db.load();
+    if ui.add(egui::DragValue::new(&mut level).clamp_range(MIN_GENERATOR_LEVEL_DB..=0.0).speed(0.2).suffix(" dBFS")).changed() {
+        params.level_db.store(level);
+    }
+}
+
+// Path field and transport of a file player input; loading works while audio is stopped too
+fn render_file_controls(ui: &mut egui::Ui, path: &mut String, error: &mut Option<String>, player: &FilePlayer) {
+    if path.is_empty() {
+        if let Some(file) = player.file() {
+            *path = file.display().to_string();
+        }
+    }
+    ui.add(egui::TextEdit::singleline(path).desired_width(170.0).hint_text("WAV/FLAC/MP3/OGG path"));
+    if ui.button("Load").clicked() {
+        *error = player.load(Path::new(path.trim())).err();
+    }
+    let playing = player.playing.load(Ordering::Relaxed);
+    if ui.add_enabled(player.file().is_some(), egui::Button::new(if playing { "Pause" } else { "Play" })).clicked() {
+        player.toggle();
+    }
+    let mut looping = player.looping.load(Ordering::Relaxed);
+    if ui.checkbox(&mut looping, "Loop").changed() {
+        player.looping.store(looping, Ordering::Relaxed);
+    }
+    let duration = player.duration.load();
+    let mut position = player.position.load();
+    if ui.add_enabled(duration > 0.0, egui::Slider::new(&mut position, 0.0..=duration.max(0.0)).show_value(false)).changed() {
+        player.seek(position as f64);
+    }
+    let time = |s: f32| format!("{}:{:02}", (s / 60.0) as u32, (s % 60.0) as u32);
+    ui.label(format!("{} / {}", time(position), time(duration)));
+    if let Some(msg) = error.clone().or_else(|| player.error()) {
+        ui.colored_label(Color32::LIGHT_RED, "!").on_hover_text(msg);
+    }
+}
@@ -0,0 +1,391 @@
+// Minimal FLAC encoder used by the recorder.
+//
+// Fixed 4096-frame blocks, independent channels, FIXED-predictor subframes (order 0-4) with a
+// single Rice partition, falling back to VERBATIM when prediction doesn't pay off. That gets
+// most of the way to `flac -0` on music while staying small. The STREAMINFO MD5 is left zero,
+// which the format allows ("unknown").
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+const BLOCK_SIZE: usize = 4096;
+// Byte offset of the packed rate/channels/bps/total-samples field inside the file
+const STREAMINFO_TOTALS_OFFSET: u64 = 4 + 4 + 2 + 2 + 3 + 3;
+const MAX_RICE_PARAM: u32 = 14;
+
+pub struct FlacWriter<W: Write + Seek> {
+    out: W,
+    sample_rate: u32,
+    channels: usize,
+    bits: u32,
+    // Interleaved samples waiting for a full block
+    pending: Vec<i32>,
+    frame_number: u64,
+    total_frames: u64,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    /// `bits` is the stored sample size: 16 or 24.
+    pub fn new(mut out: W, sample_rate: u32, channels: u16, bits: u32) -> io::Result<Self> {
+        if channels == 0 || channels > 8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "FLAC supports 1-8 channels"));
+        }
+        if bits != 16 && bits != 24 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "FLAC writer supports 16 or 24 bits"));
+        }
+        if sample_rate == 0 || sample_rate >= 1 << 20 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported FLAC sample rate"));
+        }
+
+        let mut header = Vec::with_capacity(42);
+        header.extend_from_slice(b"fLaC");
+        // Metadata block header: last block, type 0 (STREAMINFO), length 34
+        header.extend_from_slice(&[0x80, 0, 0, 34]);
+        header.extend_from_slice(&(BLOCK_SIZE as u16).to_be_bytes());
+        header.extend_from_slice(&(BLOCK_SIZE as u16).to_be_bytes());
+        header.extend_from_slice(&[0; 6]); // min/max frame size unknown
+        header.extend_from_slice(&streaminfo_totals(sample_rate, channels as u32, bits, 0).to_be_bytes());
+        header.extend_from_slice(&[0; 16]); // MD5 unknown
+        out.write_all(&header)?;
+
+        Ok(Self {
+            out,
+            sample_rate,
+            channels: channels as usize,
+            bits,
+            pending: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frame_number: 0,
+            total_frames: 0,
+        })
+    }
+
+    /// Append interleaved samples in [-1.0, 1.0]; out-of-range values are clipped.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        let scale = ((1i64 << (self.bits - 1)) - 1) as f32;
+        let block_len = BLOCK_SIZE * self.channels;
+        for &s in samples {
+            self.pending.push((s.clamp(-1.0, 1.0) * scale).round() as i32);
+            if self.pending.len() == block_len {
+                self.flush_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode the last partial block and patch the sample count into STREAMINFO.
+    pub fn finish(mut self) -> io::Result<W> {
+        // Drop a trailing partial frame rather than writing a ragged sample
+        let whole = self.pending.len() / self.channels * self.channels;
+        self.pending.truncate(whole);
+        if !self.pending.is_empty() {
+            self.flush_block()?;
+        }
+        self.out.flush()?;
+        let end = self.out.stream_position()?;
+        self.out.seek(SeekFrom::Start(STREAMINFO_TOTALS_OFFSET))?;
+        self.out.write_all(&streaminfo_totals(self.sample_rate, self.channels as u32, self.bits, self.total_frames).to_be_bytes())?;
+        self.out.seek(SeekFrom::Start(end))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn flush_block(&mut self) -> io::Result<()> {
+        let frames = self.pending.len() / self.channels;
+        let mut bw = BitWriter::new();
+
+        // Frame header
+        bw.write(0xFFF8, 16); // sync code, fixed block size
+        let size_code = if frames == BLOCK_SIZE { 0b1100 } else { 0b0111 };
+        bw.write(size_code, 4);
+        bw.write(sample_rate_code(self.sample_rate), 4);
+        bw.write(self.channels as u64 - 1, 4);
+        bw.write(if self.bits == 16 { 0b100 } else { 0b110 }, 3);
+        bw.write(0, 1);
+        write_utf8_number(&mut bw, self.frame_number);
+        if size_code == 0b0111 {
+            bw.write(frames as u64 - 1, 16);
+        }
+        let crc = crc8(bw.bytes());
+        bw.write(crc as u64, 8);
+
+        // One subframe per channel
+        let mut channel = Vec::with_capacity(frames);
+        for ch in 0..self.channels {
+            channel.clear();
+            channel.extend(self.pending.iter().skip(ch).step_by(self.channels).map(|&s| s as i64));
+            write_subframe(&mut bw, &channel, self.bits);
+        }
+
+        bw.align();
+        let crc = crc16(bw.bytes());
+        bw.write(crc as u64, 16);
+
+        self.out.write_all(bw.bytes())?;
+        self.frame_number += 1;
+        self.total_frames += frames as u64;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+fn streaminfo_totals(sample_rate: u32, channels: u32, bits: u32, total_frames: u64) -> u64 {
+    ((sample_rate as u64) << 44)
+        | (((channels - 1) as u64) << 41)
+        | (((bits - 1) as u64) << 36)
+        | (total_frames & 0xF_FFFF_FFFF)
+}
+
+fn sample_rate_code(rate: u32) -> u64 {
+    match rate {
+        88200 => 0b0001,
+        176400 => 0b0010,
+        192000 => 0b0011,
+        44100 => 0b1001,
+        48000 => 0b1010,
+        96000 => 0b1011,
+        _ => 0b0000, // take it from STREAMINFO
+    }
+}
+
+// Frame numbers use the same variable-length scheme as UTF-8 (extended to 36 bits)
+fn write_utf8_number(bw: &mut BitWriter, n: u64) {
+    if n < 0x80 {
+        bw.write(n, 8);
+        return;
+    }
+    let extra = match n {
+        _ if n < 0x800 => 1,
+        _ if n < 0x1_0000 => 2,
+        _ if n < 0x20_0000 => 3,
+        _ if n < 0x400_0000 => 4,
+        _ if n < 0x8000_0000 => 5,
+        _ => 6,
+    };
+    let lead_mask = (0xFF00u64 >> (extra + 1)) & 0xFF;
+    bw.write(lead_mask | (n >> (6 * extra)), 8);
+    for i in (0..extra).rev() {
+        bw.write(0x80 | ((n >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+fn fixed_residual(samples: &[i64], order: usize, i: usize) -> i64 {
+    let x = samples;
+    match order {
+        0 => x[i],
+        1 => x[i] - x[i - 1],
+        2 => x[i] - 2 * x[i - 1] + x[i - 2],
+        3 => x[i] - 3 * x[i - 1] + 3 * x[i - 2] - x[i - 3],
+        _ => x[i] - 4 * x[i - 1] + 6 * x[i - 2] - 4 * x[i - 3] + x[i - 4],
+    }
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn write_subframe(bw: &mut BitWriter, samples: &[i64], bits: u32) {
+    let n = samples.len();
+    let max_order = 4.min(n.saturating_sub(1));
+
+    // Pick the predictor order with the smallest residual energy (compared over the same range)
+    let start = max_order;
+    let mut best_order = 0;
+    let mut best_sum = u64::MAX;
+    for order in 0..=max_order {
+        let sum: u64 = (start..n).map(|i| fixed_residual(samples, order, i).unsigned_abs()).sum();
+        if sum < best_sum {
+            best_sum = sum;
+            best_order = order;
+        }
+    }
+
+    let count = (n - best_order) as u64;
+    let folded_sum: u64 = (best_order..n).map(|i| zigzag(fixed_residual(samples, best_order, i))).sum();
+    let mut k = 0;
+    while k < MAX_RICE_PARAM && (count << (k + 1)) < folded_sum {
+        k += 1;
+    }
+    let rice_bits: u64 = (best_order..n)
+        .map(|i| (zigzag(fixed_residual(samples, best_order, i)) >> k) + 1 + k as u64)
+        .sum();
+    let fixed_bits = best_order as u64 * bits as u64 + 6 + 4 + rice_bits;
+    let verbatim_bits = n as u64 * bits as u64;
+    let mask = (1u64 << bits) - 1;
+
+    if fixed_bits >= verbatim_bits {
+        bw.write(0b0000_0010, 8); // pad bit, VERBATIM, no wasted bits
+        for &s in samples {
+            bw.write(s as u64 & mask, bits);
+        }
+        return;
+    }
+
+    bw.write((0b001000 | best_order as u64) << 1, 8); // pad bit, FIXED(order), no wasted bits
+    for &s in &samples[..best_order] {
+        bw.write(s as u64 & mask, bits);
+    }
+    bw.write(0b00, 2); // 4-bit Rice parameters
+    bw.write(0, 4); // partition order 0
+    bw.write(k as u64, 4);
+    for i in best_order..n {
+        let u = zigzag(fixed_residual(samples, best_order, i));
+        bw.write_unary(u >> k);
+        if k > 0 {
+            bw.write(u & ((1 << k) - 1), k);
+        }
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    nbits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bytes: Vec::with_capacity(BLOCK_SIZE * 4), acc: 0, nbits: 0 }
+    }
+
+    // `bits` must be <= 32
+    fn write(&mut self, value: u64, bits: u32) {
+        self.acc = (self.acc << bits) | (value & ((1u64 << bits) - 1));
+        self.nbits += bits;
+        while self.nbits >= 8 {
+            self.nbits -= 8;
+            self.bytes.push((self.acc >> self.nbits) as u8);
+        }
+        self.acc &= (1u64 << self.nbits) - 1;
+    }
+
+    // `q` zero bits followed by a one
+    fn write_unary(&mut self, mut q: u64) {
+        while q >= 31 {
+            self.write(0, 31);
+            q -= 31;
+        }
+        self.write(1, q as u32 + 1);
+    }
+
+    fn align(&mut self) {
+        if self.nbits > 0 {
+            let pad = 8 - self.nbits;
+            self.write(0, pad);
+        }
+    }
+
+    // Only complete bytes; callers align first where it matters
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+    use std::io::Cursor;
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::DecoderOptions;
+    use symphonia::core::errors::Error as DecodeError;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    const RATE: u32 = 48_000;
+
+    // Encode interleaved stereo, decode it again and return the rate, the STREAMINFO frame
+    // count and the samples scaled back down to 24 bits
+    fn round_trip(samples: &[f32]) -> (u32, Option<u64>, Vec<i32>) {
+        let mut writer = FlacWriter::new(Cursor::new(Vec::new()), RATE, 2, 24).unwrap();
+        // Uneven writes, like the recorder's ring drains
+        for chunk in samples.chunks(1000) {
+            writer.write_samples(chunk).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let stream = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension("flac");
+        let mut format = symphonia::default::get_probe()
+            .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+            .unwrap()
+            .format;
+        let params = format.default_track().unwrap().codec_params.clone();
+        let mut codec = symphonia::default::get_codecs().make(&params, &DecoderOptions::default()).unwrap();
+        let mut decoded = Vec::new();
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(DecodeError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => panic!("{}", e),
+            };
+            let audio = codec.decode(&packet).unwrap();
+            let mut buffer = SampleBuffer::<i32>::new(audio.capacity() as u64, *audio.spec());
+            buffer.copy_interleaved_ref(audio);
+            // The decoder scales samples up to the full 32-bit range
+            decoded.extend(buffer.samples().iter().map(|&s| s >> 8));
+        }
+        (params.sample_rate.unwrap(), params.n_frames, decoded)
+    }
+
+    fn quantized(samples: &[f32]) -> Vec<i32> {
+        samples.iter().map(|&s| (s.clamp(-1.0, 1.0) * 8_388_607.0).round() as i32).collect()
+    }
+
+    #[test]
+    fn noise_and_sine_decode_exactly() {
+        // Three full blocks and a partial one
+        let frames = 3 * BLOCK_SIZE + 1234;
+        let mut state = 0x1234_5678u32;
+        let mut samples = Vec::with_capacity(frames * 2);
+        for i in 0..frames {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            samples.push(state as f32 / u32::MAX as f32 * 2.0 - 1.0);
+            samples.push(0.7 * (TAU * 997.0 * i as f32 / RATE as f32).sin());
+        }
+
+        let (rate, total, decoded) = round_trip(&samples);
+        assert_eq!(rate, RATE);
+        assert_eq!(total, Some(frames as u64));
+        assert_eq!(decoded.len(), samples.len());
+        let expected = quantized(&samples);
+        if let Some(i) = (0..expected.len()).find(|&i| decoded[i] != expected[i]) {
+            panic!("sample {} (frame {}) decoded as {}, expected {}", i, i / 2, decoded[i], expected[i]);
+        }
+    }
+
+    #[test]
+    fn full_scale_and_silence_decode_exactly() {
+        // Clipped values, constant runs and a single short block
+        let mut samples = vec![0.0f32; 2 * 300];
+        samples.extend([1.5, -1.5, 1.0, -1.0].iter().cycle().take(2 * 200));
+        let (_, total, decoded) = round_trip(&samples);
+        assert_eq!(total, Some(500));
+        assert_eq!(decoded, quantized(&samples));
+    }
+}
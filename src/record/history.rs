@@ -0,0 +1,284 @@
+// Retroactive capture: the last few minutes of the mix and inputs, kept in memory.
+//
+// One background thread drains a ring per tap into a circular buffer; "save" copies the
+// requested span out under the lock and encodes it on another thread, so neither the audio
+// threads nor the UI ever wait for the disk.
+
+use super::session::{Manifest, ManifestTrack, SessionSource};
+use super::{timestamp, unique_dir, FileWriter, RecordFormat, CHUNK_SAMPLES, WRITER_POLL};
+use crate::audio::tap::{BlockTap, TapFormat};
+use ringbuf::{HeapConsumer, HeapRb};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU64, Ordering}};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SECONDS: u32 = 120;
+pub const MIN_SECONDS: u32 = 10;
+pub const MAX_SECONDS: u32 = 600;
+// Hand-off ring between a tap and the history thread
+const RING_SECONDS: usize = 1;
+
+struct HistoryTrack {
+    source: SessionSource,
+    tap_id: u64,
+    format: TapFormat,
+    // Circular, interleaved; `pos` is the next write index
+    buffer: Vec<f32>,
+    pos: usize,
+    filled: usize,
+    // Capture time of the first frame and frames appended since, which place the newest
+    // frame in time (the tap fills gaps with silence, so the count keeps pace)
+    start: Option<Instant>,
+    appended: u64,
+}
+
+impl HistoryTrack {
+    fn append(&mut self, samples: &[f32]) {
+        self.appended += (samples.len() / self.format.channels.max(1) as usize) as u64;
+        if self.start.is_none() {
+            // Same estimate as the recorder: arrival of the first block minus its duration
+            self.start = self.source.tap.first_block(self.tap_id).map(|(arrived, len)| {
+                let frames = len / self.format.channels.max(1) as usize;
+                let duration = Duration::from_secs_f64(frames as f64 / self.format.sample_rate.max(1) as f64);
+                arrived.checked_sub(duration).unwrap_or(arrived)
+            });
+        }
+        let len = self.buffer.len();
+        let mut rest = samples;
+        // Only the newest `len` samples can survive anyway
+        if rest.len() > len {
+            rest = &rest[rest.len() - len..];
+        }
+        while !rest.is_empty() {
+            let n = rest.len().min(len - self.pos);
+            self.buffer[self.pos..self.pos + n].copy_from_slice(&rest[..n]);
+            self.pos = (self.pos + n) % len;
+            self.filled = (self.filled + n).min(len);
+            rest = &rest[n..];
+        }
+    }
+
+    // When the newest frame was captured
+    fn end_time(&self) -> Option<Instant> {
+        let duration = Duration::from_secs_f64(self.appended as f64 / self.format.sample_rate.max(1) as f64);
+        self.start.map(|t| t + duration)
+    }
+
+    // Up to `frames` frames ending `skip` frames before the newest one, oldest first
+    fn frames_before(&self, skip: usize, frames: usize) -> Vec<f32> {
+        let channels = self.format.channels as usize;
+        let skip = (skip * channels).min(self.filled);
+        let take = (frames * channels).min(self.filled - skip) / channels * channels;
+        let len = self.buffer.len();
+        let start = (self.pos + 2 * len - skip - take) % len;
+        let mut out = Vec::with_capacity(take);
+        if start + take <= len {
+            out.extend_from_slice(&self.buffer[start..start + take]);
+        } else {
+            out.extend_from_slice(&self.buffer[start..]);
+            out.extend_from_slice(&self.buffer[..take - (len - start)]);
+        }
+        out
+    }
+}
+
+/// Rolling in-memory history of several taps.
+pub struct History {
+    seconds: u32,
+    tracks: Arc<Mutex<Vec<HistoryTrack>>>,
+    taps: Vec<(Arc<BlockTap>, u64)>,
+    stop_flag: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    // Saves in flight, and the outcome of the last one for status lines
+    saving: Arc<AtomicU64>,
+    last_save: Arc<Mutex<Option<Result<PathBuf, String>>>>,
+}
+
+impl History {
+    /// Start keeping the last `seconds` of every running source.
+    pub fn start(sources: Vec<SessionSource>, seconds: u32) -> Result<Self, String> {
+        let seconds = seconds.clamp(MIN_SECONDS, MAX_SECONDS);
+        let mut tracks = Vec::new();
+        let mut consumers: Vec<HeapConsumer<f32>> = Vec::new();
+        let mut taps = Vec::new();
+        for source in sources {
+            let format = match source.tap.format() {
+                Some(f) => f,
+                None => continue,
+            };
+            let channels = format.channels as usize;
+            let (producer, consumer) = HeapRb::<f32>::new(format.sample_rate as usize * channels * RING_SECONDS).split();
+            let tap_id = source.tap.attach(producer);
+            taps.push((source.tap.clone(), tap_id));
+            consumers.push(consumer);
+            tracks.push(HistoryTrack {
+                buffer: vec![0.0; format.sample_rate as usize * channels * seconds as usize],
+                source,
+                tap_id,
+                format,
+                pos: 0,
+                filled: 0,
+                start: None,
+                appended: 0,
+            });
+        }
+        if tracks.is_empty() {
+            return Err("Audio is not running".into());
+        }
+
+        let channels: Vec<usize> = tracks.iter().map(|t| t.format.channels.max(1) as usize).collect();
+        let tracks = Arc::new(Mutex::new(tracks));
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let tracks = tracks.clone();
+            let stop_flag = stop_flag.clone();
+            thread::spawn(move || {
+                let mut chunk = vec![0.0f32; CHUNK_SAMPLES];
+                while !stop_flag.load(Ordering::Relaxed) {
+                    let mut idle = true;
+                    for (idx, consumer) in consumers.iter_mut().enumerate() {
+                        let n = consumer.pop_slice(&mut chunk[..CHUNK_SAMPLES / channels[idx] * channels[idx]]);
+                        if n > 0 {
+                            tracks.lock().unwrap()[idx].append(&chunk[..n]);
+                            idle = false;
+                        }
+                    }
+                    if idle { thread::sleep(WRITER_POLL); }
+                }
+            })
+        };
+
+        Ok(Self {
+            seconds,
+            tracks,
+            taps,
+            stop_flag,
+            thread: Some(thread),
+            saving: Arc::new(AtomicU64::new(0)),
+            last_save: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Capacity in seconds.
+    pub fn seconds(&self) -> u32 {
+        self.seconds
+    }
+
+    /// How much history is available right now (the shortest track).
+    pub fn buffered_seconds(&self) -> f32 {
+        self.tracks.lock().unwrap().iter()
+            .map(|t| (t.filled / t.format.channels as usize) as f32 / t.format.sample_rate as f32)
+            .fold(f32::MAX, f32::min)
+    }
+
+    /// Stop collecting (audio stopped); what was captured stays available for `save`.
+    pub fn pause(&mut self) {
+        for (tap, id) in self.taps.drain(..) {
+            tap.detach(id);
+        }
+        self.stop_flag.store(true, Ordering::Relaxed);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+
+    /// Write the last `seconds` of every track to a new `history_<timestamp>` folder under `dir`
+    /// (same layout as a multitrack session). Encoding happens in the background; the returned
+    /// path is where the files will appear.
+    pub fn save(&self, dir: &Path, seconds: u32, format: RecordFormat) -> Result<PathBuf, String> {
+        let created = timestamp();
+        let out_dir = unique_dir(dir, "history", &created);
+
+        // Copy out under the lock, then let the history thread carry on. The tracks were drained
+        // to slightly different points, so every clip ends at the earliest of their newest frames;
+        // clips that begin later than the others (shorter history) start with silence, as in a
+        // multitrack session, and the manifest records that lead-in.
+        let tracks = self.tracks.lock().unwrap();
+        let end = tracks.iter().filter_map(HistoryTrack::end_time).min();
+        let clips: Vec<(Vec<f32>, Option<Instant>)> = tracks.iter().map(|t| {
+            let rate = t.format.sample_rate as f64;
+            let (skip, clip_end) = match (t.end_time(), end) {
+                (Some(own), Some(end)) => ((own.duration_since(end).as_secs_f64() * rate).round() as usize, Some(end)),
+                _ => (0, None),
+            };
+            let samples = t.frames_before(skip, t.format.sample_rate as usize * seconds as usize);
+            let frames = samples.len() / t.format.channels.max(1) as usize;
+            let clip_start = clip_end.map(|e| e.checked_sub(Duration::from_secs_f64(frames as f64 / rate.max(1.0))).unwrap_or(e));
+            (samples, clip_start)
+        }).collect();
+        let earliest = clips.iter().filter_map(|(_, start)| *start).min();
+        let snapshot: Vec<(ManifestTrack, Vec<f32>)> = tracks.iter().zip(clips).map(|(t, (clip, start))| {
+            let channels = t.format.channels as usize;
+            let lead_in = match (start, earliest) {
+                (Some(start), Some(earliest)) => ((start - earliest).as_secs_f64() * t.format.sample_rate as f64).round() as usize,
+                _ => 0,
+            };
+            let mut samples = vec![0.0; lead_in * channels];
+            samples.extend_from_slice(&clip);
+            let track = ManifestTrack {
+                file: format!("{}.{}", t.source.name, format.extension()),
+                source: t.source.name.clone(),
+                device: t.source.device.as_ref().map(|d| d.name.clone()),
+                device_id: t.source.device.as_ref().and_then(|d| d.device_id.clone()),
+                mode: t.source.device.as_ref().map(|d| d.mode),
+                sample_rate: t.format.sample_rate,
+                channels: t.format.channels,
+                frames: Some((samples.len() / channels) as u64),
+                lead_in_frames: Some(lead_in as u64),
+            };
+            (track, samples)
+        }).collect();
+        drop(tracks);
+
+        fs::create_dir_all(&out_dir).map_err(|e| format!("Failed to create {}: {}", out_dir.display(), e))?;
+        let manifest = Manifest {
+            created,
+            format: format.extension(),
+            sample_type: format.sample_type(),
+            tracks: snapshot.iter().map(|(t, _)| t.clone()).collect(),
+        };
+        manifest.write(&out_dir)?;
+
+        let saving = self.saving.clone();
+        let last_save = self.last_save.clone();
+        let path = out_dir.clone();
+        saving.fetch_add(1, Ordering::Relaxed);
+        thread::spawn(move || {
+            let result = snapshot.iter()
+                .try_for_each(|(track, samples)| write_file(&out_dir.join(&track.file), format, track, samples))
+                .map(|()| out_dir);
+            match &result {
+                Ok(p) => println!("History saved to {}", p.display()),
+                Err(msg) => eprintln!("{}", msg),
+            }
+            *last_save.lock().unwrap() = Some(result);
+            saving.fetch_sub(1, Ordering::Relaxed);
+        });
+        Ok(path)
+    }
+
+    pub fn is_saving(&self) -> bool {
+        self.saving.load(Ordering::Relaxed) > 0
+    }
+
+    pub fn last_save(&self) -> Option<Result<PathBuf, String>> {
+        self.last_save.lock().unwrap().clone()
+    }
+}
+
+impl Drop for History {
+    fn drop(&mut self) {
+        self.pause();
+    }
+}
+
+fn write_file(path: &Path, format: RecordFormat, track: &ManifestTrack, samples: &[f32]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut writer = FileWriter::new(BufWriter::new(file), format, track.sample_rate, track.channels)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    writer.write(samples).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    writer.finish().map_err(|e| format!("Failed to finalize {}: {}", path.display(), e))
+}
@@ -0,0 +1,407 @@
+// Recording of tapped audio to disk.
+//
+// The audio thread only pushes into a ring buffer attached to a `BlockTap`; a dedicated writer
+// thread drains it and does all encoding and file I/O.
+
+pub mod flac;
+pub mod history;
+pub mod session;
+
+use crate::audio::tap::BlockTap;
+use flac::FlacWriter;
+use ringbuf::{HeapConsumer, HeapRb};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, atomic::{AtomicBool, AtomicU64, Ordering}};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_DIR: &str = "recordings";
+// Ring capacity in seconds of audio; the writer normally drains it every few milliseconds
+const RING_SECONDS: usize = 2;
+const WRITER_POLL: Duration = Duration::from_millis(10);
+// Largest block moved from a ring to a file in one go (samples)
+const CHUNK_SAMPLES: usize = 32 * 1024;
+// How long to wait for every track's first block before aligning without the stragglers
+const ALIGN_TIMEOUT: Duration = Duration::from_secs(1);
+const FLAC_BITS: u32 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecordFormat {
+    #[default]
+    Wav,
+    Flac,
+}
+
+impl RecordFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            RecordFormat::Wav => "wav",
+            RecordFormat::Flac => "flac",
+        }
+    }
+
+    /// Sample type stored in the file.
+    pub fn sample_type(self) -> &'static str {
+        match self {
+            RecordFormat::Wav => "f32",
+            RecordFormat::Flac => "s24",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "wav" => Some(RecordFormat::Wav),
+            "flac" => Some(RecordFormat::Flac),
+            _ => None,
+        }
+    }
+}
+
+/// One file of a recording: everything `tap` receives from now on goes to `path`.
+pub struct TrackSpec {
+    pub tap: Arc<BlockTap>,
+    pub path: PathBuf,
+}
+
+/// Length of a finished track and how it was lined up with the others.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrackResult {
+    pub frames: u64,
+    /// Silence written ahead of the first captured block so all tracks share a start time.
+    pub lead_in_frames: u64,
+}
+
+/// An in-progress recording of one or more taps. Dropping it finalizes the files as well;
+/// `stop` does the same but reports errors.
+pub struct Recorder {
+    // The file for a single track, the session folder for multitrack
+    path: PathBuf,
+    taps: Vec<(Arc<BlockTap>, u64)>,
+    sample_rate: u32,
+    stop_flag: Arc<AtomicBool>,
+    // Length of the first track
+    frames: Arc<AtomicU64>,
+    bytes: Vec<Arc<AtomicU64>>,
+    manifest: Option<session::Manifest>,
+    thread: Option<JoinHandle<Result<Vec<TrackResult>, String>>>,
+}
+
+impl Recorder {
+    /// Start recording everything `tap` receives into `path`.
+    pub fn start(tap: Arc<BlockTap>, path: PathBuf, format: RecordFormat) -> Result<Self, String> {
+        Self::start_tracks(path.clone(), vec![TrackSpec { tap, path }], format)
+    }
+
+    /// Record several taps at once, one file each, sample-aligned to a common start time.
+    pub fn start_tracks(path: PathBuf, specs: Vec<TrackSpec>, format: RecordFormat) -> Result<Self, String> {
+        // Open every file before attaching anything so a failure leaves the taps untouched
+        let mut opened = Vec::with_capacity(specs.len());
+        for spec in specs {
+            let tap_format = spec.tap.format().ok_or("Audio is not running")?;
+            if let Some(dir) = spec.path.parent() {
+                fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+            }
+            let bytes = Arc::new(AtomicU64::new(0));
+            let file = File::create(&spec.path).map_err(|e| format!("Failed to create {}: {}", spec.path.display(), e))?;
+            let out = CountingWriter::new(BufWriter::new(file), bytes.clone());
+            let writer = FileWriter::new(out, format, tap_format.sample_rate, tap_format.channels)
+                .map_err(|e| format!("Failed to write {}: {}", spec.path.display(), e))?;
+            opened.push((spec.tap, tap_format, writer, bytes));
+        }
+        let sample_rate = opened.first().map(|(_, f, _, _)| f.sample_rate).ok_or("Nothing to record")?;
+
+        let mut tracks = Vec::with_capacity(opened.len());
+        let mut taps = Vec::with_capacity(opened.len());
+        let mut bytes = Vec::with_capacity(opened.len());
+        for (tap, tap_format, writer, track_bytes) in opened {
+            let channels = tap_format.channels as usize;
+            let (producer, consumer) = HeapRb::<f32>::new(tap_format.sample_rate as usize * channels * RING_SECONDS).split();
+            let tap_id = tap.attach(producer);
+            taps.push((tap.clone(), tap_id));
+            bytes.push(track_bytes);
+            tracks.push(Track { tap, tap_id, consumer, channels, sample_rate: tap_format.sample_rate, writer, samples: 0, lead_in: 0 });
+        }
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let frames = Arc::new(AtomicU64::new(0));
+        let thread = {
+            let stop_flag = stop_flag.clone();
+            let frames = frames.clone();
+            thread::spawn(move || write_tracks(tracks, &stop_flag, &frames))
+        };
+
+        Ok(Self { path, taps, sample_rate, stop_flag, frames, bytes, manifest: None, thread: Some(thread) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Recorded length (audio time, not wall-clock time).
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.frames.load(Ordering::Relaxed) as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    /// Total size of all files written so far.
+    pub fn file_size(&self) -> u64 {
+        self.bytes.iter().map(|b| b.load(Ordering::Relaxed)).sum()
+    }
+
+    /// Detach from the taps, write out what is still buffered and close the files.
+    pub fn stop(mut self) -> Result<PathBuf, String> {
+        self.finish()?;
+        Ok(self.path.clone())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        let thread = match self.thread.take() {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        for (tap, id) in &self.taps {
+            tap.detach(*id);
+        }
+        self.stop_flag.store(true, Ordering::Release);
+        let results = thread.join().map_err(|_| "Recording thread panicked".to_string())??;
+        match self.manifest.take() {
+            Some(manifest) => manifest.finish(&self.path, &results),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(msg) = self.finish() {
+            eprintln!("{}", msg);
+        }
+    }
+}
+
+struct Track {
+    tap: Arc<BlockTap>,
+    tap_id: u64,
+    consumer: HeapConsumer<f32>,
+    channels: usize,
+    sample_rate: u32,
+    writer: FileWriter<Output>,
+    samples: u64,
+    lead_in: u64,
+}
+
+impl Track {
+    // When the first sample of the first block was captured, estimated from its arrival
+    fn start_time(&self) -> Option<Instant> {
+        self.tap.first_block(self.tap_id).map(|(arrived, len)| {
+            let duration = Duration::from_secs_f64((len / self.channels) as f64 / self.sample_rate.max(1) as f64);
+            arrived.checked_sub(duration).unwrap_or(arrived)
+        })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.writer.write(samples).map_err(|e| format!("Recording write failed: {}", e))?;
+        self.samples += samples.len() as u64;
+        Ok(())
+    }
+}
+
+// Writer thread body: align, then drain every ring until stopped and empty
+fn write_tracks(mut tracks: Vec<Track>, stop_flag: &AtomicBool, frames: &AtomicU64) -> Result<Vec<TrackResult>, String> {
+    align_starts(&mut tracks, stop_flag)?;
+
+    let mut chunk = vec![0.0f32; CHUNK_SAMPLES];
+    loop {
+        // Check the flag before draining so nothing pushed before `stop` is lost
+        let stopping = stop_flag.load(Ordering::Acquire);
+        let mut idle = true;
+        for track in tracks.iter_mut() {
+            // Whole frames only, so a write never ends mid-frame
+            let n = track.consumer.pop_slice(&mut chunk[..CHUNK_SAMPLES / track.channels * track.channels]);
+            if n > 0 {
+                track.write(&chunk[..n])?;
+                idle = false;
+            }
+        }
+        if let Some(first) = tracks.first() {
+            frames.store(first.samples / first.channels as u64, Ordering::Relaxed);
+        }
+        if idle {
+            if stopping { break; }
+            thread::sleep(WRITER_POLL);
+        }
+    }
+
+    let mut results = Vec::with_capacity(tracks.len());
+    for track in tracks {
+        results.push(TrackResult { frames: track.samples / track.channels as u64, lead_in_frames: track.lead_in });
+        track.writer.finish().map_err(|e| format!("Failed to finalize recording: {}", e))?;
+    }
+    Ok(results)
+}
+
+// Pad late-starting tracks with silence so every file begins at the same moment. Each input
+// runs on its own device clock, so this is as close as block timing gets; per-input delay
+// takes care of any fixed offset that remains.
+fn align_starts(tracks: &mut [Track], stop_flag: &AtomicBool) -> Result<(), String> {
+    if tracks.len() < 2 {
+        return Ok(());
+    }
+    let deadline = Instant::now() + ALIGN_TIMEOUT;
+    let starts = loop {
+        let starts: Vec<Option<Instant>> = tracks.iter().map(Track::start_time).collect();
+        if starts.iter().all(Option::is_some) || stop_flag.load(Ordering::Acquire) || Instant::now() >= deadline {
+            break starts;
+        }
+        thread::sleep(WRITER_POLL);
+    };
+    let earliest = match starts.iter().flatten().min() {
+        Some(t) => *t,
+        None => return Ok(()),
+    };
+
+    let silence = vec![0.0f32; CHUNK_SAMPLES];
+    for (track, start) in tracks.iter_mut().zip(starts) {
+        let start = match start {
+            Some(t) => t,
+            None => continue,
+        };
+        track.lead_in = ((start - earliest).as_secs_f64() * track.sample_rate as f64).round() as u64;
+        let mut remaining = track.lead_in as usize * track.channels;
+        while remaining > 0 {
+            let n = remaining.min(silence.len());
+            track.write(&silence[..n])?;
+            remaining -= n;
+        }
+    }
+    Ok(())
+}
+
+/// `<dir>/<prefix>_YYYYMMDD_HHMMSS.<ext>` using the current UTC time.
+pub fn timestamped_path(dir: &Path, prefix: &str, format: RecordFormat) -> PathBuf {
+    dir.join(format!("{}_{}.{}", prefix, timestamp(), format.extension()))
+}
+
+/// `<dir>/<prefix>_<timestamp>`, with a `_2`, `_3`... suffix if that already exists
+/// (two saves within the same second).
+pub fn unique_dir(dir: &Path, prefix: &str, timestamp: &str) -> PathBuf {
+    let base = dir.join(format!("{}_{}", prefix, timestamp));
+    let mut path = base.clone();
+    let mut n = 2;
+    while path.exists() {
+        path = PathBuf::from(format!("{}_{}", base.display(), n));
+        n += 1;
+    }
+    path
+}
+
+pub fn timestamp() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}{:02}{:02}_{:02}{:02}{:02}", year, month, day, rem / 3600, (rem / 60) % 60, rem % 60)
+}
+
+/// Human-readable file size for status lines.
+pub fn format_size(bytes: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+    if bytes as f64 >= 1024.0 * MB {
+        format!("{:.2} GB", bytes as f64 / (1024.0 * MB))
+    } else {
+        format!("{:.1} MB", bytes as f64 / MB)
+    }
+}
+
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+type Output = CountingWriter<BufWriter<File>>;
+
+enum FileWriter<W: Write + Seek> {
+    Wav(hound::WavWriter<W>),
+    Flac(FlacWriter<W>),
+}
+
+impl<W: Write + Seek> FileWriter<W> {
+    fn new(out: W, format: RecordFormat, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        Ok(match format {
+            RecordFormat::Wav => {
+                // 32-bit float keeps the mix bit-exact, including overs above 0 dBFS
+                let spec = hound::WavSpec { channels, sample_rate, bits_per_sample: 32, sample_format: hound::SampleFormat::Float };
+                FileWriter::Wav(hound::WavWriter::new(out, spec).map_err(hound_error)?)
+            }
+            RecordFormat::Flac => FileWriter::Flac(FlacWriter::new(out, sample_rate, channels, FLAC_BITS)?),
+        })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        match self {
+            FileWriter::Wav(w) => {
+                for &s in samples {
+                    w.write_sample(s).map_err(hound_error)?;
+                }
+                Ok(())
+            }
+            FileWriter::Flac(w) => w.write_samples(samples),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            FileWriter::Wav(w) => w.finalize().map_err(hound_error),
+            FileWriter::Flac(w) => w.finish().and_then(|mut out| out.flush()),
+        }
+    }
+}
+
+fn hound_error(e: hound::Error) -> io::Error {
+    match e {
+        hound::Error::IoError(e) => e,
+        other => io::Error::other(other.to_string()),
+    }
+}
+
+/// Tracks the size of the file being written so the UI can show it without touching the disk.
+struct CountingWriter<W> {
+    inner: W,
+    pos: u64,
+    size: Arc<AtomicU64>,
+}
+
+impl<W> CountingWriter<W> {
+    fn new(inner: W, size: Arc<AtomicU64>) -> Self {
+        Self { inner, pos: 0, size }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.pos += n as u64;
+        self.size.fetch_max(self.pos, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for CountingWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
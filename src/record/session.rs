@@ -0,0 +1,99 @@
+// Multitrack sessions: the mix and every input in their own files, plus a manifest.
+//
+//   recordings/session_<timestamp>/
+//     mix.wav, input_a.wav, input_b.wav   one file per source, sharing a start time
+//     session.json                        devices and formats; lengths are filled in when the take ends
+
+use super::{timestamp, unique_dir, RecordFormat, Recorder, TrackResult, TrackSpec};
+use crate::audio::backend::{DeviceEntry, Mode};
+use crate::audio::tap::BlockTap;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+const MANIFEST_FILE: &str = "session.json";
+
+/// A tap to record plus what the manifest should say about where it comes from.
+pub struct SessionSource {
+    /// File stem, e.g. "mix" or "input_a".
+    pub name: String,
+    pub tap: Arc<BlockTap>,
+    pub device: Option<DeviceEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Manifest {
+    pub created: String,
+    pub format: &'static str,
+    pub sample_type: &'static str,
+    pub tracks: Vec<ManifestTrack>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestTrack {
+    pub file: String,
+    pub source: String,
+    pub device: Option<String>,
+    pub device_id: Option<String>,
+    pub mode: Option<Mode>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub frames: Option<u64>,
+    pub lead_in_frames: Option<u64>,
+}
+
+impl Manifest {
+    pub(super) fn write(&self, dir: &Path) -> Result<(), String> {
+        let path = dir.join(MANIFEST_FILE);
+        let text = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+        fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Record the final track lengths and rewrite the manifest.
+    pub(super) fn finish(mut self, dir: &Path, results: &[TrackResult]) -> Result<(), String> {
+        for (track, result) in self.tracks.iter_mut().zip(results) {
+            track.frames = Some(result.frames);
+            track.lead_in_frames = Some(result.lead_in_frames);
+        }
+        self.write(dir)
+    }
+}
+
+impl Recorder {
+    /// Record every source into a new `session_<timestamp>` folder under `dir`.
+    pub fn start_session(dir: &Path, sources: Vec<SessionSource>, format: RecordFormat) -> Result<Self, String> {
+        let created = timestamp();
+        let session_dir = unique_dir(dir, "session", &created);
+        fs::create_dir_all(&session_dir).map_err(|e| format!("Failed to create {}: {}", session_dir.display(), e))?;
+
+        let mut tracks = Vec::with_capacity(sources.len());
+        let mut specs = Vec::with_capacity(sources.len());
+        for source in sources {
+            let tap_format = match source.tap.format() {
+                Some(f) => f,
+                None => continue, // slot not running
+            };
+            let file = format!("{}.{}", source.name, format.extension());
+            tracks.push(ManifestTrack {
+                file: file.clone(),
+                source: source.name,
+                device: source.device.as_ref().map(|d| d.name.clone()),
+                device_id: source.device.as_ref().and_then(|d| d.device_id.clone()),
+                mode: source.device.as_ref().map(|d| d.mode),
+                sample_rate: tap_format.sample_rate,
+                channels: tap_format.channels,
+                frames: None,
+                lead_in_frames: None,
+            });
+            specs.push(TrackSpec { tap: source.tap, path: session_dir.join(file) });
+        }
+
+        let manifest = Manifest { created, format: format.extension(), sample_type: format.sample_type(), tracks };
+        manifest.write(&session_dir)?;
+
+        let mut recorder = Self::start_tracks(session_dir, specs, format)?;
+        recorder.manifest = Some(manifest);
+        Ok(recorder)
+    }
+}
@@ -0,0 +1,341 @@
+use crate::analysis::latency::{self, MeasureStatus};
+use crate::analysis::loudness::LoudnessMeter;
+use crate::analysis::spectrum::{self, SpectrumAnalyzer, SpectrumSource};
+use crate::analysis::stereo::StereoAnalyzer;
+use crate::audio::backend::{ChannelRange, DeviceEntry, InputSelection, InputSource, OutputSelection};
+use crate::audio::controls::{AtomicF32, MixControls, MAX_CUE_BUSES, MAX_OUTPUTS};
+use crate::audio::tap::BlockTap;
+use crate::record::{self, RecordFormat, Recorder};
+use crate::record::history::{self, History};
+use crate::record::session::SessionSource;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU32, Ordering}};
+
+/// What feeds an input slot, as picked in the UI.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputKind {
+    /// The slot's device (`Routing::input_a` / `input_b`).
+    #[default]
+    Device,
+    /// The slot's test signal generator.
+    Generator,
+    /// The slot's file player.
+    File,
+}
+
+impl InputKind {
+    pub const ALL: [InputKind; 3] = [Self::Device, Self::Generator, Self::File];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Device => "Device",
+            Self::Generator => "Generator",
+            Self::File => "File",
+        }
+    }
+
+    /// Name used by the config file and the API.
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Device => "device",
+            Self::Generator => "generator",
+            Self::File => "file",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|k| k.key() == key)
+    }
+}
+
+/// Selected device indices (into the backend's `enumerate_devices` list).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Routing {
+    pub input_a: Option<usize>,
+    pub input_b: Option<usize>,
+    /// What feeds each input slot; the device selection is kept while another source plays.
+    pub sources: [InputKind; 2],
+    pub output: Option<usize>,
+    /// Channel subsets of the input devices; `None` uses every channel.
+    pub channels_a: Option<ChannelRange>,
+    pub channels_b: Option<ChannelRange>,
+    /// Output channels the mix goes to; `None` fills every channel.
+    pub output_channels: Option<ChannelRange>,
+    /// Extra outputs playing a copy of the mix (output slots 1.. of `MixControls::outputs`).
+    pub extra_outputs: [Option<OutputSelection>; MAX_OUTPUTS - 1],
+    /// Output of each cue bus (`MixControls::cue_buses`); a bus without one isn't mixed.
+    pub cue_outputs: [Option<OutputSelection>; MAX_CUE_BUSES],
+}
+
+impl Routing {
+    /// What input slot `slot` (0 = A, 1 = B) captures, as the backend takes it.
+    pub fn input(&self, slot: usize) -> Option<InputSelection> {
+        let (device, channels) = if slot == 0 { (self.input_a, self.channels_a) } else { (self.input_b, self.channels_b) };
+        match self.sources[slot] {
+            InputKind::Device => device.map(|device| InputSelection { source: InputSource::Device(device), channels }),
+            InputKind::Generator => Some(InputSelection { source: InputSource::Generator, channels: None }),
+            InputKind::File => Some(InputSelection { source: InputSource::File, channels: None }),
+        }
+    }
+
+    /// Every output slot, main output first, as the backend takes them.
+    pub fn outputs(&self) -> [Option<OutputSelection>; MAX_OUTPUTS] {
+        let mut outputs = [None; MAX_OUTPUTS];
+        outputs[0] = self.output.map(|device| OutputSelection { device, channels: self.output_channels });
+        outputs[1..].copy_from_slice(&self.extra_outputs);
+        outputs
+    }
+}
+
+/// Requests that have to run on the thread owning the backend (the UI thread).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Start,
+    Stop,
+}
+
+/// Application state shared between `AudioApp` and the remote control API.
+/// The backend itself is not shared: start/stop requests are queued as `Command`s
+/// and picked up by whoever owns the backend.
+pub struct AppState {
+    pub controls: Arc<MixControls>,
+    pub devices: Mutex<Vec<DeviceEntry>>,
+    pub routing: Mutex<Routing>,
+    pub audio_started: AtomicBool,
+    pub last_error: Mutex<Option<String>>,
+    /// Output mix recording, if one is running.
+    pub recorder: Mutex<Option<Recorder>>,
+    /// Rolling history of the mix and inputs for "save last N seconds".
+    pub history: Mutex<Option<History>>,
+    /// History length used from the next start; 0 disables it.
+    pub history_seconds: AtomicU32,
+    /// EBU R128 loudness of the output; kept after audio stops so the last readings stay visible.
+    pub loudness: Mutex<Option<LoudnessMeter>>,
+    /// Spectrum of the selected source while audio runs.
+    pub spectrum: Mutex<Option<SpectrumAnalyzer>>,
+    spectrum_source: Mutex<SpectrumSource>,
+    spectrum_smoothing: AtomicF32,
+    /// Correlation/goniometer per source, in `SpectrumSource::ALL` order; `None` for mono or unused slots.
+    pub stereo: Mutex<[Option<StereoAnalyzer>; 3]>,
+    /// Round-trip latency measurement in progress, or its last outcome.
+    pub latency_test: Arc<Mutex<MeasureStatus>>,
+    commands: Mutex<Vec<Command>>,
+    // Called after a command is queued so an idle UI wakes up to process it
+    waker: Mutex<Option<Box<dyn Fn() + Send + Sync>>>,
+}
+
+impl AppState {
+    pub fn new(devices: Vec<DeviceEntry>) -> Self {
+        Self {
+            controls: Arc::new(MixControls::new()),
+            devices: Mutex::new(devices),
+            routing: Mutex::new(Routing::default()),
+            audio_started: AtomicBool::new(false),
+            last_error: Mutex::new(None),
+            recorder: Mutex::new(None),
+            history: Mutex::new(None),
+            history_seconds: AtomicU32::new(history::DEFAULT_SECONDS),
+            loudness: Mutex::new(None),
+            spectrum: Mutex::new(None),
+            spectrum_source: Mutex::new(SpectrumSource::default()),
+            spectrum_smoothing: AtomicF32::new(spectrum::DEFAULT_SMOOTHING),
+            stereo: Mutex::new([None, None, None]),
+            latency_test: Arc::new(Mutex::new(MeasureStatus::Idle)),
+            commands: Mutex::new(Vec::new()),
+            waker: Mutex::new(None),
+        }
+    }
+
+    pub fn set_waker(&self, waker: Box<dyn Fn() + Send + Sync>) {
+        *self.waker.lock().unwrap() = Some(waker);
+    }
+
+    pub fn push_command(&self, cmd: Command) {
+        self.commands.lock().unwrap().push(cmd);
+        if let Some(wake) = self.waker.lock().unwrap().as_ref() {
+            wake();
+        }
+    }
+
+    pub fn take_commands(&self) -> Vec<Command> {
+        std::mem::take(&mut *self.commands.lock().unwrap())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.audio_started.load(Ordering::Relaxed)
+    }
+
+    pub fn routing(&self) -> Routing {
+        *self.routing.lock().unwrap()
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().clone()
+    }
+
+    /// Start recording the output mix into a new timestamped file under `dir`. With `multitrack`
+    /// the mix and each running input go into their own files in a session folder instead.
+    pub fn start_recording(&self, dir: &Path, format: RecordFormat, multitrack: bool) -> Result<PathBuf, String> {
+        if !self.is_running() {
+            return Err("Audio is not running".into());
+        }
+        let mut recorder = self.recorder.lock().unwrap();
+        if let Some(r) = recorder.as_ref() {
+            return Err(format!("Already recording to {}", r.path().display()));
+        }
+
+        let started = if multitrack {
+            Recorder::start_session(dir, self.session_sources(), format)?
+        } else {
+            Recorder::start(self.controls.output_tap.clone(), record::timestamped_path(dir, "mix", format), format)?
+        };
+        let path = started.path().to_path_buf();
+        *recorder = Some(started);
+        Ok(path)
+    }
+
+    /// Finish the current recording; `None` if nothing was being recorded.
+    pub fn stop_recording(&self) -> Option<Result<PathBuf, String>> {
+        let recorder = self.recorder.lock().unwrap().take();
+        recorder.map(Recorder::stop)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.lock().unwrap().is_some()
+    }
+
+    // The mix and both input slots, named the way session folders name their files
+    fn session_sources(&self) -> Vec<SessionSource> {
+        let routing = self.routing();
+        let devices = self.devices.lock().unwrap();
+        let device = |idx: Option<usize>| idx.and_then(|i| devices.get(i)).cloned();
+        let controls = &self.controls;
+        vec![
+            SessionSource { name: "mix".into(), tap: controls.output_tap.clone(), device: device(routing.output) },
+            SessionSource { name: "input_a".into(), tap: controls.input_taps[0].clone(), device: device(routing.input(0).and_then(|s| s.device())) },
+            SessionSource { name: "input_b".into(), tap: controls.input_taps[1].clone(), device: device(routing.input(1).and_then(|s| s.device())) },
+        ]
+    }
+
+    /// (Re)start the rolling history after audio starts; any previous history is discarded.
+    pub fn start_history(&self) {
+        let seconds = self.history_seconds.load(Ordering::Relaxed);
+        let mut slot = self.history.lock().unwrap();
+        *slot = None;
+        if seconds == 0 || !self.is_running() {
+            return;
+        }
+        match History::start(self.session_sources(), seconds) {
+            Ok(h) => *slot = Some(h),
+            Err(msg) => eprintln!("Failed to start history buffer: {}", msg),
+        }
+    }
+
+    /// Audio stopped: stop collecting but keep what is buffered so it can still be saved.
+    pub fn pause_history(&self) {
+        if let Some(h) = self.history.lock().unwrap().as_mut() {
+            h.pause();
+        }
+    }
+
+    /// Dump the last `seconds` of history to a new folder under `dir`.
+    pub fn save_history(&self, dir: &Path, seconds: u32, format: RecordFormat) -> Result<PathBuf, String> {
+        match self.history.lock().unwrap().as_ref() {
+            Some(h) => h.save(dir, seconds, format),
+            None => Err("History buffer is not enabled".into()),
+        }
+    }
+
+    /// Measure the round trip from the main output back through input `slot` (loopback
+    /// cable) on a worker thread; follow it in `latency_test`.
+    pub fn start_latency_test(&self, slot: usize, runs: usize) -> Result<(), String> {
+        if !self.is_running() {
+            return Err("Audio is not running".into());
+        }
+        let mut status = self.latency_test.lock().unwrap();
+        if matches!(*status, MeasureStatus::Running { .. }) {
+            return Err("A measurement is already running".into());
+        }
+        let runs = runs.clamp(1, latency::MAX_RUNS);
+        *status = MeasureStatus::Running { done: 0, runs };
+        let controls = self.controls.clone();
+        let shared = self.latency_test.clone();
+        thread::spawn(move || {
+            let result = latency::measure(&controls, slot, runs, |done| *shared.lock().unwrap() = MeasureStatus::Running { done, runs });
+            *shared.lock().unwrap() = match result {
+                Ok(stats) => MeasureStatus::Done(stats),
+                Err(msg) => MeasureStatus::Failed(msg),
+            };
+        });
+        Ok(())
+    }
+
+    /// Start the output analyzers after audio starts; previous readings are discarded.
+    pub fn start_analysis(&self) {
+        let mut loudness = self.loudness.lock().unwrap();
+        *loudness = None;
+        match LoudnessMeter::start(self.controls.output_tap.clone()) {
+            Ok(m) => *loudness = Some(m),
+            Err(msg) => eprintln!("Failed to start loudness meter: {}", msg),
+        }
+        drop(loudness);
+        self.start_spectrum();
+        *self.stereo.lock().unwrap() = SpectrumSource::ALL.map(|source| StereoAnalyzer::start(self.source_tap(source)).ok());
+    }
+
+    /// Audio stopped: stop analysing but keep the last readings.
+    pub fn pause_analysis(&self) {
+        if let Some(m) = self.loudness.lock().unwrap().as_mut() {
+            m.pause();
+        }
+        *self.spectrum.lock().unwrap() = None;
+        *self.stereo.lock().unwrap() = [None, None, None];
+    }
+
+    /// Restart integrated loudness and true peak.
+    pub fn reset_loudness(&self) {
+        if let Some(m) = self.loudness.lock().unwrap().as_ref() {
+            m.reset();
+        }
+    }
+
+    pub fn spectrum_source(&self) -> SpectrumSource {
+        *self.spectrum_source.lock().unwrap()
+    }
+
+    /// Point the analyzer at another source (restarts it if audio is running).
+    pub fn set_spectrum_source(&self, source: SpectrumSource) {
+        *self.spectrum_source.lock().unwrap() = source;
+        if self.is_running() {
+            self.start_spectrum();
+        }
+    }
+
+    pub fn spectrum_smoothing(&self) -> f32 {
+        self.spectrum_smoothing.load()
+    }
+
+    pub fn set_spectrum_smoothing(&self, smoothing: f32) {
+        self.spectrum_smoothing.store(smoothing);
+        if let Some(a) = self.spectrum.lock().unwrap().as_ref() {
+            a.set_smoothing(smoothing);
+        }
+    }
+
+    fn source_tap(&self, source: SpectrumSource) -> Arc<BlockTap> {
+        match source {
+            SpectrumSource::InputA => self.controls.input_taps[0].clone(),
+            SpectrumSource::InputB => self.controls.input_taps[1].clone(),
+            SpectrumSource::Output => self.controls.output_tap.clone(),
+        }
+    }
+
+    // An unused input slot has no tap format; the panel then just shows nothing
+    fn start_spectrum(&self) {
+        let tap = self.source_tap(self.spectrum_source());
+        let mut spectrum = self.spectrum.lock().unwrap();
+        *spectrum = None;
+        *spectrum = SpectrumAnalyzer::start(tap, self.spectrum_smoothing()).ok();
+    }
+}
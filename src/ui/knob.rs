@@ -0,0 +1,63 @@
+// Rotary knob: drag up/down to turn, double-click to reset, scroll for fine steps.
+// The lit arc runs from the default position to the value, so bipolar knobs read naturally.
+
+use egui::{pos2, vec2, Align2, Color32, FontId, Response, Sense, Shape, Stroke, Ui};
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+
+const RADIUS: f32 = 14.0;
+// Pixels of vertical drag for the full range
+const DRAG_PIXELS: f32 = 160.0;
+// 270° sweep starting at the bottom left
+const START_ANGLE: f32 = 0.75 * PI;
+const SWEEP: f32 = 1.5 * PI;
+
+pub fn knob(ui: &mut Ui, value: &mut f32, range: RangeInclusive<f32>, default: f32, label: &str, text: &str) -> Response {
+    let (min, max) = (*range.start(), *range.end());
+    let size = vec2(RADIUS * 2.0 + 16.0, RADIUS * 2.0 + 28.0);
+    let (rect, mut response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+
+    let before = *value;
+    if response.dragged() {
+        *value -= response.drag_delta().y * (max - min) / DRAG_PIXELS;
+    }
+    if response.hovered() {
+        let scroll = ui.input(|i| i.scroll_delta.y);
+        if scroll != 0.0 {
+            *value += scroll.signum() * (max - min) / 100.0;
+        }
+    }
+    if response.double_clicked() {
+        *value = default;
+    }
+    *value = value.clamp(min, max);
+    if *value != before {
+        response.mark_changed();
+    }
+
+    let painter = ui.painter_at(rect);
+    let centre = pos2(rect.center().x, rect.min.y + RADIUS + 2.0);
+    let angle_for = |v: f32| START_ANGLE + SWEEP * ((v - min) / (max - min)).clamp(0.0, 1.0);
+    let arc = |from: f32, to: f32| -> Vec<egui::Pos2> {
+        let steps = ((to - from).abs() / 0.1).ceil().max(1.0) as usize;
+        (0..=steps).map(|i| {
+            let a = from + (to - from) * i as f32 / steps as f32;
+            centre + vec2(a.cos(), a.sin()) * RADIUS
+        }).collect()
+    };
+
+    let visuals = ui.style().interact(&response);
+    painter.circle_filled(centre, RADIUS - 4.0, visuals.bg_fill);
+    painter.add(Shape::line(arc(START_ANGLE, START_ANGLE + SWEEP), Stroke::new(3.0, Color32::from_gray(60))));
+    let (a0, a1) = (angle_for(default), angle_for(*value));
+    if (a1 - a0).abs() > 0.01 {
+        painter.add(Shape::line(arc(a0.min(a1), a0.max(a1)), Stroke::new(3.0, Color32::from_rgb(110, 180, 240))));
+    }
+    let pointer = vec2(a1.cos(), a1.sin());
+    painter.line_segment([centre + pointer * 3.0, centre + pointer * (RADIUS - 4.0)], Stroke::new(2.0, visuals.fg_stroke.color));
+
+    painter.text(pos2(centre.x, rect.max.y - 12.0), Align2::CENTER_BOTTOM, label, FontId::proportional(11.0), visuals.text_color());
+    painter.text(pos2(centre.x, rect.max.y), Align2::CENTER_BOTTOM, text, FontId::monospace(9.0), Color32::from_gray(150));
+
+    response.on_hover_text("Drag up/down or scroll; double-click to reset")
+}
@@ -0,0 +1,164 @@
+// Level meter widget: ballistics, peak-hold and clip indicator on top of `LevelMeter`.
+//
+// The audio side only publishes raw per-block peak/RMS values; everything time-dependent
+// happens here, once per UI frame.
+
+use crate::audio::meters::{linear_to_db, LevelMeter, MAX_METER_CHANNELS};
+use egui::{pos2, vec2, Color32, Rect, Response, Sense, Stroke, Ui};
+use std::time::Instant;
+
+// Displayed range in dBFS
+const FLOOR_DB: f32 = -60.0;
+const PEAK_HOLD_SECS: f32 = 2.0;
+// Fall rates: digital peak per IEC 60268-18 (20 dB in 1.7 s), PPM per IEC 60268-10 type IIa (24 dB in 2.8 s)
+const DIGITAL_FALL_DB_PER_S: f32 = 20.0 / 1.7;
+const PPM_FALL_DB_PER_S: f32 = 24.0 / 2.8;
+const PPM_ATTACK_TAU: f32 = 0.005;
+// VU: reaches 99% of a step in 300 ms
+const VU_TAU: f32 = 0.3 / 4.6;
+
+const BAR_HEIGHT: f32 = 5.0;
+const BAR_GAP: f32 = 2.0;
+const CLIP_WIDTH: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Ballistics {
+    #[default]
+    Digital,
+    Ppm,
+    Vu,
+}
+
+impl Ballistics {
+    pub const ALL: [Ballistics; 3] = [Ballistics::Digital, Ballistics::Ppm, Ballistics::Vu];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Ballistics::Digital => "Digital peak",
+            Ballistics::Ppm => "PPM",
+            Ballistics::Vu => "VU",
+        }
+    }
+}
+
+/// Display state of one meter, advanced every time it is drawn.
+pub struct MeterDisplay {
+    // dBFS after ballistics, and the held maximum with when it was set
+    level_db: [f32; MAX_METER_CHANNELS],
+    hold_db: [f32; MAX_METER_CHANNELS],
+    hold_at: [Option<Instant>; MAX_METER_CHANNELS],
+    last: Option<Instant>,
+}
+
+impl MeterDisplay {
+    pub fn new() -> Self {
+        Self {
+            level_db: [FLOOR_DB; MAX_METER_CHANNELS],
+            hold_db: [FLOOR_DB; MAX_METER_CHANNELS],
+            hold_at: [None; MAX_METER_CHANNELS],
+            last: None,
+        }
+    }
+
+    fn advance(&mut self, meter: &LevelMeter, ballistics: Ballistics) {
+        let now = Instant::now();
+        // Clamp so a window that was hidden for a while doesn't jump
+        let dt = self.last.map(|t| (now - t).as_secs_f32()).unwrap_or(0.0).min(0.5);
+        self.last = Some(now);
+
+        for ch in 0..meter.channels() {
+            let peak_db = linear_to_db(meter.take_peak(ch)).max(FLOOR_DB);
+            let current = self.level_db[ch];
+            let level = match ballistics {
+                Ballistics::Digital => peak_db.max(current - DIGITAL_FALL_DB_PER_S * dt),
+                Ballistics::Ppm => {
+                    if peak_db > current {
+                        current + (peak_db - current) * (1.0 - (-dt / PPM_ATTACK_TAU).exp())
+                    } else {
+                        peak_db.max(current - PPM_FALL_DB_PER_S * dt)
+                    }
+                }
+                Ballistics::Vu => {
+                    let rms_db = linear_to_db(meter.channel_rms(ch)).max(FLOOR_DB);
+                    current + (rms_db - current) * (1.0 - (-dt / VU_TAU).exp())
+                }
+            };
+            self.level_db[ch] = level;
+
+            let expired = self.hold_at[ch].is_none_or(|t| (now - t).as_secs_f32() > PEAK_HOLD_SECS);
+            if level >= self.hold_db[ch] || expired {
+                self.hold_db[ch] = level;
+                self.hold_at[ch] = Some(now);
+            }
+        }
+    }
+
+    /// Draw one bar per channel plus a clip light; clicking the light clears it.
+    pub fn show(&mut self, ui: &mut Ui, meter: &LevelMeter, ballistics: Ballistics, width: f32) -> Response {
+        self.advance(meter, ballistics);
+
+        let channels = meter.channels();
+        let rows = channels.max(1) as f32;
+        let height = rows * BAR_HEIGHT + (rows - 1.0) * BAR_GAP;
+        let (rect, response) = ui.allocate_exact_size(vec2(width, height.max(12.0)), Sense::click());
+        let painter = ui.painter_at(rect);
+
+        let bars = Rect::from_min_max(rect.min, pos2(rect.max.x - CLIP_WIDTH - 4.0, rect.max.y));
+        let x_for = |db: f32| bars.min.x + bars.width() * ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+        let top = rect.center().y - height / 2.0;
+
+        for ch in 0..channels.max(1) {
+            let y = top + ch as f32 * (BAR_HEIGHT + BAR_GAP);
+            let bg = Rect::from_min_max(pos2(bars.min.x, y), pos2(bars.max.x, y + BAR_HEIGHT));
+            painter.rect_filled(bg, 1.0, Color32::from_gray(40));
+            if ch >= channels {
+                continue;
+            }
+            let level = self.level_db[ch];
+            let fill = Rect::from_min_max(bg.min, pos2(x_for(level), bg.max.y));
+            painter.rect_filled(fill, 1.0, level_color(level));
+            let hold_x = x_for(self.hold_db[ch]);
+            painter.line_segment([pos2(hold_x, bg.min.y), pos2(hold_x, bg.max.y)], Stroke::new(1.5, level_color(self.hold_db[ch])));
+        }
+
+        // 0 / -6 / -18 dBFS ticks
+        for db in [-18.0, -6.0, 0.0] {
+            let x = x_for(db);
+            painter.line_segment([pos2(x, rect.min.y), pos2(x, rect.max.y)], Stroke::new(1.0, Color32::from_black_alpha(140)));
+        }
+
+        let clip = Rect::from_min_max(pos2(rect.max.x - CLIP_WIDTH, rect.min.y), rect.max);
+        let clip_color = if meter.clipped() { Color32::from_rgb(230, 40, 40) } else { Color32::from_gray(60) };
+        painter.rect_filled(clip, 2.0, clip_color);
+
+        let clicked_clip = response.clicked() && response.interact_pointer_pos().is_some_and(|p| clip.contains(p));
+        if clicked_clip {
+            meter.clear_clip();
+        }
+
+        response.on_hover_ui(|ui| {
+            if channels == 0 {
+                ui.label("No signal");
+            }
+            for ch in 0..channels {
+                ui.label(format!(
+                    "Ch {}: peak {:.1} dBFS, RMS {:.1} dBFS",
+                    ch + 1,
+                    linear_to_db(meter.channel_peak(ch)),
+                    linear_to_db(meter.channel_rms(ch)),
+                ));
+            }
+            ui.label("Click the red light to clear a clip");
+        })
+    }
+}
+
+fn level_color(db: f32) -> Color32 {
+    if db >= -6.0 {
+        Color32::from_rgb(230, 60, 50)
+    } else if db >= -18.0 {
+        Color32::from_rgb(230, 200, 60)
+    } else {
+        Color32::from_rgb(90, 200, 100)
+    }
+}
@@ -0,0 +1,7 @@
+// Custom egui widgets used by `AudioApp`.
+
+pub mod knob;
+pub mod meters;
+pub mod spectrum;
+pub mod stereo;
+pub mod strip;
@@ -0,0 +1,73 @@
+// Spectrum panel: log frequency axis (20 Hz to Nyquist), -96..0 dBFS.
+
+use crate::analysis::spectrum::Spectrum;
+use egui::{pos2, vec2, Align2, Color32, FontId, Pos2, Sense, Shape, Stroke, Ui};
+
+const MIN_HZ: f32 = 20.0;
+const MAX_HZ: f32 = 20_000.0;
+const MIN_DB: f32 = -96.0;
+
+pub fn show(ui: &mut Ui, spectrum: Option<&Spectrum>, height: f32) {
+    let (rect, response) = ui.allocate_exact_size(vec2(ui.available_width(), height), Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, Color32::from_gray(20));
+
+    let max_hz = spectrum.map_or(MAX_HZ, |s| (s.sample_rate as f32 / 2.0).min(MAX_HZ));
+    let span = (max_hz / MIN_HZ).ln();
+    let x_for = |hz: f32| rect.min.x + rect.width() * ((hz / MIN_HZ).ln() / span).clamp(0.0, 1.0);
+    let hz_for = |x: f32| MIN_HZ * (span * (x - rect.min.x) / rect.width()).exp();
+    let y_for = |db: f32| rect.min.y + rect.height() * (db / MIN_DB).clamp(0.0, 1.0);
+
+    // Grid: decades and 24 dB steps
+    let grid = Stroke::new(1.0, Color32::from_gray(50));
+    let text = Color32::from_gray(140);
+    let font = FontId::proportional(10.0);
+    for (hz, label) in [(100.0, "100"), (1_000.0, "1k"), (10_000.0, "10k")] {
+        if hz < max_hz {
+            let x = x_for(hz);
+            painter.line_segment([pos2(x, rect.min.y), pos2(x, rect.max.y)], grid);
+            painter.text(pos2(x + 2.0, rect.max.y - 2.0), Align2::LEFT_BOTTOM, label, font.clone(), text);
+        }
+    }
+    for db in [-24.0, -48.0, -72.0] {
+        let y = y_for(db);
+        painter.line_segment([pos2(rect.min.x, y), pos2(rect.max.x, y)], grid);
+        painter.text(pos2(rect.min.x + 2.0, y - 1.0), Align2::LEFT_BOTTOM, format!("{}", db), font.clone(), text);
+    }
+
+    let spectrum = match spectrum {
+        Some(s) => s,
+        None => {
+            painter.text(rect.center(), Align2::CENTER_CENTER, "No signal", FontId::proportional(13.0), text);
+            return;
+        }
+    };
+
+    // One point per pixel column: the loudest bin the column covers, or the nearest bin
+    // where bins are wider than a pixel (low frequencies)
+    let bin_hz = spectrum.bin_hz();
+    let last = spectrum.bins_db.len() - 1;
+    let columns = rect.width().max(1.0) as usize;
+    let points: Vec<Pos2> = (0..columns).map(|i| {
+        let x = rect.min.x + i as f32;
+        let lo = ((hz_for(x) / bin_hz).round() as usize).min(last);
+        let hi = ((hz_for(x + 1.0) / bin_hz).round() as usize).clamp(lo, last);
+        let db = spectrum.bins_db[lo..=hi].iter().copied().fold(f32::MIN, f32::max);
+        pos2(x, y_for(db))
+    }).collect();
+
+    painter.add(Shape::line(points, Stroke::new(1.5, Color32::from_rgb(110, 180, 240))));
+
+    if let Some(pos) = response.hover_pos() {
+        let hz = hz_for(pos.x);
+        let bin = ((hz / bin_hz).round() as usize).min(last);
+        let label = if hz >= 1000.0 { format!("{:.2} kHz", hz / 1000.0) } else { format!("{:.0} Hz", hz) };
+        painter.text(
+            pos2(rect.max.x - 4.0, rect.min.y + 2.0),
+            Align2::RIGHT_TOP,
+            format!("{}  {:.1} dB", label, spectrum.bins_db[bin]),
+            font,
+            Color32::from_gray(200),
+        );
+    }
+}
@@ -0,0 +1,68 @@
+// Goniometer (mid up, side across) with a correlation bar underneath.
+
+use crate::analysis::stereo::StereoView;
+use egui::{pos2, vec2, Align2, Color32, FontId, Rect, Sense, Stroke, Ui};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+const CORRELATION_HEIGHT: f32 = 8.0;
+// Auto-gain never amplifies more than this, so noise doesn't fill the scope
+const MIN_SCOPE_PEAK: f32 = 0.05;
+
+pub fn show(ui: &mut Ui, label: &str, view: Option<&StereoView>, size: f32) {
+    ui.vertical(|ui| {
+        ui.label(label);
+        let (rect, _) = ui.allocate_exact_size(vec2(size, size + CORRELATION_HEIGHT + 4.0), Sense::hover());
+        let painter = ui.painter_at(rect);
+        let scope = Rect::from_min_size(rect.min, vec2(size, size));
+        let bar = Rect::from_min_size(pos2(rect.min.x, scope.max.y + 4.0), vec2(size, CORRELATION_HEIGHT));
+
+        painter.rect_filled(scope, 2.0, Color32::from_gray(20));
+        let grid = Stroke::new(1.0, Color32::from_gray(50));
+        let c = scope.center();
+        painter.line_segment([pos2(c.x, scope.min.y), pos2(c.x, scope.max.y)], grid);
+        painter.line_segment([pos2(scope.min.x, c.y), pos2(scope.max.x, c.y)], grid);
+        // L and R axes
+        painter.line_segment([scope.left_top(), scope.right_bottom()], grid);
+        painter.line_segment([scope.right_top(), scope.left_bottom()], grid);
+        painter.rect_filled(bar, 1.0, Color32::from_gray(40));
+
+        let view = match view {
+            Some(v) => v,
+            None => {
+                painter.text(c, Align2::CENTER_CENTER, "No stereo signal", FontId::proportional(11.0), Color32::from_gray(140));
+                return;
+            }
+        };
+
+        // Mid up, side across; scaled so the loudest recent point reaches 90% of the radius
+        let peak = view.points.iter().map(|&(l, r)| l.abs().max(r.abs())).fold(MIN_SCOPE_PEAK, f32::max);
+        let scale = 0.9 * size / 2.0 / peak;
+        let dot = Color32::from_rgba_unmultiplied(120, 220, 140, 90);
+        for &(l, r) in &view.points {
+            let side = (r - l) * FRAC_1_SQRT_2;
+            let mid = (l + r) * FRAC_1_SQRT_2;
+            let p = pos2(c.x + side * scale, c.y - mid * scale);
+            if scope.contains(p) {
+                painter.circle_filled(p, 0.8, dot);
+            }
+        }
+
+        let corr = view.correlation;
+        let x = bar.center().x + corr * bar.width() / 2.0;
+        let color = if corr < 0.0 {
+            Color32::from_rgb(230, 60, 50)
+        } else if corr < 0.5 {
+            Color32::from_rgb(230, 200, 60)
+        } else {
+            Color32::from_rgb(90, 200, 100)
+        };
+        let (from, to) = if x < bar.center().x { (x, bar.center().x) } else { (bar.center().x, x) };
+        painter.rect_filled(Rect::from_min_max(pos2(from, bar.min.y), pos2(to, bar.max.y)), 1.0, color);
+        painter.line_segment([pos2(bar.center().x, bar.min.y - 1.0), pos2(bar.center().x, bar.max.y + 1.0)], Stroke::new(1.0, Color32::from_gray(160)));
+    })
+    .response
+    .on_hover_text(match view {
+        Some(v) => format!("Correlation {:+.2} (-1 out of phase, 0 wide, +1 mono)", v.correlation),
+        None => "Source not running or mono".to_string(),
+    });
+}
@@ -0,0 +1,185 @@
+// Channel strip: the per-input processing controls (EQ, filter, gate, routing), one strip per input slot.
+
+use crate::audio::controls::{AtomicF32, MixControls};
+use crate::audio::engine::MAX_INPUT_DELAY_MS;
+use crate::audio::matrix::MAX_ROUTE_GAIN;
+use crate::audio::params::Param;
+use crate::dsp::eq::{self, Band};
+use crate::ui::knob::knob;
+use egui::{Color32, DragValue, Key, RichText, Ui};
+use std::ops::RangeInclusive;
+use std::sync::atomic::Ordering;
+
+/// `hotkeys` maps keys to parameter ids (and their help group); matching keys are shown in tooltips.
+pub fn show(ui: &mut Ui, name: &str, slot: usize, controls: &MixControls, hotkeys: &[(Key, &str, &str)]) {
+    let input = &controls.inputs[slot];
+    let hint = |param: Param| {
+        let id = param.id();
+        match hotkeys.iter().find(|(_, p, _)| *p == id) {
+            Some((key, _, _)) => format!("{} ({:?})", id, key),
+            None => id,
+        }
+    };
+
+    egui::Frame::group(ui.style()).show(ui, |ui| {
+        ui.vertical(|ui| {
+            ui.label(RichText::new(name).strong());
+            ui.horizontal(|ui| {
+                for band in Band::ALL {
+                    ui.vertical(|ui| {
+                        let param = Param::EqGain(slot, band);
+                        let mut db = input.eq_gain_db[band as usize].load();
+                        if knob(ui, &mut db, eq::MIN_DB..=eq::MAX_DB, 0.0, band.label(), &param.display(controls)).changed() {
+                            input.eq_gain_db[band as usize].store(db);
+                        }
+                        let kill = Param::EqKill(slot, band);
+                        let on = input.eq_kill[band as usize].load(Ordering::Relaxed);
+                        let text = RichText::new("KILL").small().color(if on { Color32::from_rgb(230, 60, 50) } else { Color32::LIGHT_GRAY });
+                        if ui.selectable_label(on, text).on_hover_text(hint(kill)).clicked() {
+                            kill.toggle(controls);
+                        }
+                    });
+                }
+                ui.separator();
+                ui.vertical(|ui| {
+                    let param = Param::Filter(slot);
+                    let mut position = input.filter.load();
+                    if knob(ui, &mut position, -1.0..=1.0, 0.0, "Filter", &param.display(controls)).on_hover_text(hint(param)).changed() {
+                        input.filter.store(position);
+                    }
+                });
+                ui.vertical(|ui| {
+                    let param = Param::FilterResonance(slot);
+                    let mut resonance = input.filter_resonance.load();
+                    if knob(ui, &mut resonance, 0.0..=1.0, 0.0, "Res", &param.display(controls)).on_hover_text(hint(param)).changed() {
+                        input.filter_resonance.store(resonance);
+                    }
+                });
+            });
+
+            // Noise gate, with a light that is green while the gate is open
+            ui.horizontal(|ui| {
+                let mut enabled = input.gate_enabled.load(Ordering::Relaxed);
+                if ui.checkbox(&mut enabled, "Gate").on_hover_text(hint(Param::Gate(slot))).changed() {
+                    input.gate_enabled.store(enabled, Ordering::Relaxed);
+                }
+                let open = input.gate_open.load(Ordering::Relaxed);
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(10.0, 10.0), egui::Sense::hover());
+                let color = match (enabled, open) {
+                    (false, _) => Color32::from_gray(60),
+                    (true, true) => Color32::from_rgb(90, 200, 100),
+                    (true, false) => Color32::from_rgb(120, 40, 40),
+                };
+                ui.painter().circle_filled(rect.center(), 4.5, color);
+                ui.add_enabled_ui(enabled, |ui| {
+                    drag_value(ui, &input.gate_threshold_db, -80.0..=0.0, "thr ", " dB");
+                    drag_value(ui, &input.gate_hysteresis_db, 0.0..=20.0, "hyst ", " dB");
+                });
+            });
+            ui.add_enabled_ui(input.gate_enabled.load(Ordering::Relaxed), |ui| {
+                ui.horizontal(|ui| {
+                    drag_value(ui, &input.gate_attack_ms, 0.1..=100.0, "att ", " ms");
+                    drag_value(ui, &input.gate_hold_ms, 0.0..=2000.0, "hold ", " ms");
+                    drag_value(ui, &input.gate_release_ms, 5.0..=3000.0, "rel ", " ms");
+                });
+            });
+
+            // Alignment delay, and where that leaves the input's total latency
+            ui.horizontal(|ui| {
+                ui.label("Delay");
+                drag_value(ui, &input.delay_ms, 0.0..=MAX_INPUT_DELAY_MS, "", " ms");
+                if let Some(format) = controls.input_taps[slot].format() {
+                    let samples = (input.delay_ms.load() / 1000.0 * format.sample_rate as f32) as u32;
+                    ui.label(RichText::new(format!("{} smp", samples)).small().weak());
+                }
+                let total = match controls.input_latency_ms(slot) {
+                    Some(ms) => format!("total ≈ {:.1} ms", ms),
+                    None => "total -".to_string(),
+                };
+                ui.label(RichText::new(total).small().monospace())
+                    .on_hover_text("Capture block + queue + delay + render block + output delay; converter latency is not included");
+            });
+
+            egui::CollapsingHeader::new("Routing").id_source(("routing", slot)).show(ui, |ui| routing(ui, slot, controls));
+        });
+    });
+}
+
+// Crosspoint grid: one row per input channel, one column per output channel
+fn routing(ui: &mut Ui, slot: usize, controls: &MixControls) {
+    let route = &controls.inputs[slot].route;
+    let (in_channels, out_channels) = controls.route_channels(slot);
+    egui::Grid::new(("routing_grid", slot)).spacing([4.0, 2.0]).show(ui, |ui| {
+        ui.label(RichText::new("in \\ out").small().weak());
+        for o in 0..out_channels {
+            ui.label(RichText::new((o + 1).to_string()).small());
+        }
+        ui.end_row();
+        for i in 0..in_channels {
+            ui.label(RichText::new((i + 1).to_string()).small());
+            for o in 0..out_channels {
+                let mut gain = route.gain(i, o, in_channels);
+                let cell = DragValue::new(&mut gain)
+                    .clamp_range(0.0..=MAX_ROUTE_GAIN)
+                    .speed(0.01)
+                    .custom_formatter(|v, _| if v <= 0.0 { "-".to_string() } else { format!("{:.2}", v) });
+                if ui.add(cell).on_hover_text(format!("in {} to out {}", i + 1, o + 1)).changed() {
+                    route.set_gain(i, o, gain, in_channels);
+                }
+            }
+            ui.end_row();
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label(RichText::new(if route.is_custom() { "custom" } else { "default (ch to ch)" }).small().weak());
+        if ui.add_enabled(route.is_custom(), egui::Button::new("Reset").small()).clicked() {
+            route.reset();
+        }
+        // Edits live only in memory; the copied line goes into a `--config` file to keep them
+        if ui.add_enabled(route.is_custom(), egui::Button::new("Copy as config").small())
+            .on_hover_text("Copy this matrix as the config file's matrix entry")
+            .clicked()
+        {
+            let rows = serde_json::to_string(&route.to_rows(in_channels, out_channels)).unwrap_or_default();
+            let key = if slot == 0 { "matrix_a" } else { "matrix_b" };
+            ui.output_mut(|o| o.copied_text = format!("\"{}\": {}", key, rows));
+        }
+    });
+}
+
+/// Talkover row: microphone input, threshold, depth, timing and a ducking indicator.
+pub fn talkover(ui: &mut Ui, controls: &MixControls) {
+    let t = &controls.talkover;
+    ui.horizontal(|ui| {
+        let mut enabled = t.enabled.load(Ordering::Relaxed);
+        if ui.checkbox(&mut enabled, "Talkover").on_hover_text("Duck the other input while the microphone input is above threshold (T)").changed() {
+            t.enabled.store(enabled, Ordering::Relaxed);
+        }
+        let mut source = t.source.load(Ordering::Relaxed);
+        egui::ComboBox::from_id_source("talkover_source")
+            .selected_text(if source == 0 { "Mic: Input A" } else { "Mic: Input B" })
+            .width(100.0)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut source, 0, "Mic: Input A");
+                ui.selectable_value(&mut source, 1, "Mic: Input B");
+            });
+        t.source.store(source, Ordering::Relaxed);
+
+        drag_value(ui, &t.threshold_db, -60.0..=0.0, "thr ", " dB");
+        drag_value(ui, &t.depth_db, -40.0..=0.0, "depth ", " dB");
+        drag_value(ui, &t.attack_ms, 1.0..=500.0, "att ", " ms");
+        drag_value(ui, &t.release_ms, 10.0..=3000.0, "rel ", " ms");
+
+        let reduction = t.reduction_db.load();
+        let ducking = reduction < -0.5;
+        let text = RichText::new(format!("DUCK {:>5.1} dB", reduction)).monospace();
+        ui.label(if ducking { text.color(Color32::from_rgb(230, 160, 40)).strong() } else { text.weak() });
+    });
+}
+
+fn drag_value(ui: &mut Ui, value: &AtomicF32, range: RangeInclusive<f32>, prefix: &str, suffix: &str) {
+    let mut v = value.load();
+    if ui.add(DragValue::new(&mut v).clamp_range(range).speed(0.5).prefix(prefix).suffix(suffix)).changed() {
+        value.store(v);
+    }
+}